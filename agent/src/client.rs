@@ -1,10 +1,72 @@
-use anyhow::Result;
-use reqwest::{Client, header};
+use anyhow::{Context, Result};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, header};
+use serde::Deserialize;
 use std::time::Duration;
 use crate::config::GlobalConfig;
 
-pub fn get_authenticated_client() -> Result<Client> {
-  let config = GlobalConfig::load()?;
+/// Access tokens are renewed when they expire within this many seconds.
+const REFRESH_MARGIN_SECS: i64 = 60;
+/// Attempts made when the server answers `429 Too Many Requests`.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+/// Upper bound on a single wait, whatever `Retry-After` says.
+const MAX_RETRY_WAIT_SECS: u64 = 60;
+
+#[derive(Deserialize)]
+pub struct TokenResponse {
+  pub access_token: String,
+  pub refresh_token: Option<String>,
+  pub expires_in: Option<i64>,
+}
+
+impl GlobalConfig {
+  pub fn store_tokens(&mut self, tokens: TokenResponse) {
+    self.auth_token = Some(tokens.access_token);
+    // Providers may rotate the refresh token; keep the previous one otherwise.
+    if tokens.refresh_token.is_some() {
+      self.refresh_token = tokens.refresh_token;
+    }
+    self.token_expires_at = tokens.expires_in.map(|secs| chrono::Utc::now().timestamp() + secs);
+  }
+}
+
+/// Exchanges the stored refresh token for a new access token when the current one is about to expire.
+async fn refresh_if_needed(config: &mut GlobalConfig) -> Result<()> {
+  let (Some(refresh_token), Some(endpoint), Some(client_id)) = (&config.refresh_token, &config.token_endpoint, &config.client_id) else {
+    return Ok(());
+  };
+
+  let expires_soon = config.token_expires_at
+    .map(|exp| exp - chrono::Utc::now().timestamp() < REFRESH_MARGIN_SECS)
+    .unwrap_or(true);
+  if !expires_soon {
+    return Ok(());
+  }
+
+  let res = Client::new()
+    .post(endpoint)
+    .form(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token.as_str()), ("client_id", client_id.as_str())])
+    .send()
+    .await
+    .context("Could not reach the identity provider to renew the session")?;
+
+  if !res.status().is_success() {
+    anyhow::bail!("Session expired. Run 'plectr login'.");
+  }
+
+  let tokens: TokenResponse = res.json().await.context("Invalid token response")?;
+  config.store_tokens(tokens);
+  config.save()?;
+  Ok(())
+}
+
+/// Organization repositories are named `org/repo`; the slash must be escaped to stay a single path segment.
+pub fn repo_path(name: &str) -> String {
+  name.replace('/', "%2F")
+}
+
+pub async fn get_authenticated_client() -> Result<Client> {
+  let mut config = GlobalConfig::load()?;
+  refresh_if_needed(&mut config).await?;
   let mut headers = header::HeaderMap::new();
 
   if let Some(token) = config.auth_token {
@@ -20,4 +82,27 @@ pub fn get_authenticated_client() -> Result<Client> {
     .build()?;
 
   Ok(client)
-}
\ No newline at end of file
+}
+
+/// Sends a request built by `build`, waiting and retrying while the server rate limits it.
+/// The request is rebuilt for each attempt since multipart bodies cannot be replayed.
+pub async fn send_with_retry<F>(build: F) -> reqwest::Result<Response> where F: Fn() -> RequestBuilder {
+  let mut attempt = 0;
+  loop {
+    let res = build().send().await?;
+    if res.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_RATE_LIMIT_RETRIES {
+      return Ok(res);
+    }
+
+    // Honor `Retry-After` (seconds); fall back to exponential backoff when it is missing.
+    let wait = res
+      .headers()
+      .get(header::RETRY_AFTER)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse::<u64>().ok())
+      .unwrap_or(1 << attempt)
+      .min(MAX_RETRY_WAIT_SECS);
+    tokio::time::sleep(Duration::from_secs(wait)).await;
+    attempt += 1;
+  }
+}
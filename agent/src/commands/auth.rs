@@ -1,10 +1,28 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use console::style;
 use dialoguer::{Input, theme::ColorfulTheme};
+use indicatif::ProgressBar;
 use reqwest::StatusCode;
-use crate::{config::GlobalConfig, client::get_authenticated_client};
+use serde::Deserialize;
+use std::time::Duration;
+use crate::{config::GlobalConfig, client::{get_authenticated_client, TokenResponse}};
 
-pub async fn login() -> Result<()> {
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+  device_code: String,
+  user_code: String,
+  verification_uri: String,
+  verification_uri_complete: Option<String>,
+  expires_in: u64,
+  interval: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+  error: String,
+}
+
+pub async fn login(paste_token: bool, ssh_remote: Option<String>) -> Result<()> {
   println!("{}", style("🔐 Plectr Authentication Setup").bold().cyan());
 
   let current_config = GlobalConfig::load()?;
@@ -13,29 +31,137 @@ pub async fn login() -> Result<()> {
     .with_prompt("Forge URL")
     .default(current_config.server_url)
     .interact_text()?;
+  let server_url = url.trim_end_matches('/').to_string();
 
-  println!("{}", style("ℹ️ To get your token, log in to the Web UI and go to Settings > Tokens").dim());
+  let config = if let Some(remote) = ssh_remote {
+    println!("{}", style("ℹ️ Add your public key (~/.ssh/id_ed25519.pub) in the Web UI under Settings > SSH Keys").dim());
+    GlobalConfig {
+      server_url,
+      ssh_remote: Some(remote),
+      ..Default::default()
+    }
+  } else if paste_token {
+    println!("{}", style("ℹ️ Create a personal access token (plectr_pat_...) in the Web UI under Settings > Tokens").dim());
 
-  let token: String = Input::with_theme(&ColorfulTheme::default())
-    .with_prompt("Paste JWT / API Token")
-    .interact_text()?;
+    let token: String = Input::with_theme(&ColorfulTheme::default())
+      .with_prompt("Paste Personal Access Token")
+      .interact_text()?;
 
-  let config = GlobalConfig { 
-    server_url: url.trim_end_matches('/').to_string(), 
-    auth_token: Some(token.trim().to_string()) 
+    GlobalConfig { 
+      server_url, 
+      auth_token: Some(token.trim().to_string()),
+      ..Default::default()
+    }
+  } else {
+    device_login(server_url).await?
   };
   config.save()?;
 
   println!("{}", style("✨ Configuration saved.").green());
+  let _tunnel = match &config.ssh_remote {
+    Some(remote) => Some(crate::ssh::open(remote).await?),
+    None => None,
+  };
   whoami().await?; 
   Ok(())
 }
 
+/// OAuth 2.0 device authorization grant (RFC 8628): no browser needed on this machine.
+async fn device_login(server_url: String) -> Result<GlobalConfig> {
+  let http = reqwest::Client::new();
+
+  let auth_config: serde_json::Value = http.get(format!("{}/api/auth/config", server_url))
+    .send().await?
+    .error_for_status()
+    .context("Forge does not expose its login configuration. Use 'plectr login --token'.")?
+    .json().await?;
+  let issuer = auth_config["issuer"].as_str().context("Missing issuer")?.trim_end_matches('/').to_string();
+  let client_id = auth_config["client_id"].as_str().context("Missing client_id")?.to_string();
+
+  let discovery: serde_json::Value = http.get(format!("{}/.well-known/openid-configuration", issuer))
+    .send().await?
+    .error_for_status()
+    .context("Identity provider discovery failed")?
+    .json().await?;
+  let device_endpoint = discovery["device_authorization_endpoint"].as_str()
+    .context("Identity provider does not support the device flow. Use 'plectr login --token'.")?;
+  let token_endpoint = discovery["token_endpoint"].as_str().context("Missing token endpoint")?.to_string();
+
+  let device: DeviceAuthorization = http.post(device_endpoint)
+    .form(&[("client_id", client_id.as_str()), ("scope", "openid offline_access")])
+    .send().await?
+    .error_for_status()
+    .context("Device authorization request rejected")?
+    .json().await?;
+
+  println!();
+  println!("  Open {} and enter the code:", style(&device.verification_uri).cyan().underlined());
+  println!("\n      {}\n", style(&device.user_code).bold().yellow());
+  if let Some(complete) = &device.verification_uri_complete {
+    println!("  {}", style(format!("(or go straight to {})", complete)).dim());
+  }
+
+  let spinner = ProgressBar::new_spinner();
+  spinner.set_message("Waiting for authorization...");
+  spinner.enable_steady_tick(Duration::from_millis(100));
+
+  let mut interval = device.interval.unwrap_or(5);
+  let deadline = std::time::Instant::now() + Duration::from_secs(device.expires_in);
+
+  let tokens = loop {
+    tokio::time::sleep(Duration::from_secs(interval)).await;
+    if std::time::Instant::now() > deadline {
+      spinner.finish_and_clear();
+      anyhow::bail!("The code expired before it was approved. Run 'plectr login' again.");
+    }
+
+    let res = http.post(&token_endpoint)
+      .form(&[
+        ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+        ("device_code", device.device_code.as_str()),
+        ("client_id", client_id.as_str()),
+      ])
+      .send().await?;
+
+    if res.status().is_success() {
+      break res.json::<TokenResponse>().await?;
+    }
+
+    let err: TokenError = res.json().await.context("Invalid response from identity provider")?;
+    match err.error.as_str() {
+      "authorization_pending" => {}
+      "slow_down" => interval += 5,
+      "access_denied" => {
+        spinner.finish_and_clear();
+        anyhow::bail!("Login was denied.");
+      }
+      "expired_token" => {
+        spinner.finish_and_clear();
+        anyhow::bail!("The code expired before it was approved. Run 'plectr login' again.");
+      }
+      other => {
+        spinner.finish_and_clear();
+        anyhow::bail!("Login failed: {}", other);
+      }
+    }
+  };
+  spinner.finish_and_clear();
+
+  let mut config = GlobalConfig {
+    server_url,
+    token_endpoint: Some(token_endpoint),
+    client_id: Some(client_id),
+    ..Default::default()
+  };
+  config.store_tokens(tokens);
+  Ok(config)
+}
+
 pub async fn whoami() -> Result<()> {
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
   let config = GlobalConfig::load()?;
 
-  if config.auth_token.is_none() {
+  if config.auth_token.is_none() && config.ssh_remote.is_none() {
     println!("❌ Not logged in.");
     return Ok(());
   }
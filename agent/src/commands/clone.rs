@@ -6,15 +6,15 @@ use reqwest::StatusCode;
 use std::{fs, path::Path};
 use tokio;
 
-use crate::{config::{GlobalConfig, LocalRepoConfig}, client::get_authenticated_client};
+use crate::{config::{GlobalConfig, LocalRepoConfig}, client::{get_authenticated_client, repo_path, send_with_retry}};
 
 pub async fn clone(name: String) -> Result<()> {
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
   let config = GlobalConfig::load()?;
 
   println!("📡 Accessing Forge: {}...", style(&name).bold());
 
-  let head_res = client.get(format!("{}/repos/{}/head", config.server_url, name)).send().await?;
+  let head_res = send_with_retry(|| client.get(format!("{}/repos/{}/head", config.server_url, repo_path(&name)))).await?;
   let status = head_res.status();
 
   if status == StatusCode::NOT_FOUND {
@@ -48,9 +48,11 @@ pub async fn clone(name: String) -> Result<()> {
 
   let commit_id_opt = head["commit_id"].as_str();
 
-  let root = Path::new(&name);
+  // `org/repo` is cloned into `repo`, like git does.
+  let dir_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+  let root = Path::new(&dir_name);
   if root.exists() {
-    anyhow::bail!("Directory '{}' already exists. Cannot clone here.", name);
+    anyhow::bail!("Directory '{}' already exists. Cannot clone here.", dir_name);
   }
   fs::create_dir(root)?;
 
@@ -61,7 +63,7 @@ pub async fn clone(name: String) -> Result<()> {
     Some(commit_id) => {
       println!("📥 Materializing assets from snapshot {}...", style(&commit_id[..8]).cyan());
 
-      let tree_res = client.get(format!("{}/repos/{}/commits/{}/tree", config.server_url, name, commit_id)).send().await?;
+      let tree_res = send_with_retry(|| client.get(format!("{}/repos/{}/commits/{}/tree", config.server_url, repo_path(&name), commit_id))).await?;
       let files: Vec<serde_json::Value> = tree_res.json().await?;
 
       let pb = ProgressBar::new(files.len() as u64);
@@ -84,7 +86,7 @@ pub async fn clone(name: String) -> Result<()> {
           let c = client_ref.clone();
           let u = url_ref.clone();
           let cid = cid_ref.to_string();
-          let rname = name_ref.to_string();
+          let rname = repo_path(name_ref);
           let r_root = root_path.clone();
           let pb_ref = pb_clone.clone();
 
@@ -93,7 +95,7 @@ pub async fn clone(name: String) -> Result<()> {
             if let Some(parent) = target.parent() { fs::create_dir_all(parent).ok(); }
 
             let file_url = format!("{}/repos/{}/commits/{}/files/{}", u, rname, cid, path_str);
-            if let Ok(res) = c.get(file_url).send().await {
+            if let Ok(res) = send_with_retry(|| c.get(&file_url)).await {
               if let Ok(bytes) = res.bytes().await {
                 fs::write(target, bytes).ok();
               }
@@ -126,7 +128,7 @@ pub async fn clone(name: String) -> Result<()> {
 
       println!("{}", style("✨ Void resonance established.").blue());
       println!("  This repository is empty. Add files and run:");
-      println!("  $ cd {}", style(&name).bold());
+      println!("  $ cd {}", style(&dir_name).bold());
       println!("  $ plectr save -m \"First light\"");
     }
   }
@@ -4,24 +4,29 @@ use indicatif::ProgressBar;
 use reqwest::StatusCode;
 use std::{fs, time::Duration};
 
-use crate::{config::{GlobalConfig, LocalRepoConfig}, client::get_authenticated_client};
+use crate::{config::{GlobalConfig, LocalRepoConfig}, client::{get_authenticated_client, send_with_retry}};
 
 pub async fn init(name: String, is_public: bool) -> Result<()> {
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
   let config = GlobalConfig::load()?;
 
   let spinner = ProgressBar::new_spinner();
   spinner.set_message("Resonating with Forge...");
   spinner.enable_steady_tick(Duration::from_millis(100));
 
-  let res = client.post(format!("{}/repos", config.server_url))
-    .json(&serde_json::json!({
-      "name": name,
-      "description": "Initialized via CLI",
-      "is_public": is_public
-    }))
-    .send()
-    .await?;
+  // `org/repo` creates the repository inside an organization.
+  let (org, repo) = match name.split_once('/') {
+    Some((org, repo)) => (Some(org), repo),
+    None => (None, name.as_str()),
+  };
+
+  let body = serde_json::json!({
+    "name": repo,
+    "description": "Initialized via CLI",
+    "is_public": is_public,
+    "org": org
+  });
+  let res = send_with_retry(|| client.post(format!("{}/repos", config.server_url)).json(&body)).await?;
 
   spinner.finish_and_clear();
 
@@ -32,6 +37,9 @@ pub async fn init(name: String, is_public: bool) -> Result<()> {
     if res.status() == StatusCode::CONFLICT {
        anyhow::bail!("Repository '{}' already exists.", name);
     }
+    if res.status() == StatusCode::FORBIDDEN || res.status() == StatusCode::NOT_FOUND {
+       anyhow::bail!("Cannot create '{}': {}", name, res.text().await?);
+    }
     anyhow::bail!("Failed to init repo: {}", res.text().await?);
   }
 
@@ -1,13 +1,13 @@
 use anyhow::Result;
 use console::style;
-use crate::{config::{GlobalConfig, load_local_config}, client::get_authenticated_client};
+use crate::{config::{GlobalConfig, load_local_config}, client::{get_authenticated_client, repo_path, send_with_retry}};
 
 pub async fn log() -> Result<()> {
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
   let config = GlobalConfig::load()?;
   let local_config = load_local_config()?;
 
-  let res = client.get(format!("{}/repos/{}/commits", config.server_url, local_config.repo_name)).send().await?;
+  let res = send_with_retry(|| client.get(format!("{}/repos/{}/commits", config.server_url, repo_path(&local_config.repo_name)))).await?;
   let commits: Vec<serde_json::Value> = res.json().await?;
 
   println!("{}", style(format!("Timeline: {}", local_config.repo_name)).bold().underlined());
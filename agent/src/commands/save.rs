@@ -7,16 +7,16 @@ use std::{ fs, time::Duration, path::Path };
 use blake3;
 
 use crate::{
-  config::{ GlobalConfig, load_local_config, save_local_config },
-  client::get_authenticated_client,
+  config::{ GlobalConfig, load_local_config, save_local_config, follow_rename },
+  client::{get_authenticated_client, repo_path, send_with_retry},
 };
 
 pub async fn save(message: Option<String>) -> Result<()> {
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
   let config = GlobalConfig::load()?;
   let mut local_config = load_local_config()?;
 
-  let user_res = client.get(format!("{}/api/me", config.server_url)).send().await;
+  let user_res = send_with_retry(|| client.get(format!("{}/api/me", config.server_url))).await;
   let (author_name, author_email) = match user_res {
     Ok(r) if r.status().is_success() => {
       let json: serde_json::Value = r.json().await?;
@@ -32,19 +32,16 @@ pub async fn save(message: Option<String>) -> Result<()> {
   let mut remote_head_id = None;
 
   if
-    let Ok(res) = client
-      .get(format!("{}/repos/{}/head", config.server_url, local_config.repo_name))
-      .send().await
+    let Ok(res) = send_with_retry(|| client.get(format!("{}/repos/{}/head", config.server_url, repo_path(&local_config.repo_name)))).await
   {
     if let Ok(json) = res.json::<serde_json::Value>().await {
+      follow_rename(&mut local_config, &json)?;
       remote_head_id = json["commit_id"].as_str().map(|s| s.to_string());
       if let Some(ref id) = remote_head_id {
         if
-          let Ok(tree_res) = client
-            .get(
-              format!("{}/repos/{}/commits/{}/tree", config.server_url, local_config.repo_name, id)
-            )
-            .send().await
+          let Ok(tree_res) = send_with_retry(|| {
+            client.get(format!("{}/repos/{}/commits/{}/tree", config.server_url, repo_path(&local_config.repo_name), id))
+          }).await
         {
           if let Ok(files) = tree_res.json::<Vec<serde_json::Value>>().await {
             for f in files {
@@ -144,22 +141,25 @@ pub async fn save(message: Option<String>) -> Result<()> {
 
     let client_ref = &client;
     let url_ref = &config.server_url;
+    let repo_ref = repo_path(&local_config.repo_name);
 
     let upload_results: Vec<Result<()>> = stream
       ::iter(files_to_upload)
       .map(|(path, content)| {
         let c = client_ref.clone();
         let u = url_ref.clone();
+        let r = repo_ref.clone();
         let pb_clone = pb.clone();
 
         async move {
           let file_name = path.clone();
           pb_clone.set_message(path.clone()); 
-          let form = reqwest::multipart::Form
-            ::new()
-            .part("file", reqwest::multipart::Part::bytes(content).file_name(path.clone()));
-
-          let res = c.post(format!("{}/upload", u)).multipart(form).send().await;
+          let res = send_with_retry(|| {
+            let form = reqwest::multipart::Form
+              ::new()
+              .part("file", reqwest::multipart::Part::bytes(content.clone()).file_name(path.clone()));
+            c.post(format!("{}/repos/{}/upload", u, r)).multipart(form)
+          }).await;
 
           match res {
             Ok(response) => {
@@ -215,18 +215,16 @@ pub async fn save(message: Option<String>) -> Result<()> {
     format!("Resonance snapshot {}", now.format("%Y-%m-%d %H:%M"))
   });
 
-  let commit_res = client
-    .post(format!("{}/repos/{}/commits", config.server_url, local_config.repo_name))
-    .json(
-      &serde_json::json!({
-      "message": msg,
-      "author_name": author_name,
-      "author_email": author_email,
-      "parent_commit_id": local_config.last_commit_id,
-      "files": commit_tree
-    })
-    )
-    .send().await?;
+  let commit_body = serde_json::json!({
+    "message": msg,
+    "author_name": author_name,
+    "author_email": author_email,
+    "parent_commit_id": local_config.last_commit_id,
+    "files": commit_tree
+  });
+  let commit_res = send_with_retry(|| {
+    client.post(format!("{}/repos/{}/commits", config.server_url, repo_path(&local_config.repo_name))).json(&commit_body)
+  }).await?;
 
   if commit_res.status().is_success() {
     let res_data: serde_json::Value = commit_res.json().await?;
@@ -4,12 +4,12 @@ use ignore::WalkBuilder;
 use std::{ collections::HashMap, fs };
 use blake3;
 
-use crate::{ config::{ GlobalConfig, load_local_config }, client::get_authenticated_client };
+use crate::{ config::{ GlobalConfig, load_local_config, follow_rename }, client::{ get_authenticated_client, repo_path, send_with_retry } };
 
 pub async fn status() -> Result<()> {
-  let local_config = load_local_config()?;
+  let mut local_config = load_local_config()?;
   let config = GlobalConfig::load()?;
-  let client = get_authenticated_client()?;
+  let client = get_authenticated_client().await?;
 
   println!("📊 Repository: {}", style(&local_config.repo_name).cyan().bold());
   println!("  Remote ID: {}", style(&local_config.repo_id[..8]).dim());
@@ -18,11 +18,10 @@ pub async fn status() -> Result<()> {
   let mut remote_head_id = None;
 
   if
-    let Ok(res) = client
-      .get(format!("{}/repos/{}/head", config.server_url, local_config.repo_name))
-      .send().await
+    let Ok(res) = send_with_retry(|| client.get(format!("{}/repos/{}/head", config.server_url, repo_path(&local_config.repo_name)))).await
   {
     if let Ok(json) = res.json::<serde_json::Value>().await {
+      follow_rename(&mut local_config, &json)?;
       remote_head_id = json["commit_id"].as_str().map(|s| s.to_string());
 
       println!(
@@ -35,11 +34,9 @@ pub async fn status() -> Result<()> {
 
       if let Some(ref id) = remote_head_id {
         if
-          let Ok(tree_res) = client
-            .get(
-              format!("{}/repos/{}/commits/{}/tree", config.server_url, local_config.repo_name, id)
-            )
-            .send().await
+          let Ok(tree_res) = send_with_retry(|| {
+            client.get(format!("{}/repos/{}/commits/{}/tree", config.server_url, repo_path(&local_config.repo_name), id))
+          }).await
         {
           if let Ok(files) = tree_res.json::<Vec<serde_json::Value>>().await {
             for f in files {
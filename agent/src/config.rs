@@ -7,6 +7,19 @@ use std::{fs};
 pub struct GlobalConfig {
   pub server_url: String,
   pub auth_token: Option<String>,
+  /// Set by the device flow login: used to renew `auth_token` before it expires.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub refresh_token: Option<String>,
+  /// Unix timestamp at which `auth_token` expires.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token_expires_at: Option<i64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token_endpoint: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  /// `user@host[:port]` of the forge's SSH transport, used instead of `server_url` and tokens when set.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ssh_remote: Option<String>,
 }
 
 impl GlobalConfig {
@@ -19,11 +32,15 @@ impl GlobalConfig {
     if !path.exists() {
       return Ok(GlobalConfig { 
         server_url: "https://plectr.com".to_string(), 
-        auth_token: None 
+        ..Default::default()
       });
     }
     let content = fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&content)?)
+    let mut config: GlobalConfig = serde_json::from_str(&content)?;
+    if let Some(url) = crate::ssh::tunnel_url() {
+      config.server_url = url.to_string();
+    }
+    Ok(config)
   }
 
   pub fn save(&self) -> Result<()> {
@@ -54,4 +71,17 @@ pub fn save_local_config(config: &LocalRepoConfig) -> Result<()> {
   fs::create_dir_all(".plectr")?;
   fs::write(".plectr/config.json", serde_json::to_string_pretty(config)?)?;
   Ok(())
-}
\ No newline at end of file
+}
+
+/// The forge reports the repository's current name in `/head`; keep the local config in sync
+/// when the repository was renamed or transferred so later requests stop relying on redirects.
+pub fn follow_rename(config: &mut LocalRepoConfig, head: &serde_json::Value) -> Result<()> {
+  if let Some(current) = head["name"].as_str() {
+    if current != config.repo_name {
+      println!("↪️  Repository moved: {} -> {}", config.repo_name, current);
+      config.repo_name = current.to_string();
+      save_local_config(config)?;
+    }
+  }
+  Ok(())
+}
@@ -5,6 +5,7 @@ use console::style;
 mod config;
 mod client;
 mod commands;
+mod ssh;
 
 use commands::{ auth, init, save, clone, log, status };
 
@@ -18,7 +19,14 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-  Login,
+  Login {
+    /// Paste a personal access token instead of using the device flow
+    #[arg(long)]
+    token: bool,
+    /// Reach the forge over SSH (user@host[:port]) with a key registered in the Web UI
+    #[arg(long, conflicts_with = "token")]
+    ssh: Option<String>,
+  },
   Whoami,
   Init {
     #[arg(short, long)]
@@ -45,8 +53,14 @@ async fn main() -> Result<()> {
     print_banner();
   }
 
+  // Login manages its own tunnel: the saved configuration must keep the real forge URL.
+  let _tunnel = match (&cli.command, config::GlobalConfig::load()?.ssh_remote) {
+    (Commands::Login { .. }, _) | (_, None) => None,
+    (_, Some(remote)) => Some(ssh::open(&remote).await?),
+  };
+
   match cli.command {
-    Commands::Login => auth::login().await?,
+    Commands::Login { token, ssh } => auth::login(token, ssh).await?,
     Commands::Whoami => auth::whoami().await?,
     Commands::Init { name, public } => init::init(name, public).await?,
     Commands::Save { message } => save::save(message).await?,
@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Context, Result};
+use std::{process::Stdio, sync::OnceLock, time::Duration};
+use tokio::process::{Child, Command};
+
+/// How long the system `ssh` client gets to authenticate and open the forward.
+const TUNNEL_READY_TIMEOUT: Duration = Duration::from_secs(15);
+
+static TUNNEL_URL: OnceLock<String> = OnceLock::new();
+
+/// Local URL of the open tunnel: replaces `server_url` for the rest of the command.
+pub fn tunnel_url() -> Option<&'static str> {
+  TUNNEL_URL.get().map(|s| s.as_str())
+}
+
+/// A running `ssh -L` forwarding a local port to the forge. The process is killed on drop.
+pub struct Tunnel {
+  _child: Child,
+}
+
+/// Splits `user@host[:port]` into the `ssh` destination and port.
+fn parse_remote(remote: &str) -> Result<(&str, &str)> {
+  let (dest, port) = match remote.rsplit_once(':') {
+    Some((dest, port)) if port.parse::<u16>().is_ok() => (dest, port),
+    _ => (remote, "22"),
+  };
+  if dest.is_empty() {
+    return Err(anyhow!("Invalid SSH remote '{}', expected user@host[:port]", remote));
+  }
+  Ok((dest, port))
+}
+
+/// Opens the tunnel with the system `ssh` client, so keys, agents and `~/.ssh/config` work as usual.
+/// The forge authenticates the key and serves its HTTP API over the forwarded port.
+pub async fn open(remote: &str) -> Result<Tunnel> {
+  let (dest, port) = parse_remote(remote)?;
+
+  let local_port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+  let mut child = Command::new("ssh")
+    .args(["-N", "-T", "-o", "ExitOnForwardFailure=yes", "-o", "BatchMode=yes"])
+    .args(["-L", &format!("127.0.0.1:{}:localhost:80", local_port)])
+    .args(["-p", port, dest])
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .kill_on_drop(true)
+    .spawn()
+    .context("Could not start 'ssh'. Is OpenSSH installed?")?;
+
+  let deadline = tokio::time::Instant::now() + TUNNEL_READY_TIMEOUT;
+  loop {
+    if let Some(status) = child.try_wait()? {
+      return Err(anyhow!("SSH connection to {} failed ({}). Is your key registered in Settings > SSH Keys?", remote, status));
+    }
+    if tokio::net::TcpStream::connect(("127.0.0.1", local_port)).await.is_ok() {
+      break;
+    }
+    if tokio::time::Instant::now() >= deadline {
+      return Err(anyhow!("Timed out opening the SSH tunnel to {}", remote));
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+
+  let _ = TUNNEL_URL.set(format!("http://127.0.0.1:{}", local_port));
+  Ok(Tunnel { _child: child })
+}
@@ -0,0 +1,4 @@
+-- Lien vers le repo source pour les forks (les commits sont dupliqués, les blobs restent partagés dans le CAS)
+ALTER TABLE repositories ADD COLUMN IF NOT EXISTS forked_from UUID REFERENCES repositories(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_repositories_forked_from ON repositories(forked_from);
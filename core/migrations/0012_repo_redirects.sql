@@ -0,0 +1,8 @@
+-- Anciens noms de repos (rename / transfert) : ils continuent de résoudre vers le repo courant
+CREATE TABLE IF NOT EXISTS repo_redirects (
+  old_name TEXT PRIMARY KEY,
+  repo_id UUID NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_repo_redirects_repo ON repo_redirects(repo_id);
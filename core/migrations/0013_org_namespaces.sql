@@ -0,0 +1,7 @@
+-- Les noms de repos ne sont plus globaux : unicité par organisation (UNIQUE(org_id, name) existe déjà)
+-- et unicité globale uniquement pour les repos personnels.
+ALTER TABLE repositories DROP CONSTRAINT IF EXISTS unique_repo_name;
+
+CREATE UNIQUE INDEX IF NOT EXISTS unique_personal_repo_name ON repositories(name) WHERE org_id IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_repositories_org ON repositories(org_id);
@@ -0,0 +1,12 @@
+-- Suppression douce : le repo part à la corbeille, une tâche de purge le supprime après la rétention.
+ALTER TABLE repositories ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+ALTER TABLE repositories ADD COLUMN IF NOT EXISTS deleted_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- Un repo à la corbeille libère son nom : l'unicité ne porte que sur les repos actifs.
+ALTER TABLE repositories DROP CONSTRAINT IF EXISTS repositories_org_id_name_key;
+DROP INDEX IF EXISTS unique_personal_repo_name;
+
+CREATE UNIQUE INDEX IF NOT EXISTS unique_personal_repo_name ON repositories(name) WHERE org_id IS NULL AND deleted_at IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS unique_org_repo_name ON repositories(org_id, name) WHERE org_id IS NOT NULL AND deleted_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_repositories_deleted ON repositories(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,27 @@
+-- Les tokens runners ne sont plus stockés en clair : empreinte SHA-256 salée + préfixe pour la recherche
+ALTER TABLE runners
+ADD COLUMN IF NOT EXISTS token_prefix TEXT,
+ADD COLUMN IF NOT EXISTS token_salt TEXT,
+ADD COLUMN IF NOT EXISTS token_hash TEXT,
+-- Ancien token encore accepté pendant la période de grâce d'une rotation
+ADD COLUMN IF NOT EXISTS previous_token_prefix TEXT,
+ADD COLUMN IF NOT EXISTS previous_token_salt TEXT,
+ADD COLUMN IF NOT EXISTS previous_token_hash TEXT,
+ADD COLUMN IF NOT EXISTS previous_token_expires_at TIMESTAMPTZ,
+-- Portée : une organisation, une liste de repos (runner_repos), ou toute l'instance si rien n'est défini
+ADD COLUMN IF NOT EXISTS org_id UUID REFERENCES organizations(id) ON DELETE CASCADE;
+
+-- Migration des tokens existants (ils restent valides)
+UPDATE runners SET token_prefix = left(token, 16), token_salt = md5(random()::text);
+UPDATE runners SET token_hash = encode(sha256(convert_to(token_salt || token, 'UTF8')), 'hex');
+
+ALTER TABLE runners DROP COLUMN token;
+
+CREATE INDEX IF NOT EXISTS idx_runners_token_prefix ON runners(token_prefix);
+CREATE INDEX IF NOT EXISTS idx_runners_previous_token_prefix ON runners(previous_token_prefix);
+
+CREATE TABLE IF NOT EXISTS runner_repos (
+    runner_id UUID REFERENCES runners(id) ON DELETE CASCADE,
+    repo_id UUID REFERENCES repositories(id) ON DELETE CASCADE,
+    PRIMARY KEY (runner_id, repo_id)
+);
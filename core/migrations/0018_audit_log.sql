@@ -0,0 +1,29 @@
+-- Journal d'audit des événements sensibles (membres, visibilité, miroirs, runners, suppressions, pushs)
+-- Pas de clé étrangère : une entrée doit survivre à la purge du dépôt ou de l'utilisateur concerné
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    actor_id UUID,
+    actor_name TEXT, -- Copié au moment de l'événement
+    ip TEXT,
+    action TEXT NOT NULL, -- ex: repo.member.add, runner.token.rotate
+    repo_id UUID,
+    target TEXT NOT NULL,
+    before JSONB,
+    after JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_repo ON audit_log(repo_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_created ON audit_log(created_at DESC);
+
+-- Append-only : toute modification ou suppression est refusée par la base elle-même
+CREATE OR REPLACE FUNCTION audit_log_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS audit_log_no_update ON audit_log;
+CREATE TRIGGER audit_log_no_update
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_immutable();
@@ -0,0 +1,12 @@
+-- Blobs envoyés sur un dépôt mais pas encore référencés par un commit
+-- Permet de rattacher chaque upload à un dépôt (quotas, GC) et d'interdire de committer un blob inconnu du dépôt
+CREATE TABLE IF NOT EXISTS pending_uploads (
+    repo_id UUID NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    blob_hash TEXT NOT NULL REFERENCES blobs(hash) ON DELETE CASCADE,
+    size BIGINT NOT NULL,
+    uploaded_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (repo_id, blob_hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_uploads_created ON pending_uploads(created_at);
@@ -0,0 +1,12 @@
+-- Clés publiques SSH des utilisateurs (transport SSH de l'agent)
+CREATE TABLE IF NOT EXISTS ssh_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    public_key TEXT NOT NULL, -- Format OpenSSH, sans commentaire
+    fingerprint TEXT NOT NULL UNIQUE, -- SHA256:..., une clé ne peut appartenir qu'à un seul compte
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ssh_keys_user ON ssh_keys(user_id);
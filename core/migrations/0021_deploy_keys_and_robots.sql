@@ -0,0 +1,22 @@
+-- Identités non humaines limitées à un dépôt
+
+-- Clés de déploiement : un token par dépôt, en lecture seule ou lecture-écriture (seule l'empreinte est stockée)
+CREATE TABLE IF NOT EXISTS deploy_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    repo_id UUID NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    token_prefix TEXT NOT NULL,
+    read_only BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID, -- Pas de FK : la clé survit au départ de son créateur
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_deploy_keys_repo ON deploy_keys(repo_id);
+
+-- Comptes robots : des utilisateurs rattachés à un dépôt, membres via repository_members,
+-- authentifiés par un token d'accès. Supprimés avec leur dépôt.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS robot_repo_id UUID REFERENCES repositories(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_users_robot_repo ON users(robot_repo_id) WHERE robot_repo_id IS NOT NULL;
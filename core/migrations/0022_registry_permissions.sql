@@ -0,0 +1,7 @@
+-- Permissions registry explicites par membre, indépendantes du rôle sur le code.
+-- NULL : dérivées du rôle (viewer = pull, editor = pull + push, admin = pull + push + delete)
+ALTER TABLE repository_members ADD COLUMN IF NOT EXISTS registry_perms TEXT[];
+
+ALTER TABLE repository_members DROP CONSTRAINT IF EXISTS repository_members_registry_perms_check;
+ALTER TABLE repository_members ADD CONSTRAINT repository_members_registry_perms_check
+    CHECK (registry_perms <@ ARRAY['pull', 'push', 'delete']::TEXT[]);
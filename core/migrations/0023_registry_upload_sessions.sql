@@ -0,0 +1,7 @@
+-- Sessions d'upload du registry : chaque PATCH est stocké comme un morceau sous uploads/<uuid>/<n>,
+-- assemblés et vérifiés au PUT final
+ALTER TABLE docker_uploads ADD COLUMN IF NOT EXISTS size BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE docker_uploads ADD COLUMN IF NOT EXISTS chunks INT NOT NULL DEFAULT 0;
+ALTER TABLE docker_uploads ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS idx_docker_uploads_updated ON docker_uploads(updated_at);
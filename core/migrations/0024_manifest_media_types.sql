@@ -0,0 +1,32 @@
+-- Les manifests gardent leur media type et leurs octets exacts : le digest porte sur ces octets,
+-- pas sur le JSON ré-sérialisé par Postgres.
+ALTER TABLE docker_manifests ADD COLUMN IF NOT EXISTS media_type TEXT;
+ALTER TABLE docker_manifests ADD COLUMN IF NOT EXISTS raw BYTEA;
+
+UPDATE docker_manifests
+SET media_type = COALESCE(content->>'mediaType', 'application/vnd.docker.distribution.manifest.v2+json')
+WHERE media_type IS NULL;
+
+ALTER TABLE docker_manifests ALTER COLUMN media_type SET NOT NULL;
+
+-- Un même digest peut vivre dans plusieurs images (ex : les plateformes d'une image multi-arch
+-- poussée deux fois) : la clé devient (repo_id, digest).
+ALTER TABLE docker_tags DROP CONSTRAINT IF EXISTS docker_tags_manifest_digest_fkey;
+
+INSERT INTO docker_manifests (digest, repo_id, content, media_type, raw, created_at)
+SELECT DISTINCT ON (t.repo_id, m.digest) m.digest, t.repo_id, m.content, m.media_type, m.raw, m.created_at
+FROM docker_tags t
+JOIN docker_manifests m ON m.digest = t.manifest_digest
+WHERE m.repo_id IS DISTINCT FROM t.repo_id
+  AND NOT EXISTS (SELECT 1 FROM docker_manifests o WHERE o.repo_id = t.repo_id AND o.digest = m.digest)
+ON CONFLICT DO NOTHING;
+
+DELETE FROM docker_manifests WHERE repo_id IS NULL;
+
+ALTER TABLE docker_manifests DROP CONSTRAINT IF EXISTS docker_manifests_pkey;
+ALTER TABLE docker_manifests ALTER COLUMN repo_id SET NOT NULL;
+ALTER TABLE docker_manifests ADD PRIMARY KEY (repo_id, digest);
+CREATE INDEX IF NOT EXISTS idx_docker_manifests_digest ON docker_manifests(digest);
+
+ALTER TABLE docker_tags
+    ADD CONSTRAINT docker_tags_manifest_fkey FOREIGN KEY (repo_id, manifest_digest) REFERENCES docker_manifests(repo_id, digest);
@@ -0,0 +1,11 @@
+-- API referrers OCI 1.1 : une signature, un SBOM ou une attestation désigne l'image qu'il décrit
+-- par le champ `subject` de son manifest
+ALTER TABLE docker_manifests ADD COLUMN IF NOT EXISTS subject_digest TEXT;
+ALTER TABLE docker_manifests ADD COLUMN IF NOT EXISTS artifact_type TEXT;
+
+UPDATE docker_manifests
+SET subject_digest = content->'subject'->>'digest',
+    artifact_type = COALESCE(content->>'artifactType', content->'config'->>'mediaType')
+WHERE content ? 'subject';
+
+CREATE INDEX IF NOT EXISTS idx_docker_manifests_subject ON docker_manifests(repo_id, subject_digest) WHERE subject_digest IS NOT NULL;
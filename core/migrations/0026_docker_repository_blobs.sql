@@ -0,0 +1,20 @@
+-- Lien blob ↔ image : un blob n'est lisible, et référençable par un manifest, que depuis les images
+-- où il a été poussé ou monté. Connaître un digest ne suffit pas.
+CREATE TABLE IF NOT EXISTS docker_repository_blobs (
+    repo_id UUID NOT NULL REFERENCES docker_repositories(id) ON DELETE CASCADE,
+    sha256 TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (repo_id, sha256)
+);
+
+-- Les images existantes gardent l'accès aux blobs de leurs manifests
+INSERT INTO docker_repository_blobs (repo_id, sha256)
+SELECT DISTINCT m.repo_id, substr(d.digest, 8)
+FROM docker_manifests m,
+LATERAL (
+    SELECT m.content->'config'->>'digest' AS digest
+    UNION
+    SELECT l->>'digest' FROM jsonb_array_elements(COALESCE(m.content->'layers', '[]'::jsonb)) l
+) d
+WHERE d.digest LIKE 'sha256:%'
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,33 @@
+-- Un commit copié par un fork pointe vers le commit amont dont il provient : c'est ce qui permet
+-- de calculer une base de fusion entre un fork et son dépôt source
+ALTER TABLE commits ADD COLUMN IF NOT EXISTS upstream_id UUID;
+CREATE INDEX IF NOT EXISTS idx_commits_upstream ON commits(upstream_id) WHERE upstream_id IS NOT NULL;
+
+-- Forks existants : les copies ont gardé message et date du commit source
+UPDATE commits f
+SET upstream_id = s.id
+FROM repositories r, commits s
+WHERE f.repo_id = r.id
+  AND r.forked_from IS NOT NULL
+  AND s.repo_id = r.forked_from
+  AND s.created_at = f.created_at
+  AND s.message = f.message
+  AND f.upstream_id IS NULL;
+
+-- Merge requests d'un fork vers son dépôt amont
+CREATE TABLE IF NOT EXISTS merge_requests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    source_repo_id UUID NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    source_commit_id UUID NOT NULL REFERENCES commits(id) ON DELETE CASCADE,
+    target_repo_id UUID NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    description TEXT,
+    author_id UUID NOT NULL REFERENCES users(id),
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'merged', 'closed')),
+    merge_commit_id UUID REFERENCES commits(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_merge_requests_target ON merge_requests(target_repo_id, status);
+CREATE INDEX IF NOT EXISTS idx_merge_requests_source ON merge_requests(source_repo_id);
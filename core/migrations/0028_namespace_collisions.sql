@@ -0,0 +1,43 @@
+-- Les organisations et les repos personnels partagent le premier segment des noms (`foo/bar` est soit
+-- le repo `bar` de l'organisation `foo`, soit l'image `bar` du repo personnel `foo`) : un même nom ne
+-- peut pas désigner les deux. Les collisions déjà présentes ne sont pas corrigées, seules les nouvelles
+-- sont refusées (code 23505, comme une violation d'unicité).
+CREATE OR REPLACE FUNCTION namespace_lock(name TEXT) RETURNS void AS $$
+BEGIN
+    -- Sérialise les créations concurrentes d'une organisation et d'un repo personnel du même nom.
+    PERFORM pg_advisory_xact_lock(hashtext('namespace:' || name));
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION organization_name_free() RETURNS trigger AS $$
+BEGIN
+    PERFORM namespace_lock(NEW.name);
+    IF EXISTS (SELECT 1 FROM repositories WHERE org_id IS NULL AND deleted_at IS NULL AND name = NEW.name) THEN
+        RAISE EXCEPTION 'Name % is already used by a personal repository', NEW.name USING ERRCODE = '23505';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS organization_name_free ON organizations;
+CREATE TRIGGER organization_name_free
+    BEFORE INSERT OR UPDATE OF name ON organizations
+    FOR EACH ROW EXECUTE FUNCTION organization_name_free();
+
+CREATE OR REPLACE FUNCTION personal_repo_name_free() RETURNS trigger AS $$
+BEGIN
+    PERFORM namespace_lock(NEW.name);
+    IF EXISTS (SELECT 1 FROM organizations WHERE name = NEW.name) THEN
+        RAISE EXCEPTION 'Name % is already used by an organization', NEW.name USING ERRCODE = '23505';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Création, renommage, transfert vers l'espace personnel et restauration depuis la corbeille.
+DROP TRIGGER IF EXISTS personal_repo_name_free ON repositories;
+CREATE TRIGGER personal_repo_name_free
+    BEFORE INSERT OR UPDATE OF name, org_id, deleted_at ON repositories
+    FOR EACH ROW
+    WHEN (NEW.org_id IS NULL AND NEW.deleted_at IS NULL)
+    EXECUTE FUNCTION personal_repo_name_free();
@@ -0,0 +1,24 @@
+-- Chaque image appartient au repo PLECTR qui possède son nom : la purge, le renommage et la liste des
+-- images d'un repo passent par cet id plutôt que par un préfixe de nom (`foo` ≠ `foo/...` de l'organisation
+-- `foo`, et `_`/`%` ne sont pas des jokers).
+ALTER TABLE docker_repositories ADD COLUMN IF NOT EXISTS repository_id UUID REFERENCES repositories(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS idx_docker_repositories_owner ON docker_repositories(repository_id);
+
+-- Images existantes : même résolution que le registry (`org/repo/...` avant `repo/...`), un repo actif
+-- avant un repo à la corbeille.
+UPDATE docker_repositories d
+SET repository_id = COALESCE(
+    (
+        SELECT r.id FROM repositories r JOIN organizations o ON r.org_id = o.id
+        WHERE d.name = o.name || '/' || r.name OR left(d.name, length(o.name || '/' || r.name) + 1) = o.name || '/' || r.name || '/'
+        ORDER BY r.deleted_at IS NULL DESC, r.deleted_at DESC
+        LIMIT 1
+    ),
+    (
+        SELECT r.id FROM repositories r
+        WHERE r.org_id IS NULL AND (d.name = r.name OR left(d.name, length(r.name) + 1) = r.name || '/')
+        ORDER BY r.deleted_at IS NULL DESC, r.deleted_at DESC
+        LIMIT 1
+    )
+)
+WHERE d.repository_id IS NULL;
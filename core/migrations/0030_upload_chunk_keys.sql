@@ -0,0 +1,9 @@
+-- Chaque PATCH écrit son morceau sous une clé qui lui est propre ; seule la requête qui obtient l'offset
+-- l'ajoute à la session, l'autre supprime le sien. Deux PATCH concurrents n'écrasent plus le morceau du
+-- gagnant.
+ALTER TABLE docker_uploads ADD COLUMN IF NOT EXISTS chunk_keys TEXT[] NOT NULL DEFAULT '{}';
+
+-- Sessions en cours : leurs morceaux suivent l'ancien nommage uploads/<uuid>/<n>.
+UPDATE docker_uploads
+SET chunk_keys = ARRAY(SELECT 'uploads/' || uuid || '/' || n FROM generate_series(0, chunks - 1) n)
+WHERE chunks > 0 AND cardinality(chunk_keys) = 0;
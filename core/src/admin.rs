@@ -1,13 +1,15 @@
 use axum::{ extract::{ State, Path }, Json, http::StatusCode };
 use serde_json::{ json, Value };
 use std::sync::Arc;
-use crate::{ state::AppState, auth::AuthUser };
+use crate::{ state::AppState, auth::AuthUser, audit::{ self, ClientIp } };
 use uuid::Uuid;
 use rand::{ distributions::Alphanumeric, Rng };
-use sqlx::Row;
+use serde::Deserialize;
+use sha2::{ Digest, Sha256 };
+use sqlx::{ PgPool, Row };
 
 pub async fn list_runners(State(state): State<Arc<AppState>>, user: AuthUser) -> Result<Json<Value>, (StatusCode, String)> {
-  check_admin(&state, user.id).await?;
+  check_admin(&state, &user).await?;
 
   let runners = sqlx
     ::query(
@@ -16,7 +18,13 @@ pub async fn list_runners(State(state): State<Arc<AppState>>, user: AuthUser) ->
       id, name, platform, hostname, version, tags,
       last_heartbeat_at,
       (EXTRACT(EPOCH FROM (NOW() - last_heartbeat_at)) < 30) as is_online,
-      (SELECT COUNT(*) FROM jobs WHERE runner_id = runners.id AND status = 'running') as active_jobs
+      (SELECT COUNT(*) FROM jobs WHERE runner_id = runners.id AND status = 'running') as active_jobs,
+      (SELECT name FROM organizations WHERE id = runners.org_id) as org_name,
+      ARRAY(
+        SELECT COALESCE(o.name || '/', '') || r.name
+        FROM runner_repos rr JOIN repositories r ON rr.repo_id = r.id LEFT JOIN organizations o ON r.org_id = o.id
+        WHERE rr.runner_id = runners.id
+      ) as repos
     FROM runners
     ORDER BY is_online DESC, name ASC
     "#
@@ -36,7 +44,9 @@ pub async fn list_runners(State(state): State<Arc<AppState>>, user: AuthUser) ->
     "online": r.get::<Option<bool>, _>("is_online").unwrap_or(false),
     "last_seen": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_heartbeat_at").map(|d| d.to_rfc3339()),
     "active_jobs": r.get::<i64, _>("active_jobs"),
-    "tags": r.get::<Option<Vec<String>>, _>("tags")
+    "tags": r.get::<Option<Vec<String>>, _>("tags"),
+    "org": r.get::<Option<String>, _>("org_name"),
+    "repos": r.get::<Vec<String>, _>("repos")
   })
     )
     .collect();
@@ -44,45 +54,226 @@ pub async fn list_runners(State(state): State<Arc<AppState>>, user: AuthUser) ->
   Ok(Json(json!(json_runners)))
 }
 
+const RUNNER_TOKEN_PREFIX_LEN: usize = 16;
+const DEFAULT_ROTATION_GRACE_MINUTES: i64 = 60;
+
+/// Salted SHA-256 of a runner token; the clear token is only shown once, at creation or rotation.
+fn hash_runner_token(salt: &str, token: &str) -> String {
+  hex::encode(Sha256::digest(format!("{}{}", salt, token).as_bytes()))
+}
+
+/// New token with its lookup prefix, salt and hash.
+fn generate_runner_token() -> (String, String, String, String) {
+  let rand_string: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+  let token = format!("plectr_run_{}", rand_string);
+  let salt: String = rand::thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect();
+  let hash = hash_runner_token(&salt, &token);
+  (token.clone(), token[..RUNNER_TOKEN_PREFIX_LEN].to_string(), salt, hash)
+}
+
+/// Resolves a runner from its token. The previous token keeps working until its rotation grace period ends.
+pub async fn authenticate_runner(db: &PgPool, token: &str) -> Option<Uuid> {
+  if token.len() < RUNNER_TOKEN_PREFIX_LEN {
+    return None;
+  }
+  let prefix = &token[..RUNNER_TOKEN_PREFIX_LEN];
+
+  let rows = sqlx
+    ::query(
+      r#"
+      SELECT id, token_salt, token_hash, previous_token_prefix, previous_token_salt, previous_token_hash, previous_token_expires_at > NOW() as previous_valid
+      FROM runners
+      WHERE token_prefix = $1 OR previous_token_prefix = $1
+      "#
+    )
+    .bind(prefix)
+    .fetch_all(db).await
+    .ok()?;
+
+  rows.iter().find_map(|r| {
+    let current = match (r.get::<Option<String>, _>("token_salt"), r.get::<Option<String>, _>("token_hash")) {
+      (Some(salt), Some(hash)) => hash_runner_token(&salt, token) == hash,
+      _ => false,
+    };
+    let previous =
+      r.get::<Option<bool>, _>("previous_valid").unwrap_or(false) &&
+      r.get::<Option<String>, _>("previous_token_prefix").as_deref() == Some(prefix) &&
+      (match (r.get::<Option<String>, _>("previous_token_salt"), r.get::<Option<String>, _>("previous_token_hash")) {
+        (Some(salt), Some(hash)) => hash_runner_token(&salt, token) == hash,
+        _ => false,
+      });
+    if current || previous { Some(r.get("id")) } else { None }
+  })
+}
+
+#[derive(Deserialize)]
+pub struct CreateRunnerRequest {
+  pub name: Option<String>,
+  /// Restrict the runner to the repositories of this organization.
+  pub org: Option<String>,
+  /// Restrict the runner to these repositories (full names).
+  pub repos: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct RotateRunnerRequest {
+  pub grace_minutes: Option<i64>,
+}
+
 pub async fn create_runner_token(
   State(state): State<Arc<AppState>>,
   user: AuthUser,
-  Json(payload): Json<serde_json::Value>
+  ip: ClientIp,
+  Json(payload): Json<CreateRunnerRequest>
 ) -> Result<Json<Value>, (StatusCode, String)> {
-  check_admin(&state, user.id).await?;
+  check_admin(&state, &user).await?;
 
-  let name = payload["name"].as_str().unwrap_or("unnamed-runner");
-  let rand_string: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
-  let token = format!("plectr_run_{}", rand_string);
+  let name = payload.name.as_deref().unwrap_or("unnamed-runner");
+
+  let org_id: Option<Uuid> = match &payload.org {
+    Some(org) =>
+      Some(
+        sqlx
+          ::query("SELECT id FROM organizations WHERE name = $1")
+          .bind(org)
+          .fetch_optional(&state.db).await
+          .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+          .ok_or((StatusCode::NOT_FOUND, format!("Organization '{}' not found", org)))?
+          .get("id")
+      ),
+    None => None,
+  };
+
+  let mut repo_ids = Vec::new();
+  for repo in payload.repos.iter().flatten() {
+    let access = crate::auth
+      ::repo_access(&state.db, repo, None).await
+      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+      .ok_or((StatusCode::NOT_FOUND, format!("Repository '{}' not found", repo)))?;
+    repo_ids.push(access.repo_id);
+  }
+
+  let (token, prefix, salt, hash) = generate_runner_token();
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  sqlx
-    ::query("INSERT INTO runners (name, token, platform) VALUES ($1, $2, 'unknown')")
+  let runner_id: Uuid = sqlx
+    ::query(
+      "INSERT INTO runners (name, token_prefix, token_salt, token_hash, org_id, platform) VALUES ($1, $2, $3, $4, $5, 'unknown') RETURNING id"
+    )
     .bind(name)
-    .bind(&token)
-    .execute(&state.db).await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    .bind(&prefix)
+    .bind(&salt)
+    .bind(&hash)
+    .bind(org_id)
+    .fetch_one(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("id");
+
+  for repo_id in &repo_ids {
+    sqlx
+      ::query("INSERT INTO runner_repos (runner_id, repo_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+      .bind(runner_id)
+      .bind(repo_id)
+      .execute(&mut *tx).await
+      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  }
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&user), &ip, audit::Entry {
+    action: "runner.create",
+    repo_id: None,
+    target: &runner_id.to_string(),
+    before: None,
+    after: Some(json!({ "name": name, "org": payload.org, "repos": payload.repos, "token_prefix": prefix })),
+  }).await;
 
-  Ok(Json(json!({ "token": token, "name": name })))
+  Ok(Json(json!({ "id": runner_id, "token": token, "name": name, "org": payload.org, "repos": payload.repos })))
+}
+
+pub async fn rotate_runner_token(
+  State(state): State<Arc<AppState>>,
+  user: AuthUser,
+  ip: ClientIp,
+  Path(runner_id): Path<Uuid>,
+  Json(payload): Json<RotateRunnerRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  check_admin(&state, &user).await?;
+
+  let grace = payload.grace_minutes.unwrap_or(DEFAULT_ROTATION_GRACE_MINUTES).max(0);
+  let (token, prefix, salt, hash) = generate_runner_token();
+
+  let row = sqlx
+    ::query(
+      r#"
+      UPDATE runners SET
+        previous_token_prefix = token_prefix,
+        previous_token_salt = token_salt,
+        previous_token_hash = token_hash,
+        previous_token_expires_at = NOW() + make_interval(mins => $5),
+        token_prefix = $2,
+        token_salt = $3,
+        token_hash = $4
+      WHERE id = $1
+      RETURNING previous_token_prefix, previous_token_expires_at
+      "#
+    )
+    .bind(runner_id)
+    .bind(&prefix)
+    .bind(&salt)
+    .bind(&hash)
+    .bind(grace as i32)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Runner not found".to_string()))?;
+
+  let previous_expires_at: chrono::DateTime<chrono::Utc> = row.get("previous_token_expires_at");
+
+  tracing::info!("🔄 Runner token rotated: {} (old token valid until {})", runner_id, previous_expires_at.to_rfc3339());
+
+  audit::record(&state.db, Some(&user), &ip, audit::Entry {
+    action: "runner.token.rotate",
+    repo_id: None,
+    target: &runner_id.to_string(),
+    before: Some(json!({ "token_prefix": row.get::<Option<String>, _>("previous_token_prefix") })),
+    after: Some(json!({ "token_prefix": prefix, "previous_token_expires_at": previous_expires_at.to_rfc3339() })),
+  }).await;
+
+  Ok(Json(json!({ "id": runner_id, "token": token, "previous_token_expires_at": previous_expires_at.to_rfc3339() })))
 }
 
 pub async fn delete_runner(
   State(state): State<Arc<AppState>>,
   user: AuthUser,
+  ip: ClientIp,
   Path(runner_id): Path<Uuid>
 ) -> Result<Json<Value>, (StatusCode, String)> {
-  check_admin(&state, user.id).await?;
-  sqlx
-    ::query("DELETE FROM runners WHERE id = $1")
+  check_admin(&state, &user).await?;
+  let deleted = sqlx
+    ::query("DELETE FROM runners WHERE id = $1 RETURNING name")
     .bind(runner_id)
-    .execute(&state.db).await
+    .fetch_optional(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if let Some(r) = deleted {
+    audit::record(&state.db, Some(&user), &ip, audit::Entry {
+      action: "runner.delete",
+      repo_id: None,
+      target: &runner_id.to_string(),
+      before: Some(json!({ "name": r.get::<Option<String>, _>("name") })),
+      after: None,
+    }).await;
+  }
   Ok(Json(json!({ "status": "deleted" })))
 }
 
-async fn check_admin(state: &Arc<AppState>, user_id: Uuid) -> Result<(), (StatusCode, String)> {
+pub async fn check_admin(state: &Arc<AppState>, user: &AuthUser) -> Result<(), (StatusCode, String)> {
+  user.require_scope("admin")?;
+
   let is_admin = sqlx
     ::query("SELECT is_system_admin FROM users WHERE id = $1")
-    .bind(user_id)
+    .bind(user.id)
     .fetch_optional(&state.db).await
     .unwrap_or(None)
     .map(|r| r.get::<Option<bool>, _>("is_system_admin").unwrap_or(false))
@@ -1,4 +1,4 @@
-use crate::state::AppState;
+use crate::{ state::AppState, auth::RepoReadGuard };
 use axum::{ extract::{ Path, State }, Json };
 use serde::Deserialize;
 use serde_json::{ json, Value };
@@ -14,16 +14,18 @@ pub struct QueryRequest {
 
 pub async fn run_query(
   State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
   Path((_repo_name, commit_id_str, file_path)): Path<(String, String, String)>,
   Json(payload): Json<QueryRequest>
 ) -> Result<Json<Value>, String> {
   let commit_uuid = uuid::Uuid::parse_str(&commit_id_str).map_err(|_| "Invalid Commit UUID")?;
 
   let row = sqlx
-    ::query(r#"SELECT b.hash FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash
-           WHERE cf.commit_id = $1 AND cf.file_path = $2"#)
+    ::query(r#"SELECT b.hash FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash JOIN commits c ON cf.commit_id = c.id
+           WHERE cf.commit_id = $1 AND cf.file_path = $2 AND c.repo_id = $3"#)
     .bind(commit_uuid)
     .bind(file_path)
+    .bind(guard.repo_id)
     .fetch_optional(&state.db).await
     .map_err(|e| e.to_string())?
     .ok_or("File not found in this commit")?;
@@ -0,0 +1,190 @@
+use axum::{
+  async_trait,
+  body::Body,
+  extract::{ ConnectInfo, FromRequestParts, Path, Query, State },
+  http::{ header, request::Parts, StatusCode },
+  response::{ IntoResponse, Response },
+  Json,
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use sqlx::{ PgPool, Row };
+use std::{ convert::Infallible, net::SocketAddr, sync::Arc };
+use uuid::Uuid;
+use crate::{ state::AppState, auth::{ AuthUser, RepoAdminGuard } };
+
+/// Header set by the reverse proxy with the real client address (e.g. `X-Real-IP` behind Caddy).
+/// Unset by default: a client could otherwise forge its own address.
+static TRUSTED_IP_HEADER: Lazy<Option<String>> = Lazy::new(|| std::env::var("TRUSTED_IP_HEADER").ok().filter(|h| !h.is_empty()));
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Address of the caller, from the trusted proxy header when configured, the TCP peer otherwise.
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp where S: Send + Sync {
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    if let Some(name) = TRUSTED_IP_HEADER.as_deref() {
+      let forwarded = parts.headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+      if forwarded.is_some() {
+        return Ok(ClientIp(forwarded));
+      }
+    }
+    let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ci| ci.0.ip().to_string());
+    Ok(ClientIp(peer))
+  }
+}
+
+pub struct Entry<'a> {
+  pub action: &'a str,
+  pub repo_id: Option<Uuid>,
+  pub target: &'a str,
+  pub before: Option<Value>,
+  pub after: Option<Value>,
+}
+
+/// Appends an event to the audit log. A failure is logged but never fails the audited operation.
+pub async fn record(db: &PgPool, actor: Option<&AuthUser>, ip: &ClientIp, entry: Entry<'_>) {
+  let res = sqlx
+    ::query(
+      "INSERT INTO audit_log (actor_id, actor_name, ip, action, repo_id, target, before, after) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    )
+    .bind(actor.map(|a| a.id))
+    .bind(actor.map(|a| a.username.clone()))
+    .bind(&ip.0)
+    .bind(entry.action)
+    .bind(entry.repo_id)
+    .bind(entry.target)
+    .bind(entry.before)
+    .bind(entry.after)
+    .execute(db).await;
+
+  if let Err(e) = res {
+    tracing::error!("❌ Audit log write failed ({} on {}): {}", entry.action, entry.target, e);
+  }
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+  pub action: Option<String>,
+  pub actor: Option<String>,
+  /// Cursor: only entries with a smaller id (the `next` value of the previous page).
+  pub before: Option<i64>,
+  pub limit: Option<i64>,
+  /// `jsonl` exports the matching entries as JSON lines instead of a page.
+  pub format: Option<String>,
+}
+
+/// One page of entries matching the filters, newest first, strictly before the `before` id.
+async fn fetch_entries(
+  db: &PgPool,
+  repo_id: Option<Uuid>,
+  action: Option<&str>,
+  actor: Option<&str>,
+  before: Option<i64>,
+  limit: i64
+) -> Result<Vec<Value>, sqlx::Error> {
+  let rows = sqlx
+    ::query(
+      r#"
+      SELECT id, actor_id, actor_name, ip, action, repo_id, target, before, after, created_at
+      FROM audit_log
+      WHERE ($1::uuid IS NULL OR repo_id = $1)
+        AND ($2::text IS NULL OR action = $2 OR action LIKE $2 || '.%')
+        AND ($3::text IS NULL OR actor_name = $3)
+        AND ($4::bigint IS NULL OR id < $4)
+      ORDER BY id DESC
+      LIMIT $5
+      "#
+    )
+    .bind(repo_id)
+    .bind(action)
+    .bind(actor)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(db).await?;
+
+  Ok(
+    rows
+      .iter()
+      .map(|r| {
+        json!({
+          "id": r.get::<i64, _>("id"),
+          "actor_id": r.get::<Option<Uuid>, _>("actor_id"),
+          "actor": r.get::<Option<String>, _>("actor_name"),
+          "ip": r.get::<Option<String>, _>("ip"),
+          "action": r.get::<String, _>("action"),
+          "repo_id": r.get::<Option<Uuid>, _>("repo_id"),
+          "target": r.get::<String, _>("target"),
+          "before": r.get::<Option<Value>, _>("before"),
+          "after": r.get::<Option<Value>, _>("after"),
+          "created_at": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at").to_rfc3339()
+        })
+      })
+      .collect()
+  )
+}
+
+/// Streams the whole history from the cursor as JSON lines, one bounded page at a time: memory stays flat
+/// and no connection is held while a slow client downloads.
+fn export_log(db: PgPool, repo_id: Option<Uuid>, q: &AuditQuery) -> Response {
+  let (action, actor) = (q.action.clone(), q.actor.clone());
+  // `None` once the last page has been sent.
+  let pages = futures::stream::try_unfold(Some(q.before), move |cursor| {
+    let (db, action, actor) = (db.clone(), action.clone(), actor.clone());
+    async move {
+      let Some(before) = cursor else {
+        return Ok(None);
+      };
+      let entries = fetch_entries(&db, repo_id, action.as_deref(), actor.as_deref(), before, MAX_PAGE_SIZE).await?;
+      if entries.is_empty() {
+        return Ok(None);
+      }
+      let next = if entries.len() as i64 == MAX_PAGE_SIZE { Some(entries.last().and_then(|e| e["id"].as_i64())) } else { None };
+      let chunk: String = entries
+        .iter()
+        .map(|e| format!("{}\n", e))
+        .collect();
+      Ok::<_, sqlx::Error>(Some((chunk, next)))
+    }
+  });
+  ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(pages)).into_response()
+}
+
+async fn query_log(db: &PgPool, repo_id: Option<Uuid>, q: &AuditQuery) -> Result<Response, (StatusCode, String)> {
+  if q.format.as_deref() == Some("jsonl") {
+    return Ok(export_log(db.clone(), repo_id, q));
+  }
+
+  let limit = q.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+  let entries = fetch_entries(db, repo_id, q.action.as_deref(), q.actor.as_deref(), q.before, limit).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let next = if entries.len() as i64 == limit { entries.last().and_then(|e| e["id"].as_i64()) } else { None };
+
+  Ok(Json(json!({ "entries": entries, "next": next })).into_response())
+}
+
+pub async fn list_repo_audit(
+  State(state): State<Arc<AppState>>,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>,
+  Query(q): Query<AuditQuery>
+) -> Result<Response, (StatusCode, String)> {
+  query_log(&state.db, Some(guard.0.repo_id), &q).await
+}
+
+pub async fn list_audit(State(state): State<Arc<AppState>>, user: AuthUser, Query(q): Query<AuditQuery>) -> Result<Response, (StatusCode, String)> {
+  crate::admin::check_admin(&state, &user).await?;
+  query_log(&state.db, None, &q).await
+}
@@ -8,11 +8,12 @@ use axum::{
 };
 use serde::{ Deserialize, Serialize };
 use serde_json::json;
-use sqlx::Row;
+use sqlx::{ postgres::PgRow, PgPool, Row };
 use std::sync::Arc;
 use uuid::Uuid;
 use crate::state::AppState;
-use base64::{ Engine as _, engine::general_purpose };
+use once_cell::sync::Lazy;
+use rand::RngCore;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -27,6 +28,106 @@ pub struct AuthUser {
   pub id: Uuid,
   pub username: String,
   pub email: String,
+  /// Scopes of the personal access token used, `None` for an interactive (OIDC) session.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scopes: Option<Vec<String>>,
+  /// Set for CI job credentials: the token only reads this repository at this commit.
+  #[serde(skip)]
+  pub job: Option<JobBinding>,
+  /// Set for deploy keys: the key only reaches this repository, with the permission its scopes allow.
+  #[serde(skip)]
+  pub deploy_key: Option<DeployKeyBinding>,
+  /// Set for robot accounts: the repository they were created in and are confined to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub robot_repo: Option<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeployKeyBinding {
+  pub key_id: Uuid,
+  pub repo_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobBinding {
+  pub job_id: Uuid,
+  pub repo_id: Uuid,
+  pub commit_id: Uuid,
+}
+
+/// Claims of the per-job tokens minted at dispatch.
+#[derive(Debug, Serialize, Deserialize)]
+struct JobClaims {
+  sub: String,
+  #[serde(flatten)]
+  job: JobBinding,
+  exp: usize,
+}
+
+/// Upper bound on a job token lifetime; the token dies earlier when the job finishes.
+const JOB_TOKEN_TTL_HOURS: i64 = 6;
+
+impl AuthUser {
+  pub fn has_scope(&self, scope: &str) -> bool {
+    match &self.scopes {
+      None => true,
+      Some(scopes) => scopes.iter().any(|s| s == scope || s == "admin"),
+    }
+  }
+
+  pub fn require_scope(&self, scope: &str) -> Result<(), (StatusCode, String)> {
+    if self.has_scope(scope) {
+      Ok(())
+    } else {
+      Err((StatusCode::FORBIDDEN, format!("Token lacks the '{}' scope", scope)))
+    }
+  }
+
+  /// Repository the credentials are confined to (CI job, deploy key or robot account), if any.
+  pub fn confined_repo(&self) -> Option<Uuid> {
+    self.job
+      .as_ref()
+      .map(|j| j.repo_id)
+      .or(self.deploy_key.as_ref().map(|k| k.repo_id))
+      .or(self.robot_repo)
+  }
+
+  /// Creating repositories or organizations is reserved to people.
+  pub fn require_unconfined(&self) -> Result<(), (StatusCode, String)> {
+    if self.confined_repo().is_some() {
+      Err((StatusCode::FORBIDDEN, "These credentials are limited to a single repository".to_string()))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Applies the repository binding of job tokens, deploy keys and robots to a computed access:
+  /// no permission outside their repository, a fixed one inside for jobs and deploy keys.
+  pub fn confine(&self, access: &mut RepoAccess) {
+    if let Some(repo_id) = self.confined_repo() {
+      if repo_id != access.repo_id {
+        access.perm = RepoPerm::None;
+        access.registry_perms = None;
+      } else if self.job.is_some() {
+        access.perm = RepoPerm::Read;
+      } else if self.deploy_key.is_some() {
+        access.perm = self.perm_cap();
+      }
+    }
+  }
+
+  /// Highest repository permission the credentials allow, whatever the user's role.
+  pub fn perm_cap(&self) -> RepoPerm {
+    if self.has_scope("admin") {
+      RepoPerm::Admin
+    } else if self.has_scope("repo:write") {
+      RepoPerm::Write
+    } else if self.has_scope("repo:read") {
+      RepoPerm::Read
+    } else {
+      RepoPerm::None
+    }
+  }
 }
 
 pub trait FromRef<S> {
@@ -39,11 +140,92 @@ impl FromRef<Arc<AppState>> for AppState {
   }
 }
 
+/// CI job tokens are HS256-signed with `JOB_TOKEN_SECRET`; user tokens come from the OIDC provider.
+static JOB_TOKEN_SECRET: Lazy<Vec<u8>> = Lazy::new(|| {
+  match std::env::var("JOB_TOKEN_SECRET") {
+    Ok(secret) => secret.into_bytes(),
+    Err(_) => {
+      tracing::warn!("⚠️ JOB_TOKEN_SECRET not set, using an ephemeral secret (running jobs lose access on restart).");
+      let mut secret = vec![0u8; 32];
+      rand::thread_rng().fill_bytes(&mut secret);
+      secret
+    }
+  }
+});
+
+/// Verifies a bearer token and returns the caller. Every extractor goes through here.
+pub async fn verify_token(state: &AppState, token: &str) -> Result<AuthUser, String> {
+  if token.starts_with(crate::token::TOKEN_PREFIX) {
+    return crate::token::authenticate(state, token).await;
+  }
+  if token.starts_with(crate::deploy::DEPLOY_KEY_PREFIX) {
+    return crate::deploy::authenticate(state, token).await;
+  }
+
+  let header = jsonwebtoken::decode_header(token).map_err(|e| format!("Invalid JWT: {}", e))?;
+
+  if header.alg == jsonwebtoken::Algorithm::HS256 {
+    return verify_job_token(state, token).await;
+  }
+
+  let claims = state.oidc.verify(token).await?;
+
+  let user_id = Uuid::parse_str(&claims.sub).map_err(|_| "Invalid UID".to_string())?;
+
+  Ok(AuthUser {
+    id: user_id,
+    username: claims.preferred_username.unwrap_or("unknown".to_string()),
+    email: claims.email.unwrap_or("".to_string()),
+    scopes: None,
+    job: None,
+    deploy_key: None,
+    robot_repo: None,
+  })
+}
+
+async fn verify_job_token(state: &AppState, token: &str) -> Result<AuthUser, String> {
+  let key = jsonwebtoken::DecodingKey::from_secret(&JOB_TOKEN_SECRET);
+  let claims = jsonwebtoken
+    ::decode::<JobClaims>(token, &key, &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256))
+    .map_err(|e| format!("Invalid job token: {}", e))?.claims;
+
+  // Revocation: the token is only honoured while its job is still pending or running.
+  let active = sqlx
+    ::query(
+      "SELECT 1 FROM jobs j JOIN pipelines p ON j.pipeline_id = p.id WHERE j.id = $1 AND p.repo_id = $2 AND p.commit_id = $3 AND j.status IN ('pending', 'running')"
+    )
+    .bind(claims.job.job_id)
+    .bind(claims.job.repo_id)
+    .bind(claims.job.commit_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| e.to_string())?
+    .is_some();
+
+  if !active {
+    return Err("Job is finished".to_string());
+  }
+
+  Ok(AuthUser {
+    id: claims.job.job_id,
+    username: format!("ci-job-{}", &claims.job.job_id.to_string()[..8]),
+    email: "ci@plectr.internal".to_string(),
+    scopes: Some(vec!["repo:read".to_string()]),
+    job: Some(claims.job),
+    deploy_key: None,
+    robot_repo: None,
+  })
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthUser where S: Send + Sync, AppState: FromRef<S> {
   type Rejection = Response;
 
-  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    // Requests tunnelled over SSH were already authenticated by the caller's key.
+    if let Some(session) = parts.extensions.get::<crate::ssh::SshSession>() {
+      return Ok(session.0.clone());
+    }
+
     let auth_header = parts.headers
       .get("Authorization")
       .and_then(|h| h.to_str().ok())
@@ -54,31 +236,22 @@ impl<S> FromRequestParts<S> for AuthUser where S: Send + Sync, AppState: FromRef
     }
 
     let token = &auth_header[7..];
+    let app_state = AppState::from_ref(state);
 
-    let parts: Vec<&str> = token.split('.').collect();
-    if parts.len() != 3 {
-      return Err((StatusCode::UNAUTHORIZED, "Invalid JWT Structure").into_response());
-    }
-
-    let payload_str = parts[1];
-    let decoded_bytes = general_purpose::URL_SAFE_NO_PAD
-      .decode(payload_str)
-      .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid Base64 Token").into_response())?;
-
-    let claims: Claims = serde_json
-      ::from_slice(&decoded_bytes)
-      .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid Claims JSON").into_response())?;
-
-    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid UID").into_response())?;
-
-    Ok(AuthUser {
-      id: user_id,
-      username: claims.preferred_username.unwrap_or("unknown".to_string()),
-      email: claims.email.unwrap_or("".to_string()),
+    verify_token(&app_state, token).await.map_err(|e| {
+      tracing::debug!("🔒 Rejected token: {}", e);
+      (StatusCode::UNAUTHORIZED, "Invalid or expired token").into_response()
     })
   }
 }
 
+/// Public OIDC settings for the CLI: it discovers the device authorization and token endpoints
+/// from the issuer and logs in with the public `OIDC_CLI_CLIENT_ID` client.
+pub async fn get_auth_config(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+  let client_id = std::env::var("OIDC_CLI_CLIENT_ID").unwrap_or_else(|_| "plectr-cli".to_string());
+  Json(json!({ "issuer": state.oidc.issuer(), "client_id": client_id }))
+}
+
 pub async fn get_me(auth: AuthUser) -> Json<AuthUser> {
   Json(auth)
 }
@@ -123,9 +296,112 @@ pub enum RepoPerm {
 
 pub struct RepoReadGuard {
   pub repo_id: Uuid,
+  pub repo_name: String,
   pub perm: RepoPerm,
 }
 
+pub struct RepoAccess {
+  pub repo_id: Uuid,
+  pub repo_name: String,
+  pub is_public: bool,
+  pub perm: RepoPerm,
+  /// Explicit registry permissions of a direct member (`pull`, `push`, `delete`); `None` derives them from `perm`.
+  pub registry_perms: Option<Vec<String>>,
+}
+
+/// SQL expression for the full repository name: `repo` for personal repositories, `org/repo` inside an
+/// organization. Expects `repositories r LEFT JOIN organizations o ON r.org_id = o.id`.
+pub const REPO_FULL_NAME_SQL: &str = "COALESCE(o.name || '/', '') || r.name";
+
+/// Maps a `repository_members` / `organization_members` role onto a repository permission.
+/// Organization owners administer every repository of the organization, plain members can read them.
+fn role_perm(role: &str) -> RepoPerm {
+  match role {
+    "owner" | "admin" => RepoPerm::Admin,
+    "editor" => RepoPerm::Write,
+    "viewer" | "member" => RepoPerm::Read,
+    _ => RepoPerm::None,
+  }
+}
+
+/// Columns and joins shared by the access lookups. `$2` is the caller's user id.
+fn access_query(filter: &str) -> String {
+  format!(
+    r#"
+        SELECT r.id, {full} as full_name, r.is_public,
+               rm.role::text as member_role,
+               rm.registry_perms,
+               om.role::text as org_role,
+               -- repo_role_enum est ordonné admin < editor < viewer : MIN = rôle le plus fort
+               (
+                   SELECT MIN(g.role)::text
+                   FROM team_repo_grants g
+                   JOIN team_members tm ON tm.team_id = g.team_id
+                   WHERE g.repo_id = r.id AND tm.user_id = $2
+               ) as team_role
+        FROM repositories r
+        LEFT JOIN organizations o ON r.org_id = o.id
+        LEFT JOIN repository_members rm ON r.id = rm.repo_id AND rm.user_id = $2
+        LEFT JOIN organization_members om ON r.org_id = om.org_id AND om.user_id = $2
+        {filter}
+        "#,
+    full = REPO_FULL_NAME_SQL,
+    filter = filter
+  )
+}
+
+fn access_from_row(row: &PgRow) -> RepoAccess {
+  let member_role: Option<String> = row.try_get("member_role").unwrap_or(None);
+  let org_role: Option<String> = row.try_get("org_role").unwrap_or(None);
+  let team_role: Option<String> = row.try_get("team_role").unwrap_or(None);
+
+  // Effective permission: the strongest of the direct, team and organization grants.
+  let perm = [member_role, team_role, org_role]
+    .iter()
+    .filter_map(|role| role.as_deref().map(role_perm))
+    .max()
+    .unwrap_or(RepoPerm::None);
+
+  RepoAccess {
+    repo_id: row.get("id"),
+    repo_name: row.get("full_name"),
+    is_public: row.get::<Option<bool>, _>("is_public").unwrap_or(false),
+    perm,
+    registry_perms: row.try_get("registry_perms").unwrap_or(None),
+  }
+}
+
+/// Resolves a repository by its full name (`repo` or `org/repo`) or by one of its former names
+/// (see `repo_redirects`) and computes the caller's permission on it, from direct membership,
+/// team grants and organization role. Repositories in the trash are invisible.
+pub async fn repo_access(db: &PgPool, repo_name: &str, user_id: Option<Uuid>) -> Result<Option<RepoAccess>, sqlx::Error> {
+  let query = access_query(
+    &format!(
+      r#"
+        WHERE r.deleted_at IS NULL
+          AND ({full} = $1 OR r.id = (SELECT repo_id FROM repo_redirects WHERE old_name = $1))
+        ORDER BY ({full} = $1) DESC
+        LIMIT 1
+      "#,
+      full = REPO_FULL_NAME_SQL
+    )
+  );
+
+  let row = sqlx::query(&query).bind(repo_name).bind(user_id).fetch_optional(db).await?;
+
+  Ok(row.as_ref().map(access_from_row))
+}
+
+/// Same as `repo_access`, for a soft-deleted repository addressed by id (names can be reused while
+/// a repository sits in the trash).
+pub async fn trashed_repo_access(db: &PgPool, repo_id: Uuid, user_id: Uuid) -> Result<Option<RepoAccess>, sqlx::Error> {
+  let query = access_query("WHERE r.id = $1 AND r.deleted_at IS NOT NULL");
+
+  let row = sqlx::query(&query).bind(repo_id).bind(user_id).fetch_optional(db).await?;
+
+  Ok(row.as_ref().map(access_from_row))
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for RepoReadGuard where S: Send + Sync, AppState: FromRef<S> {
   type Rejection = Response;
@@ -141,48 +417,33 @@ impl<S> FromRequestParts<S> for RepoReadGuard where S: Send + Sync, AppState: Fr
 
     let user: Option<AuthUser> = parts.extract_with_state::<Option<AuthUser>, S>(state).await.unwrap_or(None);
 
-    let row = sqlx
-      ::query(
-        r#"
-            SELECT r.id, r.is_public, 
-                   rm.role::text as member_role, 
-                   om.role::text as org_role
-            FROM repositories r
-            LEFT JOIN repository_members rm ON r.id = rm.repo_id AND rm.user_id = $2
-            LEFT JOIN organization_members om ON r.org_id = om.org_id AND om.user_id = $2
-            WHERE r.name = $1
-            "#
-      )
-      .bind(repo_name)
-      .bind(user.as_ref().map(|u| u.id))
-      .fetch_optional(&app_state.db).await
-      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-
-    let row = row.ok_or_else(|| (StatusCode::NOT_FOUND, "Repository not found").into_response())?;
-
-    let id: Uuid = row.try_get("id").unwrap();
-    let is_public: bool = row.try_get("is_public").unwrap();
-    let member_role: Option<String> = row.try_get("member_role").unwrap_or(None);
-    let org_role: Option<String> = row.try_get("org_role").unwrap_or(None);
-
-    if let Some("owner") = org_role.as_deref() {
-      return Ok(RepoReadGuard { repo_id: id, perm: RepoPerm::Admin });
-    }
-    if let Some("admin") = member_role.as_deref() {
-      return Ok(RepoReadGuard { repo_id: id, perm: RepoPerm::Admin });
-    }
-    if let Some("editor") = member_role.as_deref() {
-      return Ok(RepoReadGuard { repo_id: id, perm: RepoPerm::Write });
-    }
-    if let Some(_) = member_role {
-      return Ok(RepoReadGuard { repo_id: id, perm: RepoPerm::Read });
+    let mut access = repo_access(&app_state.db, repo_name, user.as_ref().map(|u| u.id)).await
+      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?
+      .ok_or_else(|| (StatusCode::NOT_FOUND, "Repository not found").into_response())?;
+
+    if let Some(user) = &user {
+      access.perm = std::cmp::min(access.perm, user.perm_cap());
+      user.confine(&mut access);
+
+      // Job tokens read their own repository at their own commit, nothing else.
+      if let Some(job) = &user.job {
+        if job.repo_id == access.repo_id && params.get("commit_id").map(|c| c != &job.commit_id.to_string()).unwrap_or(false) {
+          return Err((StatusCode::FORBIDDEN, "Job token is bound to another commit").into_response());
+        }
+      }
     }
 
-    if is_public {
-      return Ok(RepoReadGuard { repo_id: id, perm: RepoPerm::Read });
+    if access.perm == RepoPerm::None && !access.is_public {
+      // Anonymous callers are asked to authenticate; members may then be let in.
+      if user.is_none() {
+        return Err((StatusCode::UNAUTHORIZED, "Authentication required").into_response());
+      }
+      return Err((StatusCode::FORBIDDEN, "Access Denied: Private Repository").into_response());
     }
 
-    Err((StatusCode::FORBIDDEN, "Access Denied: Private Repository").into_response())
+    let perm = if access.perm == RepoPerm::None { RepoPerm::Read } else { access.perm };
+
+    Ok(RepoReadGuard { repo_id: access.repo_id, repo_name: access.repo_name, perm })
   }
 }
 
@@ -216,21 +477,22 @@ impl<S> FromRequestParts<S> for RepoAdminGuard where S: Send + Sync, AppState: F
   }
 }
 
-pub fn create_system_token() -> anyhow::Result<String> {
+/// Mints the credentials handed to the runner for one job: read access to the job's repository
+/// at its commit, plus artifact upload for the job itself.
+pub fn create_job_token(binding: JobBinding) -> anyhow::Result<String> {
   let expiration = chrono::Utc
     ::now()
-    .checked_add_signed(chrono::Duration::minutes(15))
+    .checked_add_signed(chrono::Duration::hours(JOB_TOKEN_TTL_HOURS))
     .expect("valid timestamp")
     .timestamp();
 
-  let claims = Claims {
-    sub: Uuid::new_v4().to_string(),
-    preferred_username: Some("plectr-ci-system".to_string()),
-    email: Some("ci@plectr.internal".to_string()),
+  let claims = JobClaims {
+    sub: format!("job:{}", binding.job_id),
+    job: binding,
     exp: expiration as usize,
   };
 
-  let header = jsonwebtoken::Header::default();
-  let key = jsonwebtoken::EncodingKey::from_secret("secret".as_ref()); // TODO: Use env var
+  let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+  let key = jsonwebtoken::EncodingKey::from_secret(&JOB_TOKEN_SECRET);
   Ok(jsonwebtoken::encode(&header, &claims, &key)?)
 }
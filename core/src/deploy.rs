@@ -0,0 +1,333 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Json };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use sqlx::Row;
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::{
+  state::AppState,
+  audit::{ self, ClientIp },
+  auth::{ AuthUser, DeployKeyBinding, RepoAdminGuard },
+  token::{ generate_token, hash_token, TOKEN_PREFIX },
+  validation,
+};
+
+pub const DEPLOY_KEY_PREFIX: &str = "plectr_deploy_";
+
+/// Robots get every repository scope: their role in `repository_members` decides what they can do.
+const ROBOT_SCOPES: [&str; 3] = ["repo:read", "repo:write", "registry:push"];
+
+#[derive(Deserialize)]
+pub struct CreateDeployKeyRequest {
+  pub title: String,
+  /// Read-only unless explicitly set to false.
+  pub read_only: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRobotRequest {
+  pub name: String,
+  /// `viewer` (clone, pull) or `editor` (push, image push). Defaults to `viewer`.
+  pub role: Option<String>,
+}
+
+/// Resolves a deploy key into a caller bound to its repository.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, String> {
+  let row = sqlx
+    ::query("SELECT id, repo_id, title, read_only FROM deploy_keys WHERE token_hash = $1")
+    .bind(hash_token(token))
+    .fetch_optional(&state.db).await
+    .map_err(|e| e.to_string())?
+    .ok_or("Unknown deploy key")?;
+
+  let key_id: Uuid = row.get("id");
+
+  let _ = sqlx
+    ::query("UPDATE deploy_keys SET last_used_at = NOW() WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')")
+    .bind(key_id)
+    .execute(&state.db).await;
+
+  let scopes: &[&str] = if row.get("read_only") { &["repo:read"] } else { &ROBOT_SCOPES };
+
+  Ok(AuthUser {
+    id: key_id,
+    username: format!("deploy-key-{}", &key_id.to_string()[..8]),
+    email: "deploy@plectr.internal".to_string(),
+    scopes: Some(scopes.iter().map(|s| s.to_string()).collect()),
+    job: None,
+    deploy_key: Some(DeployKeyBinding { key_id, repo_id: row.get("repo_id") }),
+    robot_repo: None,
+  })
+}
+
+pub async fn list_deploy_keys(State(state): State<Arc<AppState>>, guard: RepoAdminGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query("SELECT id, title, token_prefix, read_only, last_used_at, created_at FROM deploy_keys WHERE repo_id = $1 ORDER BY created_at DESC")
+    .bind(guard.0.repo_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let keys: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "title": r.get::<String, _>("title"),
+        "prefix": r.get::<String, _>("token_prefix"),
+        "read_only": r.get::<bool, _>("read_only"),
+        "last_used_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_used_at").map(|d| d.to_rfc3339()),
+        "created_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(keys)))
+}
+
+pub async fn create_deploy_key(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>,
+  Json(payload): Json<CreateDeployKeyRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_unconfined()?;
+  if payload.title.trim().is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "Key title is required".to_string()));
+  }
+  let read_only = payload.read_only.unwrap_or(true);
+  let token = generate_token(DEPLOY_KEY_PREFIX);
+
+  let id: Uuid = sqlx
+    ::query(
+      "INSERT INTO deploy_keys (repo_id, title, token_hash, token_prefix, read_only, created_by) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(guard.0.repo_id)
+    .bind(payload.title.trim())
+    .bind(hash_token(&token))
+    .bind(&token[..DEPLOY_KEY_PREFIX.len() + 4])
+    .bind(read_only)
+    .bind(auth.id)
+    .fetch_one(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("id");
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.deploy_key.add",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: None,
+    after: Some(json!({ "id": id, "title": payload.title.trim(), "read_only": read_only })),
+  }).await;
+
+  tracing::info!("🔑 Deploy key created on {}: {}", guard.0.repo_name, payload.title.trim());
+
+  // The clear token is only ever returned here.
+  Ok(Json(json!({ "id": id, "title": payload.title.trim(), "read_only": read_only, "token": token })))
+}
+
+pub async fn delete_deploy_key(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path((_repo_name, id)): Path<(String, Uuid)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let row = sqlx
+    ::query("DELETE FROM deploy_keys WHERE id = $1 AND repo_id = $2 RETURNING title, read_only")
+    .bind(id)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Deploy key not found".to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.deploy_key.delete",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: Some(json!({ "id": id, "title": row.get::<String, _>("title"), "read_only": row.get::<bool, _>("read_only") })),
+    after: None,
+  }).await;
+
+  Ok(Json(json!({ "status": "deleted", "id": id })))
+}
+
+/// Issues a non-expiring access token for a robot, revoking the previous ones.
+async fn issue_robot_token(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, robot_id: Uuid) -> Result<String, sqlx::Error> {
+  sqlx
+    ::query("UPDATE personal_access_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+    .bind(robot_id)
+    .execute(&mut **tx).await?;
+
+  let token = generate_token(TOKEN_PREFIX);
+  sqlx
+    ::query("INSERT INTO personal_access_tokens (user_id, name, token_hash, token_prefix, scopes) VALUES ($1, 'robot', $2, $3, $4)")
+    .bind(robot_id)
+    .bind(hash_token(&token))
+    .bind(&token[..TOKEN_PREFIX.len() + 4])
+    .bind(ROBOT_SCOPES.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    .execute(&mut **tx).await?;
+
+  Ok(token)
+}
+
+pub async fn list_robots(State(state): State<Arc<AppState>>, guard: RepoAdminGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query(
+      r#"
+        SELECT u.id, u.username, u.created_at, rm.role::text as role,
+               (SELECT MAX(t.last_used_at) FROM personal_access_tokens t WHERE t.user_id = u.id AND t.revoked_at IS NULL) as last_used_at
+        FROM users u
+        LEFT JOIN repository_members rm ON rm.user_id = u.id AND rm.repo_id = u.robot_repo_id
+        WHERE u.robot_repo_id = $1
+        ORDER BY u.username
+        "#
+    )
+    .bind(guard.0.repo_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let robots: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "username": r.get::<String, _>("username"),
+        "role": r.get::<Option<String>, _>("role"),
+        "last_used_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_used_at").map(|d| d.to_rfc3339()),
+        "created_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(robots)))
+}
+
+pub async fn create_robot(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>,
+  Json(payload): Json<CreateRobotRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_unconfined()?;
+  if !validation::is_valid_name(&payload.name) {
+    return Err((StatusCode::BAD_REQUEST, "Invalid robot name".to_string()));
+  }
+  let role = payload.role.as_deref().unwrap_or("viewer");
+  if role != "viewer" && role != "editor" {
+    return Err((StatusCode::BAD_REQUEST, "Robot role must be 'viewer' or 'editor'".to_string()));
+  }
+
+  let username = format!("{}-{}[bot]", guard.0.repo_name.replace('/', "-"), payload.name);
+  let robot_id = Uuid::new_v4();
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let taken = sqlx
+    ::query("SELECT 1 FROM users WHERE username = $1")
+    .bind(&username)
+    .fetch_optional(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .is_some();
+  if taken {
+    return Err((StatusCode::CONFLICT, format!("Robot '{}' already exists", payload.name)));
+  }
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email, robot_repo_id) VALUES ($1, $2, $3, $4)")
+    .bind(robot_id)
+    .bind(&username)
+    .bind(format!("{}@robots.plectr.internal", robot_id))
+    .bind(guard.0.repo_id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  sqlx
+    ::query("INSERT INTO repository_members (repo_id, user_id, role) VALUES ($1, $2, $3::repo_role_enum)")
+    .bind(guard.0.repo_id)
+    .bind(robot_id)
+    .bind(role)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let token = issue_robot_token(&mut tx, robot_id).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.robot.create",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: None,
+    after: Some(json!({ "id": robot_id, "username": username, "role": role })),
+  }).await;
+
+  tracing::info!("🤖 Robot account created on {}: {}", guard.0.repo_name, username);
+
+  Ok(Json(json!({ "id": robot_id, "username": username, "role": role, "token": token })))
+}
+
+pub async fn rotate_robot_token(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path((_repo_name, id)): Path<(String, Uuid)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_unconfined()?;
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let username: String = sqlx
+    ::query("SELECT username FROM users WHERE id = $1 AND robot_repo_id = $2")
+    .bind(id)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Robot not found".to_string()))?
+    .get("username");
+
+  let token = issue_robot_token(&mut tx, id).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.robot.token.rotate",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: None,
+    after: Some(json!({ "id": id, "username": username })),
+  }).await;
+
+  Ok(Json(json!({ "id": id, "username": username, "token": token })))
+}
+
+pub async fn delete_robot(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path((_repo_name, id)): Path<(String, Uuid)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  // Tokens and memberships go with the user row.
+  let username: String = sqlx
+    ::query("DELETE FROM users WHERE id = $1 AND robot_repo_id = $2 RETURNING username")
+    .bind(id)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Robot not found".to_string()))?
+    .get("username");
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.robot.delete",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: Some(json!({ "id": id, "username": username })),
+    after: None,
+  }).await;
+
+  Ok(Json(json!({ "status": "deleted", "id": id })))
+}
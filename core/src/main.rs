@@ -11,10 +11,26 @@ mod crypto;
 mod mirror;
 mod pipeline;
 mod admin;
+mod org;
+mod team;
+mod oidc;
+mod token;
+mod audit;
+mod ratelimit;
+mod ssh;
+mod deploy;
+mod merge_request;
+
+#[cfg(test)]
+mod router_tests;
+#[cfg(test)]
+mod oidc_tests;
+#[cfg(test)]
+mod ratelimit_tests;
 
 use dashmap::DashMap;
 use anyhow::{ Context, Result };
-use axum::{ extract::{ Multipart, State, DefaultBodyLimit }, routing::{ get, post, put, delete }, Json, Router };
+use axum::{ extract::{ Multipart, Path, State, DefaultBodyLimit }, http::StatusCode, routing::{ get, post, put, delete }, Json, Router };
 use s3::bucket::Bucket;
 use s3::creds::Credentials;
 use s3::region::Region;
@@ -31,6 +47,8 @@ async fn main() -> Result<()> {
   }
   dotenv::dotenv().ok();
   tracing_subscriber::fmt::init();
+  // Fail now rather than on the first `docker login` when PUBLIC_URL is missing.
+  once_cell::sync::Lazy::force(&registry::REGISTRY_TOKEN_REALM);
 
   let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
   let pool = PgPoolOptions::new()
@@ -67,22 +85,91 @@ async fn main() -> Result<()> {
     db: pool,
     bucket,
     active_runners: DashMap::new(),
+    oidc: Arc::new(oidc::OidcVerifier::from_env()),
+    rate_limiter: Arc::new(ratelimit::RateLimiter::from_env()),
   });
 
+  let limiter_state = state.clone();
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+      interval.tick().await;
+      limiter_state.rate_limiter.prune();
+    }
+  });
+
+  // Trash retention and blob garbage collection.
+  let gc_state = state.clone();
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    loop {
+      interval.tick().await;
+      match repo::purge_expired_repos(&gc_state).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("🔥 Purged {} expired repositories", n),
+        Err(e) => tracing::warn!("⚠️ Repository purge failed: {}", e),
+      }
+      match storage::collect_garbage(&gc_state).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("🧹 Collected {} orphaned blobs", n),
+        Err(e) => tracing::warn!("⚠️ Blob GC failed: {}", e),
+      }
+    }
+  });
+
+  let app = router(state.clone());
+
+  tokio::spawn(ssh::serve(state, app.clone()));
+
+  let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+  tracing::info!("🚀 PLECTR Core listening on {}", addr);
+
+  let listener = tokio::net::TcpListener::bind(addr).await?;
+  axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+
+  Ok(())
+}
+
+/// Every HTTP route of the core, also served over SSH.
+fn router(state: Arc<AppState>) -> Router {
   let cors = CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
 
-  let app = Router::new()
+  Router::new()
     .route("/", get(root))
     .route("/api/me", get(auth::get_me).patch(auth::update_profile))
+    .route("/api/auth/config", get(auth::get_auth_config))
+    .route("/api/tokens", get(token::list_tokens).post(token::create_token))
+    .route("/api/tokens/:id", delete(token::revoke_token))
+    .route("/api/me/ssh-keys", get(ssh::list_ssh_keys).post(ssh::add_ssh_key))
+    .route("/api/me/ssh-keys/:id", delete(ssh::delete_ssh_key))
     .route("/api/check/repo/:name", get(validation::check_repo_name))
     .route("/api/check/user/:name", get(validation::check_username))
 
-    .route("/upload", post(upload_handler))
     .route("/repos", post(repo::create_repo).get(repo::list_repos))
+    .route("/orgs", post(org::create_org).get(org::list_orgs))
+    .route("/orgs/:org", get(org::get_org).delete(org::delete_org))
+    .route("/orgs/:org/members", get(org::list_org_members).post(org::add_org_member))
+    .route("/orgs/:org/members/:username", delete(org::remove_org_member))
+    .route("/orgs/:org/repos", get(org::list_org_repos))
+    .route("/orgs/:org/teams", get(team::list_teams).post(team::create_team))
+    .route("/orgs/:org/teams/:team", get(team::get_team).delete(team::delete_team))
+    .route("/orgs/:org/teams/:team/members", post(team::add_team_member))
+    .route("/orgs/:org/teams/:team/members/:username", delete(team::remove_team_member))
+    .route("/orgs/:org/teams/:team/repos", post(team::grant_team_repo))
+    .route("/orgs/:org/teams/:team/repos/:repo", delete(team::revoke_team_repo))
+    .route("/api/trash", get(repo::list_trash))
+    .route("/api/trash/:id", delete(repo::purge_repo_now))
+    .route("/api/trash/:id/restore", post(repo::restore_repo))
     .route("/repos/:name", axum::routing::patch(repo::update_repo).delete(repo::delete_repo))
     .route("/repos/:name/head", get(repo::get_head_commit))
     .route("/repos/:name/commits", get(repo::list_repo_commits).post(repo::create_commit))
     .route("/repos/:name/merge", post(repo::merge_commits))
+    .route("/repos/:name/merge-requests", get(merge_request::list_merge_requests).post(merge_request::create_merge_request))
+    .route("/repos/:name/merge-requests/:id/merge", post(merge_request::merge_merge_request))
+    .route("/repos/:name/merge-requests/:id/close", post(merge_request::close_merge_request))
+    .route("/repos/:name/upload", post(upload_handler))
+    .route("/repos/:name/fork", post(repo::fork_repo))
+    .route("/repos/:name/transfer", post(repo::transfer_repo))
     .route("/repos/:name/mirror", get(mirror::get_mirror_status).post(mirror::save_mirror_config))
     .route("/repos/:name/commits/:commit_id/tree", get(repo::list_commit_files))
     .route("/repos/:name/commits/:commit_id/files/*path", get(repo::get_file_content))
@@ -90,29 +177,50 @@ async fn main() -> Result<()> {
     .route("/analytics/repos/:name/commits/:commit_id/files/*path", post(analytics::run_query))
     .route("/repos/:name/compare", post(repo::compare_blobs))
     .route("/repos/:name/images", get(registry::list_repo_images))
+    .route("/repos/:name/images/tags", delete(registry::delete_image_tag))
     .route("/repos/:name/images/:digest/config", get(registry::inspect_image_config))
     .route("/repos/:name/members", get(repo::list_repo_members).post(repo::add_repo_member))
+    .route("/repos/:name/audit", get(audit::list_repo_audit))
+    .route("/repos/:name/deploy-keys", get(deploy::list_deploy_keys).post(deploy::create_deploy_key))
+    .route("/repos/:name/deploy-keys/:id", delete(deploy::delete_deploy_key))
+    .route("/repos/:name/robots", get(deploy::list_robots).post(deploy::create_robot))
+    .route("/repos/:name/robots/:id", delete(deploy::delete_robot))
+    .route("/repos/:name/robots/:id/token", post(deploy::rotate_robot_token))
 
     // --- DOCKER REGISTRY V2 ---
 
     .route("/v2/", get(registry::v2_base_check).head(registry::v2_base_check))
-    .route("/v2/:name/blobs/:digest", get(registry::head_blob).head(registry::head_blob))
+    .route("/v2/token", get(registry::issue_token))
+    .route("/v2/_catalog", get(registry::catalog))
+    .route("/v2/:name/tags/list", get(registry::list_tags))
+    .route("/v2/:ns/:img/tags/list", get(registry::list_tags_ns))
+    .route("/v2/:name/referrers/:digest", get(registry::referrers))
+    .route("/v2/:ns/:img/referrers/:digest", get(registry::referrers_ns))
+    .route("/v2/:name/blobs/:digest", get(registry::get_blob).head(registry::head_blob))
     .route("/v2/:name/blobs/uploads/", post(registry::start_upload))
-    .route("/v2/:name/blobs/uploads/:uuid", put(registry::complete_upload).patch(registry::complete_upload))
-    .route("/v2/:name/manifests/:reference", put(registry::put_manifest).get(registry::get_manifest).head(registry::head_manifest))
-    .route("/v2/:ns/:img/blobs/:digest", get(registry::head_blob_ns).head(registry::head_blob_ns))
+    .route(
+      "/v2/:name/blobs/uploads/:uuid",
+      put(registry::complete_upload).patch(registry::patch_upload).get(registry::upload_status).delete(registry::cancel_upload)
+    )
+    .route(
+      "/v2/:name/manifests/:reference",
+      put(registry::put_manifest).get(registry::get_manifest).head(registry::head_manifest).delete(registry::delete_manifest)
+    )
+    .route("/v2/:ns/:img/blobs/:digest", get(registry::get_blob_ns).head(registry::head_blob_ns))
     .route("/v2/:ns/:img/blobs/uploads/", post(registry::start_upload_ns))
-    .route("/v2/:ns/:img/blobs/uploads/:uuid", put(registry::complete_upload_ns).patch(registry::complete_upload_ns))
+    .route(
+      "/v2/:ns/:img/blobs/uploads/:uuid",
+      put(registry::complete_upload_ns).patch(registry::patch_upload_ns).get(registry::upload_status_ns).delete(registry::cancel_upload_ns)
+    )
     .route(
       "/v2/:ns/:img/manifests/:reference",
-      put(registry::put_manifest_ns).get(registry::get_manifest_ns).head(registry::head_manifest_ns)
+      put(registry::put_manifest_ns).get(registry::get_manifest_ns).head(registry::head_manifest_ns).delete(registry::delete_manifest_ns)
     )
 
     .route("/api/runner/ws", get(pipeline::runner_ws_handler))
     .route("/repos/:name/pipelines", get(pipeline::list_pipelines))
     .route("/repos/:name/pipelines/:id", get(pipeline::get_pipeline_details))
 
-    .route("/repos/:name/pipelines/artifacts", post(pipeline::upload_job_artifact))
     .route("/api/runner/jobs/:id/artifacts", post(pipeline::upload_job_artifact))
 
     .route("/repos/:name/releases", get(pipeline::list_repo_releases))
@@ -120,30 +228,70 @@ async fn main() -> Result<()> {
 
     .route("/api/admin/runners", get(admin::list_runners).post(admin::create_runner_token))
     .route("/api/admin/runners/:id", delete(admin::delete_runner))
+    .route("/api/admin/runners/:id/rotate", post(admin::rotate_runner_token))
+    .route("/api/admin/audit", get(audit::list_audit))
 
     .layer(DefaultBodyLimit::disable())
+    .layer(axum::middleware::from_fn_with_state(state.clone(), ratelimit::rate_limit))
     .layer(cors)
-    .with_state(state);
-
-  let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-  tracing::info!("🚀 PLECTR Core listening on {}", addr);
-
-  let listener = tokio::net::TcpListener::bind(addr).await?;
-  axum::serve(listener, app).await?;
-
-  Ok(())
+    .with_state(state)
 }
 
 async fn root() -> &'static str {
   "PLECTR Core: Online & Resonating."
 }
 
-async fn upload_handler(State(state): State<Arc<AppState>>, mut multipart: Multipart) -> Json<Value> {
+/// Stores the uploaded files and records them as pending on the repository until a commit references them.
+/// Every file gets an entry: in `blobs` when stored, in `errors` otherwise.
+async fn upload_handler(
+  State(state): State<Arc<AppState>>,
+  user: auth::AuthUser,
+  guard: auth::RepoWriteGuard,
+  Path(_repo_name): Path<String>,
+  mut multipart: Multipart
+) -> (StatusCode, Json<Value>) {
   let mut uploaded_blobs = Vec::new();
-  while let Ok(Some(field)) = multipart.next_field().await {
-    if let Ok(info) = storage::ingest_file(state.clone(), field).await {
-      uploaded_blobs.push(json!({ "hash": info.hash, "size": info.size, "mime_type": info.mime_type }));
+  let mut errors = Vec::new();
+
+  loop {
+    let field = match multipart.next_field().await {
+      Ok(Some(field)) => field,
+      Ok(None) => break,
+      Err(e) => {
+        errors.push(json!({ "file": null, "error": format!("Malformed multipart body: {}", e) }));
+        break;
+      }
+    };
+    let file_name = field.file_name().unwrap_or("unknown").to_string();
+
+    match storage::ingest_file(state.clone(), field).await {
+      Ok(info) => {
+        let pending = sqlx
+          ::query(
+            "INSERT INTO pending_uploads (repo_id, blob_hash, size, uploaded_by) VALUES ($1, $2, $3, $4) ON CONFLICT (repo_id, blob_hash) DO UPDATE SET created_at = NOW()"
+          )
+          .bind(guard.0.repo_id)
+          .bind(&info.hash)
+          .bind(info.size)
+          .bind(user.id)
+          .execute(&state.db).await;
+
+        match pending {
+          Ok(_) => uploaded_blobs.push(json!({ "file": file_name, "hash": info.hash, "size": info.size, "mime_type": info.mime_type })),
+          Err(e) => errors.push(json!({ "file": file_name, "error": e.to_string() })),
+        }
+      }
+      Err(e) => {
+        tracing::warn!("⚠️ Upload of {} to {} failed: {:#}", file_name, guard.0.repo_name, e);
+        errors.push(json!({ "file": file_name, "error": format!("{:#}", e) }));
+      }
     }
   }
-  Json(json!({ "status": "ok", "blobs": uploaded_blobs }))
+
+  let (code, status) = match (uploaded_blobs.is_empty(), errors.is_empty()) {
+    (_, true) => (StatusCode::OK, "ok"),
+    (false, false) => (StatusCode::MULTI_STATUS, "partial"),
+    (true, false) => (StatusCode::UNPROCESSABLE_ENTITY, "failed"),
+  };
+  (code, Json(json!({ "status": status, "blobs": uploaded_blobs, "errors": errors })))
 }
@@ -0,0 +1,298 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Json };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use std::sync::Arc;
+use sqlx::{ PgPool, Row };
+use uuid::Uuid;
+use crate::{ state::AppState, audit::{ self, ClientIp }, auth::{ AuthUser, RepoPerm, RepoReadGuard, RepoWriteGuard, REPO_FULL_NAME_SQL } };
+
+#[derive(Deserialize)]
+pub struct CreateMergeRequest {
+  pub title: String,
+  pub description: Option<String>,
+  /// Fork commit to propose; the fork's head when omitted.
+  pub commit_id: Option<Uuid>,
+}
+
+/// Latest commit of the target repository that `commit` descends from, found through the
+/// `upstream_id` each fork copy keeps.
+async fn merge_base(db: &PgPool, commit: Uuid, target_repo_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+  let row = sqlx
+    ::query(
+      r#"
+      WITH RECURSIVE ancestry AS (
+        SELECT id, parent_id, upstream_id, 0 AS depth FROM commits WHERE id = $1
+        UNION ALL
+        SELECT c.id, c.parent_id, c.upstream_id, a.depth + 1 FROM commits c JOIN ancestry a ON c.id = a.parent_id
+      )
+      SELECT t.id FROM ancestry a JOIN commits t ON t.id = a.upstream_id AND t.repo_id = $2
+      ORDER BY a.depth
+      LIMIT 1
+      "#
+    )
+    .bind(commit)
+    .bind(target_repo_id)
+    .fetch_optional(db).await?;
+  Ok(row.map(|r| r.get("id")))
+}
+
+async fn head_commit(db: &PgPool, repo_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+  let row = sqlx
+    ::query("SELECT id FROM commits WHERE repo_id = $1 AND is_divergent = FALSE ORDER BY created_at DESC LIMIT 1")
+    .bind(repo_id)
+    .fetch_optional(db).await?;
+  Ok(row.map(|r| r.get("id")))
+}
+
+/// Opens a merge request from a fork to the repository it was forked from.
+pub async fn create_merge_request(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoWriteGuard,
+  Path(_repo_name): Path<String>,
+  Json(payload): Json<CreateMergeRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("repo:write")?;
+  if payload.title.trim().is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "Title is required".to_string()));
+  }
+
+  let upstream = sqlx
+    ::query(
+      &format!(
+        "SELECT r.id, {} AS name FROM repositories f JOIN repositories r ON f.forked_from = r.id LEFT JOIN organizations o ON r.org_id = o.id WHERE f.id = $1 AND r.deleted_at IS NULL",
+        REPO_FULL_NAME_SQL
+      )
+    )
+    .bind(guard.0.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::BAD_REQUEST, "This repository is not a fork".to_string()))?;
+  let target_id: Uuid = upstream.get("id");
+  let target_name: String = upstream.get("name");
+
+  // The author must still be able to see the upstream, e.g. after losing access to a private source.
+  let target_access = crate::auth
+    ::repo_access(&state.db, &target_name, Some(auth.id)).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  if !target_access.is_some_and(|a| a.is_public || a.perm >= RepoPerm::Read) {
+    return Err((StatusCode::NOT_FOUND, "Upstream repository not found".to_string()));
+  }
+
+  let commit_id = match payload.commit_id {
+    Some(id) => id,
+    None =>
+      head_commit(&state.db, guard.0.repo_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::BAD_REQUEST, "The fork has no commits".to_string()))?,
+  };
+  let owned = sqlx
+    ::query("SELECT 1 FROM commits WHERE id = $1 AND repo_id = $2")
+    .bind(commit_id)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .is_some();
+  if !owned {
+    return Err((StatusCode::NOT_FOUND, "Commit not found in this repository".to_string()));
+  }
+
+  let base = merge_base(&state.db, commit_id, target_id).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::UNPROCESSABLE_ENTITY, "No common history with the upstream repository".to_string()))?;
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
+    .bind(auth.id)
+    .bind(&auth.username)
+    .bind(&auth.email)
+    .execute(&state.db).await
+    .ok();
+
+  let id: Uuid = sqlx
+    ::query(
+      "INSERT INTO merge_requests (source_repo_id, source_commit_id, target_repo_id, title, description, author_id) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(guard.0.repo_id)
+    .bind(commit_id)
+    .bind(target_id)
+    .bind(payload.title.trim())
+    .bind(&payload.description)
+    .bind(auth.id)
+    .fetch_one(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("id");
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "merge_request.open",
+    repo_id: Some(target_id),
+    target: &target_name,
+    before: None,
+    after: Some(json!({ "id": id, "source": guard.0.repo_name, "commit_id": commit_id, "title": payload.title.trim() })),
+  }).await;
+
+  Ok(Json(json!({ "id": id, "status": "open", "target": target_name, "merge_base": base })))
+}
+
+/// Merge requests targeting this repository, and those opened from it.
+pub async fn list_merge_requests(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query(
+      &format!(
+        r#"
+        SELECT m.id, m.title, m.description, m.status, m.source_commit_id, m.merge_commit_id, m.created_at, m.updated_at,
+               m.target_repo_id, u.username AS author, {src} AS source, {dst} AS target
+        FROM merge_requests m
+        JOIN users u ON u.id = m.author_id
+        JOIN repositories sr ON sr.id = m.source_repo_id LEFT JOIN organizations so ON sr.org_id = so.id
+        JOIN repositories tr ON tr.id = m.target_repo_id LEFT JOIN organizations tor ON tr.org_id = tor.id
+        WHERE m.target_repo_id = $1 OR m.source_repo_id = $1
+        ORDER BY m.created_at DESC
+        "#,
+        src = "COALESCE(so.name || '/', '') || sr.name",
+        dst = "COALESCE(tor.name || '/', '') || tr.name"
+      )
+    )
+    .bind(guard.repo_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let list: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "title": r.get::<String, _>("title"),
+        "description": r.get::<Option<String>, _>("description"),
+        "status": r.get::<String, _>("status"),
+        "direction": if r.get::<Uuid, _>("target_repo_id") == guard.repo_id { "incoming" } else { "outgoing" },
+        "source": r.get::<String, _>("source"),
+        "target": r.get::<String, _>("target"),
+        "author": r.get::<String, _>("author"),
+        "source_commit_id": r.get::<Uuid, _>("source_commit_id"),
+        "merge_commit_id": r.get::<Option<Uuid>, _>("merge_commit_id"),
+        "created_at": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at").to_rfc3339(),
+        "updated_at": r.get::<chrono::DateTime<chrono::Utc>, _>("updated_at").to_rfc3339()
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(list)))
+}
+
+/// Merges an open request into the target. Only fast-forwards are accepted: if the target moved past
+/// the merge base, the fork has to catch up first. The merge commit takes the fork commit's tree, and
+/// the fork commit is linked to it so the next request from the same fork starts from there.
+pub async fn merge_merge_request(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoWriteGuard,
+  Path((_repo_name, id)): Path<(String, Uuid)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("repo:write")?;
+  let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+  let mut tx = state.db.begin().await.map_err(internal)?;
+  let mr = sqlx
+    ::query("SELECT title, status, source_commit_id FROM merge_requests WHERE id = $1 AND target_repo_id = $2 FOR UPDATE")
+    .bind(id)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&mut *tx).await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Merge request not found".to_string()))?;
+  if mr.get::<String, _>("status") != "open" {
+    return Err((StatusCode::CONFLICT, "Merge request is not open".to_string()));
+  }
+  let title: String = mr.get("title");
+  let source_commit: Uuid = mr.get("source_commit_id");
+
+  let base = merge_base(&state.db, source_commit, guard.0.repo_id).await.map_err(internal)?;
+  let head = head_commit(&state.db, guard.0.repo_id).await.map_err(internal)?;
+  if base.is_none() || base != head {
+    return Err((StatusCode::CONFLICT, "The target has moved since the fork: update the fork before merging".to_string()));
+  }
+
+  let merge_commit: Uuid = sqlx
+    ::query(
+      r#"
+      INSERT INTO commits (repo_id, message, author_name, author_email, tree_hash, parent_id, is_divergent)
+      SELECT $1, $2, author_name, author_email, tree_hash, $3, FALSE FROM commits WHERE id = $4
+      RETURNING id
+      "#
+    )
+    .bind(guard.0.repo_id)
+    .bind(format!("Merge request: {}", title))
+    .bind(head)
+    .bind(source_commit)
+    .fetch_one(&mut *tx).await
+    .map_err(internal)?
+    .get("id");
+
+  sqlx
+    ::query("INSERT INTO commit_files (commit_id, file_path, blob_hash) SELECT $1, file_path, blob_hash FROM commit_files WHERE commit_id = $2")
+    .bind(merge_commit)
+    .bind(source_commit)
+    .execute(&mut *tx).await
+    .map_err(internal)?;
+
+  sqlx::query("UPDATE commits SET upstream_id = $1 WHERE id = $2").bind(merge_commit).bind(source_commit).execute(&mut *tx).await.map_err(internal)?;
+
+  sqlx
+    ::query("UPDATE merge_requests SET status = 'merged', merge_commit_id = $1, updated_at = NOW() WHERE id = $2")
+    .bind(merge_commit)
+    .bind(id)
+    .execute(&mut *tx).await
+    .map_err(internal)?;
+
+  tx.commit().await.map_err(internal)?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "merge_request.merge",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: Some(json!({ "id": id, "head": head })),
+    after: Some(json!({ "id": id, "commit_id": merge_commit })),
+  }).await;
+
+  Ok(Json(json!({ "status": "merged", "commit_id": merge_commit })))
+}
+
+/// Closes an open request without merging. Allowed to the target's writers and to the author.
+pub async fn close_merge_request(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoReadGuard,
+  Path((_repo_name, id)): Path<(String, Uuid)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("repo:write")?;
+  let author: Uuid = sqlx
+    ::query("SELECT author_id FROM merge_requests WHERE id = $1 AND target_repo_id = $2 AND status = 'open'")
+    .bind(id)
+    .bind(guard.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Open merge request not found".to_string()))?
+    .get("author_id");
+  if author != auth.id && guard.perm < RepoPerm::Write {
+    return Err((StatusCode::FORBIDDEN, "Only the author or the target's writers can close this merge request".to_string()));
+  }
+
+  sqlx
+    ::query("UPDATE merge_requests SET status = 'closed', updated_at = NOW() WHERE id = $1")
+    .bind(id)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "merge_request.close",
+    repo_id: Some(guard.repo_id),
+    target: &guard.repo_name,
+    before: Some(json!({ "id": id, "status": "open" })),
+    after: Some(json!({ "id": id, "status": "closed" })),
+  }).await;
+
+  Ok(Json(json!({ "status": "closed", "id": id })))
+}
@@ -10,7 +10,7 @@ use tokio::fs;
 use anyhow::{ anyhow, Context, Result };
 use tempfile::TempDir;
 
-use crate::{ state::AppState, auth::RepoAdminGuard, crypto };
+use crate::{ state::AppState, auth::{ AuthUser, RepoAdminGuard }, audit::{ self, ClientIp }, crypto };
 
 #[derive(Deserialize)]
 pub struct MirrorConfig {
@@ -21,12 +21,21 @@ pub struct MirrorConfig {
 
 pub async fn save_mirror_config(
   State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
   guard: RepoAdminGuard,
   Path(_repo_name): Path<String>,
   Json(payload): Json<MirrorConfig>
 ) -> Result<Json<Value>, (StatusCode, String)> {
   let encrypted = crypto::encrypt(&payload.token).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Crypto Error: {}", e)))?;
 
+  let before = sqlx
+    ::query("SELECT remote_url, is_enabled FROM repo_mirrors WHERE repo_id = $1")
+    .bind(guard.0.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| json!({ "remote_url": r.get::<String, _>("remote_url"), "enabled": r.get::<Option<bool>, _>("is_enabled") }));
+
   sqlx
     ::query(
       r#"
@@ -49,6 +58,15 @@ pub async fn save_mirror_config(
     .execute(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+  // The credential itself never reaches the log, only the fact that it was replaced.
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.mirror.configure",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before,
+    after: Some(json!({ "remote_url": payload.remote_url, "enabled": payload.enabled, "token_changed": true })),
+  }).await;
+
   Ok(Json(json!({ "status": "configured", "encrypted": true })))
 }
 
@@ -0,0 +1,115 @@
+use jsonwebtoken::{ decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation };
+use std::time::{ Duration, Instant };
+use tokio::sync::RwLock;
+use crate::auth::Claims;
+
+/// Keys are refetched after this delay, or earlier when a token carries an unknown `kid` (key rotation).
+const JWKS_TTL: Duration = Duration::from_secs(600);
+/// Unknown `kid`s must not turn every forged token into a request to the identity provider.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+struct CachedJwks {
+  keys: JwkSet,
+  fetched_at: Instant,
+}
+
+/// Verifies access tokens issued by the OIDC provider (Keycloak) against its published JWKS:
+/// signature, issuer, audience and expiry.
+pub struct OidcVerifier {
+  issuer: String,
+  audience: Option<String>,
+  jwks_url: String,
+  http: reqwest::Client,
+  cache: RwLock<Option<CachedJwks>>,
+}
+
+impl OidcVerifier {
+  pub fn new(issuer: String, audience: Option<String>, jwks_url: String) -> Self {
+    Self {
+      issuer,
+      audience,
+      jwks_url,
+      http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default(),
+      cache: RwLock::new(None),
+    }
+  }
+
+  /// `OIDC_ISSUER` must match the `iss` claim of the tokens. The JWKS defaults to Keycloak's
+  /// certs endpoint under the issuer; `OIDC_JWKS_URL` overrides it (e.g. an internal hostname).
+  pub fn from_env() -> Self {
+    let issuer = std::env::var("OIDC_ISSUER").expect("OIDC_ISSUER must be set");
+    let audience = std::env::var("OIDC_AUDIENCE").ok().filter(|a| !a.is_empty());
+    let jwks_url = std::env
+      ::var("OIDC_JWKS_URL")
+      .unwrap_or_else(|_| format!("{}/protocol/openid-connect/certs", issuer.trim_end_matches('/')));
+    Self::new(issuer, audience, jwks_url)
+  }
+
+  pub fn issuer(&self) -> &str {
+    &self.issuer
+  }
+
+  async fn fetch_keys(&self) -> Result<JwkSet, String> {
+    let res = self.http
+      .get(&self.jwks_url)
+      .send().await
+      .map_err(|e| format!("JWKS fetch failed: {}", e))?;
+    if !res.status().is_success() {
+      return Err(format!("JWKS fetch failed: HTTP {}", res.status()));
+    }
+    res.json::<JwkSet>().await.map_err(|e| format!("Invalid JWKS: {}", e))
+  }
+
+  async fn find_key(&self, kid: &str) -> Result<Option<jsonwebtoken::jwk::Jwk>, String> {
+    {
+      let cache = self.cache.read().await;
+      if let Some(cached) = cache.as_ref() {
+        let key = cached.keys.find(kid).cloned();
+        let fresh = cached.fetched_at.elapsed() < JWKS_TTL;
+        let throttled = cached.fetched_at.elapsed() < JWKS_MIN_REFRESH;
+        if (key.is_some() && fresh) || throttled {
+          return Ok(key);
+        }
+      }
+    }
+
+    let mut cache = self.cache.write().await;
+    // Another request may have refreshed the keys while we waited for the lock.
+    if let Some(cached) = cache.as_ref() {
+      if cached.fetched_at.elapsed() < JWKS_MIN_REFRESH {
+        return Ok(cached.keys.find(kid).cloned());
+      }
+    }
+
+    let keys = self.fetch_keys().await?;
+    let key = keys.find(kid).cloned();
+    *cache = Some(CachedJwks { keys, fetched_at: Instant::now() });
+    Ok(key)
+  }
+
+  pub async fn verify(&self, token: &str) -> Result<Claims, String> {
+    let header = decode_header(token).map_err(|e| format!("Invalid JWT header: {}", e))?;
+
+    // Provider tokens are always signed with an asymmetric key; HMAC would let a public key act as a secret.
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+      return Err("Unexpected signing algorithm".to_string());
+    }
+
+    let kid = header.kid.ok_or("Missing key id")?;
+    let jwk = self.find_key(&kid).await?.ok_or("Unknown signing key")?;
+    let key = DecodingKey::from_jwk(&jwk).map_err(|e| format!("Unusable signing key: {}", e))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&self.issuer]);
+    match &self.audience {
+      Some(aud) => validation.set_audience(&[aud]),
+      None => {
+        validation.validate_aud = false;
+      }
+    }
+
+    decode::<Claims>(token, &key, &validation)
+      .map(|data| data.claims)
+      .map_err(|e| format!("Invalid token: {}", e))
+  }
+}
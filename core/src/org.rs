@@ -0,0 +1,343 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Json };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use std::sync::Arc;
+use sqlx::{ PgPool, Row };
+use uuid::Uuid;
+use crate::{ state::AppState, auth::{ AuthUser, REPO_FULL_NAME_SQL }, validation };
+
+#[derive(Deserialize)]
+pub struct CreateOrgRequest {
+  pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddOrgMemberRequest {
+  pub email: String,
+  pub role: String,
+}
+
+/// Looks up an organization by name and returns its id together with the caller's role in it
+/// (`owner`, `member`, or `None` for outsiders).
+pub async fn membership(db: &PgPool, org_name: &str, user_id: Uuid) -> Result<(Uuid, Option<String>), (StatusCode, String)> {
+  let row = sqlx
+    ::query(
+      r#"
+        SELECT o.id, om.role::text as role
+        FROM organizations o
+        LEFT JOIN organization_members om ON om.org_id = o.id AND om.user_id = $2
+        WHERE o.name = $1
+        "#
+    )
+    .bind(org_name)
+    .bind(user_id)
+    .fetch_optional(db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, format!("Organization '{}' not found", org_name)))?;
+
+  Ok((row.get("id"), row.get("role")))
+}
+
+pub async fn require_member(db: &PgPool, org_name: &str, user_id: Uuid) -> Result<(Uuid, String), (StatusCode, String)> {
+  match membership(db, org_name, user_id).await? {
+    (id, Some(role)) => Ok((id, role)),
+    // Outsiders get the same answer as for a missing organization.
+    (_, None) => Err((StatusCode::NOT_FOUND, format!("Organization '{}' not found", org_name))),
+  }
+}
+
+pub async fn require_owner(db: &PgPool, org_name: &str, auth: &AuthUser) -> Result<Uuid, (StatusCode, String)> {
+  let (id, role) = require_member(db, org_name, auth.id).await?;
+  auth.require_scope("admin")?;
+  if role != "owner" {
+    return Err((StatusCode::FORBIDDEN, "Organization owner privileges required".to_string()));
+  }
+  Ok(id)
+}
+
+pub async fn create_org(State(state): State<Arc<AppState>>, auth: AuthUser, Json(payload): Json<CreateOrgRequest>) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("admin")?;
+  auth.require_unconfined()?;
+
+  if !validation::is_valid_name(&payload.name) {
+    return Err((StatusCode::BAD_REQUEST, "Invalid organization name".to_string()));
+  }
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
+    .bind(auth.id)
+    .bind(&auth.username)
+    .bind(&auth.email)
+    .execute(&state.db).await
+    .ok();
+
+  // `name/...` must keep pointing at a single namespace (see migration 0028).
+  let personal = sqlx
+    ::query("SELECT 1 FROM repositories WHERE org_id IS NULL AND deleted_at IS NULL AND name = $1")
+    .bind(&payload.name)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .is_some();
+  if personal {
+    return Err((StatusCode::CONFLICT, format!("'{}' is already the name of a personal repository.", payload.name)));
+  }
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let row_res = sqlx::query("INSERT INTO organizations (name) VALUES ($1) RETURNING id").bind(&payload.name).fetch_one(&mut *tx).await;
+  let org_id: Uuid = match row_res {
+    Ok(r) => r.get("id"),
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, format!("Organization '{}' already exists.", payload.name)));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  };
+
+  sqlx
+    ::query("INSERT INTO organization_members (org_id, user_id, role) VALUES ($1, $2, 'owner')")
+    .bind(org_id)
+    .bind(auth.id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "created", "org_id": org_id, "name": payload.name })))
+}
+
+pub async fn list_orgs(State(state): State<Arc<AppState>>, auth: AuthUser) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query(
+      r#"
+        SELECT o.id, o.name, o.created_at, om.role::text as role,
+               (SELECT COUNT(*) FROM repositories r WHERE r.org_id = o.id AND r.deleted_at IS NULL) as repo_count
+        FROM organizations o
+        JOIN organization_members om ON om.org_id = o.id
+        WHERE om.user_id = $1
+        ORDER BY o.name ASC
+        "#
+    )
+    .bind(auth.id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let orgs: Vec<Value> = rows
+    .iter()
+    .map(
+      |r|
+        json!({
+      "id": r.get::<Uuid, _>("id"),
+      "name": r.get::<String, _>("name"),
+      "role": r.get::<String, _>("role"),
+      "repo_count": r.get::<i64, _>("repo_count"),
+      "created_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+    })
+    )
+    .collect();
+
+  Ok(Json(json!(orgs)))
+}
+
+pub async fn get_org(State(state): State<Arc<AppState>>, auth: AuthUser, Path(org): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let (org_id, role) = require_member(&state.db, &org, auth.id).await?;
+
+  let row = sqlx
+    ::query(
+      r#"
+        SELECT o.created_at,
+               (SELECT COUNT(*) FROM organization_members om WHERE om.org_id = o.id) as member_count,
+               (SELECT COUNT(*) FROM repositories r WHERE r.org_id = o.id AND r.deleted_at IS NULL) as repo_count
+        FROM organizations o WHERE o.id = $1
+        "#
+    )
+    .bind(org_id)
+    .fetch_one(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(
+    Json(
+      json!({
+    "id": org_id,
+    "name": org,
+    "role": role,
+    "member_count": row.get::<i64, _>("member_count"),
+    "repo_count": row.get::<i64, _>("repo_count"),
+    "created_at": row.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+  })
+    )
+  )
+}
+
+pub async fn delete_org(State(state): State<Arc<AppState>>, auth: AuthUser, Path(org): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = require_owner(&state.db, &org, &auth).await?;
+
+  let has_repos = sqlx
+    ::query("SELECT 1 FROM repositories WHERE org_id = $1 LIMIT 1")
+    .bind(org_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .is_some();
+
+  // Repositories in the trash still belong to the organization until they are purged.
+  if has_repos {
+    return Err((StatusCode::CONFLICT, "Organization still owns repositories. Transfer or purge them first.".to_string()));
+  }
+
+  sqlx
+    ::query("DELETE FROM organizations WHERE id = $1")
+    .bind(org_id)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "deleted", "org": org })))
+}
+
+pub async fn list_org_members(State(state): State<Arc<AppState>>, auth: AuthUser, Path(org): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let (org_id, _) = require_member(&state.db, &org, auth.id).await?;
+
+  let rows = sqlx
+    ::query(
+      r#"
+        SELECT u.username, u.email, om.role::text as role, u.avatar_url
+        FROM organization_members om
+        JOIN users u ON om.user_id = u.id
+        WHERE om.org_id = $1
+        ORDER BY om.role ASC, u.username ASC
+        "#
+    )
+    .bind(org_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let members: Vec<Value> = rows
+    .iter()
+    .map(
+      |r|
+        json!({
+      "username": r.get::<String, _>("username"),
+      "email": r.get::<String, _>("email"),
+      "role": r.get::<String, _>("role"),
+      "avatar": r.get::<Option<String>, _>("avatar_url")
+    })
+    )
+    .collect();
+
+  Ok(Json(json!(members)))
+}
+
+pub async fn add_org_member(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path(org): Path<String>,
+  Json(payload): Json<AddOrgMemberRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = require_owner(&state.db, &org, &auth).await?;
+
+  let user_id: Uuid = sqlx
+    ::query("SELECT id FROM users WHERE email = $1")
+    .bind(&payload.email)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("id"))
+    .ok_or((StatusCode::NOT_FOUND, "User not found (must login once)".to_string()))?;
+
+  sqlx
+    ::query(
+      r#"
+        INSERT INTO organization_members (org_id, user_id, role)
+        VALUES ($1, $2, $3::org_role_enum)
+        ON CONFLICT (org_id, user_id) DO UPDATE SET role = EXCLUDED.role
+        "#
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(&payload.role)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "member_added", "user": payload.email, "role": payload.role })))
+}
+
+pub async fn remove_org_member(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, username)): Path<(String, String)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = require_owner(&state.db, &org, &auth).await?;
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let result = sqlx
+    ::query("DELETE FROM organization_members WHERE org_id = $1 AND user_id = (SELECT id FROM users WHERE username = $2)")
+    .bind(org_id)
+    .bind(&username)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if result.rows_affected() == 0 {
+    return Err((StatusCode::NOT_FOUND, "Member not found".to_string()));
+  }
+
+  // Team grants must not outlive the organization membership.
+  sqlx
+    ::query("DELETE FROM team_members WHERE team_id IN (SELECT id FROM teams WHERE org_id = $1) AND user_id = (SELECT id FROM users WHERE username = $2)")
+    .bind(org_id)
+    .bind(&username)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let owners_left = sqlx
+    ::query("SELECT 1 FROM organization_members WHERE org_id = $1 AND role = 'owner' LIMIT 1")
+    .bind(org_id)
+    .fetch_optional(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .is_some();
+
+  if !owners_left {
+    return Err((StatusCode::CONFLICT, "An organization must keep at least one owner".to_string()));
+  }
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "member_removed", "user": username })))
+}
+
+pub async fn list_org_repos(State(state): State<Arc<AppState>>, auth: Option<AuthUser>, Path(org): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let query = format!(
+    r#"
+      SELECT r.id, {full} as full_name, r.description, r.is_public, r.created_at
+      FROM repositories r
+      JOIN organizations o ON r.org_id = o.id
+      LEFT JOIN organization_members om ON om.org_id = o.id AND om.user_id = $2
+      LEFT JOIN repository_members rm ON rm.repo_id = r.id AND rm.user_id = $2
+      WHERE o.name = $1 AND r.deleted_at IS NULL AND (r.is_public = TRUE OR om.user_id IS NOT NULL OR rm.user_id IS NOT NULL)
+      ORDER BY r.name ASC
+      "#,
+    full = REPO_FULL_NAME_SQL
+  );
+
+  let rows = sqlx
+    ::query(&query)
+    .bind(&org)
+    .bind(auth.map(|a| a.id))
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let repos: Vec<Value> = rows
+    .iter()
+    .map(
+      |r|
+        json!({
+      "id": r.get::<Uuid, _>("id"),
+      "name": r.get::<String, _>("full_name"),
+      "description": r.get::<Option<String>, _>("description"),
+      "is_public": r.get::<Option<bool>, _>("is_public").unwrap_or(false),
+      "created_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+    })
+    )
+    .collect();
+
+  Ok(Json(json!(repos)))
+}
@@ -2,10 +2,10 @@ use axum::{
   extract::{ ws::{ WebSocket, WebSocketUpgrade, Message }, State, Query, Path, Multipart },
   response::IntoResponse,
   Json,
-  http::StatusCode,
+  http::{ HeaderMap, StatusCode },
 };
 use std::sync::Arc;
-use crate::state::AppState;
+use crate::{ state::AppState, auth::{ AuthUser, RepoReadGuard } };
 use futures::{ sink::SinkExt, stream::StreamExt };
 use serde::Deserialize;
 use serde_json::{ json, Value };
@@ -15,7 +15,6 @@ use sqlx::Row;
 
 #[derive(Deserialize)]
 pub struct RunnerConnectParams {
-  token: String,
   name: String,
 }
 
@@ -40,12 +39,21 @@ struct JobDef {
 pub async fn runner_ws_handler(
   ws: WebSocketUpgrade,
   Query(params): Query<RunnerConnectParams>,
+  headers: HeaderMap,
   State(state): State<Arc<AppState>>
 ) -> impl IntoResponse {
-  let row = sqlx::query("SELECT id FROM runners WHERE token = $1").bind(&params.token).fetch_optional(&state.db).await.unwrap_or(None);
+  let token = headers
+    .get("Authorization")
+    .and_then(|h| h.to_str().ok())
+    .and_then(|h| h.strip_prefix("Bearer "));
+
+  let runner = match token {
+    Some(t) => crate::admin::authenticate_runner(&state.db, t).await,
+    None => None,
+  };
 
-  let runner_id: Uuid = match row {
-    Some(r) => r.get("id"),
+  let runner_id: Uuid = match runner {
+    Some(id) => id,
     None => {
       tracing::warn!("🚫 Unauthorized runner attempt: {}", params.name);
       return axum::http::StatusCode::UNAUTHORIZED.into_response();
@@ -134,21 +142,20 @@ async fn handle_runner_message(state: &Arc<AppState>, text: &str) {
   }
 }
 
-pub async fn list_pipelines(State(state): State<Arc<AppState>>, Path(repo_name): Path<String>) -> Result<Json<Value>, String> {
+pub async fn list_pipelines(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, String> {
   let rows = sqlx
     ::query(
       r#"
     SELECT p.id, p.status::text, p.commit_id, p.created_at, p.finished_at,
         c.message as commit_message, c.author_name
     FROM pipelines p
-    JOIN repositories r ON p.repo_id = r.id
     JOIN commits c ON p.commit_id = c.id
-    WHERE r.name = $1
+    WHERE p.repo_id = $1
     ORDER BY p.created_at DESC
     LIMIT 20
   "#
     )
-    .bind(repo_name)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .map_err(|e| e.to_string())?;
 
@@ -173,16 +180,19 @@ pub async fn list_pipelines(State(state): State<Arc<AppState>>, Path(repo_name):
 
 pub async fn get_pipeline_details(
   State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
   Path((_repo_name, pipeline_id)): Path<(String, Uuid)>
 ) -> Result<Json<Value>, String> {
   let jobs_rows = sqlx
     ::query(
       r#"
-    SELECT id, name, stage, status::text, started_at, finished_at, exit_code, logs
-    FROM jobs WHERE pipeline_id = $1 ORDER BY started_at ASC
+    SELECT j.id, j.name, j.stage, j.status::text, j.started_at, j.finished_at, j.exit_code, j.logs
+    FROM jobs j JOIN pipelines p ON j.pipeline_id = p.id
+    WHERE j.pipeline_id = $1 AND p.repo_id = $2 ORDER BY j.started_at ASC
   "#
     )
     .bind(pipeline_id)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .map_err(|e| e.to_string())?;
 
@@ -215,7 +225,7 @@ fn calculate_duration(start: Option<chrono::DateTime<chrono::Utc>>, end: Option<
 
 pub async fn trigger_pipeline(state: Arc<AppState>, repo_id: Uuid, commit_id: Uuid) -> Result<(), String> {
   let repo_name: String = sqlx
-    ::query("SELECT name FROM repositories WHERE id = $1")
+    ::query(&format!("SELECT {} AS name FROM repositories r LEFT JOIN organizations o ON r.org_id = o.id WHERE r.id = $1", crate::auth::REPO_FULL_NAME_SQL))
     .bind(repo_id)
     .fetch_one(&state.db).await
     .map_err(|e| e.to_string())?
@@ -250,15 +260,29 @@ pub async fn trigger_pipeline(state: Arc<AppState>, repo_id: Uuid, commit_id: Uu
     .map_err(|e| e.to_string())?;
   let pipeline_id: Uuid = pipeline_row.get("id");
 
-  // TODO: upgrade to prod this
-  let system_token = crate::auth::create_system_token().map_err(|e| e.to_string())?;
+  // A runner scoped to an org or a set of repositories only receives their jobs; unscoped runners serve the whole instance.
+  let eligible: Vec<Uuid> = sqlx
+    ::query(
+      r#"
+      SELECT ru.id FROM runners ru
+      WHERE (ru.org_id IS NULL AND NOT EXISTS (SELECT 1 FROM runner_repos rr WHERE rr.runner_id = ru.id))
+         OR ru.org_id = (SELECT org_id FROM repositories WHERE id = $1)
+         OR EXISTS (SELECT 1 FROM runner_repos rr WHERE rr.runner_id = ru.id AND rr.repo_id = $1)
+      "#
+    )
+    .bind(repo_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|r| r.get("id"))
+    .collect();
 
   for job in config.pipeline.jobs {
-    let runner_entry = state.active_runners.iter().next();
+    let runner_entry = state.active_runners.iter().find(|r| eligible.contains(r.key()));
     let (runner_id, runner_tx) = match runner_entry {
       Some(r) => (*r.key(), r.value().clone()),
       None => {
-        tracing::warn!("⚠️ No runner");
+        tracing::warn!("⚠️ No runner available for {}", repo_name);
         continue;
       }
     };
@@ -277,6 +301,7 @@ pub async fn trigger_pipeline(state: Arc<AppState>, repo_id: Uuid, commit_id: Uu
       .map_err(|e| e.to_string())?;
 
     let job_id: Uuid = job_row.get("id");
+    let job_token = crate::auth::create_job_token(crate::auth::JobBinding { job_id, repo_id, commit_id }).map_err(|e| e.to_string())?;
 
     let payload =
       json!({
@@ -291,7 +316,7 @@ pub async fn trigger_pipeline(state: Arc<AppState>, repo_id: Uuid, commit_id: Uu
           "repo_name": repo_name,
           "commit_id": commit_id.to_string(),
           "api_url": "http://plectr-core:3000",
-          "auth_token": system_token,
+          "auth_token": job_token,
         }
       }
     });
@@ -346,9 +371,15 @@ async fn update_pipeline_status(state: &Arc<AppState>, pipeline_id: Uuid) {
 
 pub async fn upload_job_artifact(
   State(state): State<Arc<AppState>>,
+  auth: AuthUser,
   Path(job_id): Path<Uuid>,
   mut multipart: Multipart
 ) -> Result<Json<Value>, (StatusCode, String)> {
+  // Only the job's own token may attach artifacts to it.
+  if auth.job.as_ref().map(|j| j.job_id) != Some(job_id) {
+    return Err((StatusCode::FORBIDDEN, "Artifacts can only be uploaded with the job's token".to_string()));
+  }
+
   let _ = sqlx
     ::query("SELECT 1 FROM jobs WHERE id = $1")
     .bind(job_id)
@@ -381,7 +412,7 @@ pub async fn upload_job_artifact(
   Ok(Json(json!({ "status": "uploaded", "files": uploaded })))
 }
 
-pub async fn list_repo_releases(State(state): State<Arc<AppState>>, Path(repo_name): Path<String>) -> Result<Json<Value>, String> {
+pub async fn list_repo_releases(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, String> {
   let rows = sqlx
     ::query(
       r#"
@@ -392,13 +423,12 @@ pub async fn list_repo_releases(State(state): State<Arc<AppState>>, Path(repo_na
     JOIN jobs j ON ja.job_id = j.id
     JOIN pipelines p ON j.pipeline_id = p.id
     JOIN commits c ON p.commit_id = c.id
-    JOIN repositories r ON p.repo_id = r.id
-    WHERE r.name = $1 AND j.status = 'success'
+    WHERE p.repo_id = $1 AND j.status = 'success'
     ORDER BY ja.created_at DESC
     LIMIT 50
   "#
     )
-    .bind(&repo_name)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .map_err(|e| e.to_string())?;
 
@@ -413,7 +443,7 @@ pub async fn list_repo_releases(State(state): State<Arc<AppState>>, Path(repo_na
         "date": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at").to_rfc3339(),
         "commit_id": r.get::<Uuid, _>("commit_id"),
         "commit_msg": r.get::<String, _>("commit_message"),
-        "download_url": format!("/repos/{}/releases/{}/download", repo_name, r.get::<Uuid, _>("id")) 
+        "download_url": format!("/repos/{}/releases/{}/download", guard.repo_name, r.get::<Uuid, _>("id")) 
       })
     })
     .collect();
@@ -423,11 +453,15 @@ pub async fn list_repo_releases(State(state): State<Arc<AppState>>, Path(repo_na
 
 pub async fn download_artifact(
   State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
   Path((_repo_name, artifact_id)): Path<(String, Uuid)>
 ) -> impl IntoResponse {
   let row = sqlx
-    ::query("SELECT blob_hash, name, mime_type FROM job_artifacts WHERE id = $1")
+    ::query(
+      "SELECT ja.blob_hash, ja.name, ja.mime_type FROM job_artifacts ja JOIN jobs j ON ja.job_id = j.id JOIN pipelines p ON j.pipeline_id = p.id WHERE ja.id = $1 AND p.repo_id = $2"
+    )
     .bind(artifact_id)
+    .bind(guard.repo_id)
     .fetch_optional(&state.db).await
     .unwrap_or(None);
 
@@ -0,0 +1,191 @@
+use axum::{
+  extract::{ Request, State },
+  http::{ header, HeaderMap, Method, StatusCode },
+  middleware::Next,
+  response::{ IntoResponse, Response },
+};
+use base64::{ Engine as _, engine::general_purpose };
+use dashmap::DashMap;
+use std::{ sync::Arc, time::{ Duration, Instant } };
+use crate::{ state::AppState, audit::ClientIp };
+
+/// Request budgets. Each class has its own bucket per caller, so a large push does not eat the browsing budget.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Class {
+  Read,
+  /// Unauthenticated name availability checks (`/api/check/*`), kept low against enumeration.
+  Check,
+  Upload,
+  Push,
+}
+
+impl Class {
+  fn env_name(&self) -> &'static str {
+    match self {
+      Class::Read => "READ",
+      Class::Check => "CHECK",
+      Class::Upload => "UPLOAD",
+      Class::Push => "PUSH",
+    }
+  }
+
+  /// (requests per minute, burst)
+  fn defaults(&self) -> (f64, f64) {
+    match self {
+      Class::Read => (600.0, 120.0),
+      Class::Check => (30.0, 10.0),
+      Class::Upload => (300.0, 100.0),
+      Class::Push => (600.0, 200.0),
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+struct Budget {
+  per_sec: f64,
+  burst: f64,
+}
+
+struct Bucket {
+  tokens: f64,
+  updated_at: Instant,
+}
+
+/// In-memory token buckets keyed by caller (job, deploy key, user or IP) and request class.
+pub struct RateLimiter {
+  enabled: bool,
+  budgets: Vec<(Class, Budget)>,
+  buckets: DashMap<(Class, String), Bucket>,
+}
+
+impl RateLimiter {
+  /// `RATE_LIMIT_<CLASS>_PER_MIN` and `RATE_LIMIT_<CLASS>_BURST` override the defaults
+  /// (classes: READ, CHECK, UPLOAD, PUSH). `RATE_LIMIT_ENABLED=false` turns limiting off.
+  pub fn from_env() -> Self {
+    let enabled = std::env::var("RATE_LIMIT_ENABLED").map(|v| v != "false" && v != "0").unwrap_or(true);
+    let budgets = [Class::Read, Class::Check, Class::Upload, Class::Push]
+      .into_iter()
+      .map(|class| {
+        let (per_min, burst) = class.defaults();
+        let read = |suffix: &str, default: f64| {
+          std::env
+            ::var(format!("RATE_LIMIT_{}_{}", class.env_name(), suffix))
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| *v > 0.0)
+            .unwrap_or(default)
+        };
+        (class, Budget { per_sec: read("PER_MIN", per_min) / 60.0, burst: read("BURST", burst) })
+      })
+      .collect();
+
+    Self { enabled, budgets, buckets: DashMap::new() }
+  }
+
+  fn budget(&self, class: Class) -> Budget {
+    self.budgets
+      .iter()
+      .find(|(c, _)| *c == class)
+      .map(|(_, b)| *b)
+      .unwrap_or(Budget { per_sec: 10.0, burst: 10.0 })
+  }
+
+  /// Takes one token from the caller's bucket. On refusal, returns how long until a token is available.
+  pub fn check(&self, class: Class, key: &str) -> Result<(), Duration> {
+    if !self.enabled {
+      return Ok(());
+    }
+    let budget = self.budget(class);
+    let now = Instant::now();
+
+    let mut bucket = self.buckets
+      .entry((class, key.to_string()))
+      .or_insert_with(|| Bucket { tokens: budget.burst, updated_at: now });
+
+    let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * budget.per_sec).min(budget.burst);
+    bucket.updated_at = now;
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      Ok(())
+    } else {
+      Err(Duration::from_secs_f64((1.0 - bucket.tokens) / budget.per_sec))
+    }
+  }
+
+  /// Drops buckets that have refilled completely: they carry no state worth keeping.
+  pub fn prune(&self) {
+    let now = Instant::now();
+    self.buckets.retain(|(class, _), bucket| {
+      let budget = self.budget(*class);
+      let refill = Duration::from_secs_f64(budget.burst / budget.per_sec);
+      now.duration_since(bucket.updated_at) < refill
+    });
+  }
+}
+
+fn classify(method: &Method, path: &str) -> Class {
+  let is_read = method == Method::GET || method == Method::HEAD || method == Method::OPTIONS;
+  if path.starts_with("/api/check/") {
+    Class::Check
+  } else if path.starts_with("/v2/") && !is_read {
+    Class::Push
+  } else if path.ends_with("/upload") || path.ends_with("/artifacts") {
+    Class::Upload
+  } else {
+    Class::Read
+  }
+}
+
+/// Credential sent by the caller, as a Bearer token or as the password of Docker's Basic auth.
+fn credential(headers: &HeaderMap) -> Option<String> {
+  let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+  if let Some(token) = value.strip_prefix("Bearer ") {
+    return Some(token.to_string());
+  }
+  let decoded = general_purpose::STANDARD.decode(value.strip_prefix("Basic ")?).ok()?;
+  String::from_utf8(decoded).ok()?.split_once(':').map(|(_, password)| password.to_string())
+}
+
+/// Callers are throttled per job for CI tokens, per key for deploy keys and per user for everything else (sessions,
+/// personal access tokens, registry tokens), so that holding several tokens does not multiply the budget. Anonymous
+/// callers and credentials that do not verify share the bucket of their IP: a made-up token never opens a new one.
+pub(crate) async fn caller_key(state: &AppState, headers: &HeaderMap, ip: &ClientIp) -> String {
+  if let Some(token) = credential(headers) {
+    // Registry tokens carry the identity they were issued for; anonymous ones are throttled per IP.
+    let user = match crate::registry::verify_registry_token(state, &token).await {
+      Some(result) => result.ok().and_then(|(user, _)| user),
+      None => crate::auth::verify_token(state, &token).await.ok(),
+    };
+    if let Some(user) = user {
+      return match (&user.deploy_key, &user.job) {
+        (Some(key), _) => format!("deploy:{}", key.key_id),
+        (None, Some(job)) => format!("job:{}", job.job_id),
+        (None, None) => format!("user:{}", user.id),
+      };
+    }
+  }
+  format!("ip:{}", ip.0.as_deref().unwrap_or("unknown"))
+}
+
+pub async fn rate_limit(State(state): State<Arc<AppState>>, ip: ClientIp, req: Request, next: Next) -> Response {
+  // Runners authenticate their long-lived WebSocket separately.
+  if req.uri().path() == "/api/runner/ws" {
+    return next.run(req).await;
+  }
+
+  let class = classify(req.method(), req.uri().path());
+  let key = match req.extensions().get::<crate::ssh::SshSession>() {
+    Some(session) => format!("ssh:{}", session.0.id),
+    None => caller_key(&state, req.headers(), &ip).await,
+  };
+
+  if let Err(wait) = state.rate_limiter.check(class, &key) {
+    let retry_after = wait.as_secs().max(1);
+    tracing::warn!("🚦 Rate limited {} ({:?}), retry in {}s", key, class, retry_after);
+    return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())], "Too many requests").into_response();
+  }
+
+  next.run(req).await
+}
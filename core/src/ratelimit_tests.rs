@@ -0,0 +1,53 @@
+//! Rate limit keys. Credentials that fail verification must not buy their own bucket.
+
+use axum::http::{ header, HeaderMap, HeaderValue };
+use base64::{ Engine as _, engine::general_purpose };
+use dashmap::DashMap;
+use s3::{ bucket::Bucket, creds::Credentials, region::Region };
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::{ audit::ClientIp, oidc::OidcVerifier, ratelimit::{ caller_key, RateLimiter }, state::AppState };
+
+/// Nothing listens behind the database or the identity provider: every lookup fails, as it would for a forged token.
+fn state() -> AppState {
+  let region = Region::Custom { region: "us-east-1".to_owned(), endpoint: "http://127.0.0.1:9".to_owned() };
+  let credentials = Credentials::new(Some("any"), Some("any"), None, None, None).unwrap();
+  AppState {
+    db: PgPoolOptions::new().acquire_timeout(Duration::from_secs(1)).connect_lazy("postgres://nobody@127.0.0.1:9/none").unwrap(),
+    bucket: Bucket::new("plectr-test", region, credentials).unwrap().with_path_style(),
+    active_runners: DashMap::new(),
+    oidc: std::sync::Arc::new(OidcVerifier::new("http://127.0.0.1:9/realms/test".to_string(), None, "http://127.0.0.1:9/certs".to_string())),
+    rate_limiter: std::sync::Arc::new(RateLimiter::from_env()),
+  }
+}
+
+fn bearer(token: &str) -> HeaderMap {
+  let mut headers = HeaderMap::new();
+  headers.insert(header::AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token)).unwrap());
+  headers
+}
+
+#[tokio::test]
+async fn unverified_credentials_share_the_ip_bucket() {
+  let state = state();
+  let ip = ClientIp(Some("203.0.113.7".to_string()));
+  let jwt = |header: &str| format!("{}.e30.c2ln", general_purpose::URL_SAFE_NO_PAD.encode(header));
+
+  let mut forged = vec![
+    Uuid::new_v4().to_string(),
+    format!("{}{}", crate::token::TOKEN_PREFIX, Uuid::new_v4().simple()),
+    format!("{}{}", crate::deploy::DEPLOY_KEY_PREFIX, Uuid::new_v4().simple()),
+    // Shaped like a provider token, and like a job or registry token.
+    jwt(r#"{"alg":"RS256","kid":"forged"}"#),
+    jwt(r#"{"alg":"HS256","typ":"JWT"}"#),
+  ];
+  for _ in 0..5 {
+    forged.push(Uuid::new_v4().simple().to_string());
+  }
+
+  for token in &forged {
+    assert_eq!(caller_key(&state, &bearer(token), &ip).await, "ip:203.0.113.7", "{} got its own bucket", token);
+  }
+  assert_eq!(caller_key(&state, &HeaderMap::new(), &ip).await, "ip:203.0.113.7");
+}
@@ -1,11 +1,15 @@
-use axum::{ body::Bytes, extract::{ Path, Query, State }, http::{ header, StatusCode, HeaderMap }, response::IntoResponse, Json };
-use serde::Deserialize;
+use axum::{ body::{ Body, Bytes }, extract::{ Path, Query, State }, http::{ header, StatusCode, HeaderMap }, response::IntoResponse, Json };
+use serde::{ Deserialize, Serialize };
 use serde_json::{ json, Value };
 use std::sync::Arc;
 use uuid::Uuid;
 use sha2::{ Sha256, Digest };
-use crate::state::AppState;
+use crate::{ state::AppState, auth::{ AuthUser, RepoAccess, RepoPerm, RepoReadGuard }, audit::{ self, ClientIp } };
+use once_cell::sync::Lazy;
 use futures::StreamExt;
+use std::{ pin::Pin, task::{ Context, Poll } };
+use tokio::io::{ AsyncRead, AsyncReadExt, ReadBuf };
+use tokio_util::io::{ ReaderStream, StreamReader };
 use sqlx::Row;
 use base64::{ Engine as _, engine::general_purpose };
 
@@ -20,353 +24,1691 @@ fn docker_headers() -> HeaderMap {
   h
 }
 
-async fn check_docker_access(
-  state: &Arc<AppState>,
-  full_image_name: &str,
-  headers: &HeaderMap,
-  require_write: bool
-) -> Result<(), (StatusCode, HeaderMap, String)> {
-  let parts: Vec<&str> = full_image_name.splitn(2, '/').collect();
-  let plectr_repo_name = parts[0];
-
-  let user_info = if let Some(auth_val) = headers.get("Authorization") {
-    let auth_str = auth_val.to_str().unwrap_or("");
-    let token_opt = if auth_str.starts_with("Basic ") {
-      let payload = auth_str.strip_prefix("Basic ").unwrap();
-      if let Ok(decoded) = general_purpose::STANDARD.decode(payload) {
-        let creds = String::from_utf8(decoded).unwrap_or_default();
-        creds
-          .splitn(2, ':')
-          .nth(1)
-          .map(|s| s.to_string())
-      } else {
-        None
-      }
-    } else if auth_str.starts_with("Bearer ") {
-      auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
+/// Registry operations, each granted separately from code access.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RegistryAction {
+  Pull,
+  Push,
+  Delete,
+}
+
+impl RegistryAction {
+  fn as_str(&self) -> &'static str {
+    match self {
+      RegistryAction::Pull => "pull",
+      RegistryAction::Push => "push",
+      RegistryAction::Delete => "delete",
+    }
+  }
+}
+
+/// Values accepted in `repository_members.registry_perms`.
+pub const REGISTRY_PERMS: [&str; 3] = ["pull", "push", "delete"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AutoCreate {
+  Off,
+  Namespace,
+  On,
+}
+
+/// `REGISTRY_AUTO_CREATE`: whether pushing an unknown image creates its repository. `off` never,
+/// `namespace` (default) only under the pusher's username or an organization they own, `on` anywhere.
+static REGISTRY_AUTO_CREATE: Lazy<AutoCreate> = Lazy::new(|| {
+  match std::env::var("REGISTRY_AUTO_CREATE").as_deref() {
+    Ok("off") => AutoCreate::Off,
+    Ok("on") => AutoCreate::On,
+    Ok("namespace") | Err(_) => AutoCreate::Namespace,
+    Ok(other) => {
+      tracing::warn!("⚠️ Unknown REGISTRY_AUTO_CREATE '{}', using 'namespace'.", other);
+      AutoCreate::Namespace
+    }
+  }
+});
+
+/// Explicit member permissions win; otherwise viewers pull, editors also push, admins also delete.
+fn registry_allows(access: &RepoAccess, action: RegistryAction) -> bool {
+  if let Some(perms) = &access.registry_perms {
+    return perms.iter().any(|p| p == action.as_str());
+  }
+  let required = match action {
+    RegistryAction::Pull => RepoPerm::Read,
+    RegistryAction::Push => RepoPerm::Write,
+    RegistryAction::Delete => RepoPerm::Admin,
+  };
+  access.perm >= required
+}
+
+/// Registry tokens are HS256-signed with `REGISTRY_TOKEN_SECRET` and tagged with this `kid`,
+/// so they are never taken for CI job tokens.
+const REGISTRY_TOKEN_KID: &str = "registry";
+/// `service` of the token challenge, and `aud` of the tokens issued for it.
+const REGISTRY_SERVICE: &str = "plectr-registry";
+/// Registry tokens are short-lived: they carry a snapshot of the credentials that obtained them.
+/// Personal tokens, deploy keys and job tokens are re-checked on every use, so revoking one also
+/// revokes the registry tokens it minted; an OIDC session keeps its token for at most this long, since a registry
+/// token is never accepted in exchange for a new one.
+const REGISTRY_TOKEN_TTL_SECS: i64 = 300;
+
+static REGISTRY_TOKEN_SECRET: Lazy<Vec<u8>> = Lazy::new(|| {
+  match std::env::var("REGISTRY_TOKEN_SECRET") {
+    Ok(secret) => secret.into_bytes(),
+    Err(_) => {
+      tracing::warn!("⚠️ REGISTRY_TOKEN_SECRET not set, using an ephemeral secret (registry tokens are invalidated on restart).");
+      let mut secret = vec![0u8; 32];
+      rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut secret);
+      secret
+    }
+  }
+});
+
+/// Token endpoint announced in challenges, under the public base URL of the instance (`PUBLIC_URL`). Never derived
+/// from the request's `Host`: a client could otherwise point `docker login` elsewhere. Forced at startup.
+pub static REGISTRY_TOKEN_REALM: Lazy<String> = Lazy::new(|| {
+  let base = std::env::var("PUBLIC_URL").expect("PUBLIC_URL must be set");
+  format!("{}/v2/token", base.trim_end_matches('/'))
+});
+
+/// One `type:name:actions` entry of a registry token, e.g. `repository:org/app:pull,push`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TokenAccess {
+  #[serde(rename = "type")]
+  kind: String,
+  name: String,
+  actions: Vec<String>,
+}
+
+impl TokenAccess {
+  fn parse(scope: &str) -> Option<Self> {
+    let (kind, rest) = scope.split_once(':')?;
+    let (name, actions) = rest.rsplit_once(':')?;
+    Some(TokenAccess { kind: kind.to_string(), name: name.to_string(), actions: actions.split(',').map(String::from).collect() })
+  }
+}
+
+/// The credentials a registry token was issued for, restored on every request.
+#[derive(Serialize, Deserialize)]
+struct TokenIdentity {
+  id: Uuid,
+  username: String,
+  email: String,
+  scopes: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  job: Option<crate::auth::JobBinding>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  deploy_key: Option<(Uuid, Uuid)>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  robot_repo: Option<Uuid>,
+  /// Personal access token the registry token was obtained with.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  token_id: Option<Uuid>,
+}
+
+impl TokenIdentity {
+  /// Whether the credential behind the identity was revoked (or its job finished) since the token was issued.
+  async fn revoked(&self, state: &AppState) -> Result<bool, sqlx::Error> {
+    let active = if let Some(job) = &self.job {
+      sqlx
+        ::query(
+          "SELECT 1 FROM jobs j JOIN pipelines p ON j.pipeline_id = p.id WHERE j.id = $1 AND p.repo_id = $2 AND p.commit_id = $3 AND j.status IN ('pending', 'running')"
+        )
+        .bind(job.job_id)
+        .bind(job.repo_id)
+        .bind(job.commit_id)
+        .fetch_optional(&state.db).await?
+    } else if let Some((key_id, _)) = self.deploy_key {
+      sqlx::query("SELECT 1 FROM deploy_keys WHERE id = $1").bind(key_id).fetch_optional(&state.db).await?
+    } else if let Some(token_id) = self.token_id {
+      sqlx
+        ::query("SELECT 1 FROM personal_access_tokens WHERE id = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())")
+        .bind(token_id)
+        .fetch_optional(&state.db).await?
     } else {
-      None
+      return Ok(false);
     };
+    Ok(active.is_none())
+  }
+}
 
-    if let Some(token) = token_opt {
-      let parts: Vec<&str> = token.split('.').collect();
-      if parts.len() == 3 {
-        if let Ok(claims_bytes) = general_purpose::URL_SAFE_NO_PAD.decode(parts[1]) {
-          if let Ok(claims) = serde_json::from_slice::<crate::auth::Claims>(&claims_bytes) {
-            if let Ok(uid) = Uuid::parse_str(&claims.sub) {
-              Some((uid, claims.preferred_username.unwrap_or("docker".to_string()), claims.email.unwrap_or_default()))
-            } else {
-              tracing::warn!("🐳 Docker Auth: Invalid UUID in token");
-              None
-            }
-          } else {
-            tracing::warn!("🐳 Docker Auth: Invalid Claims");
-            None
-          }
-        } else {
-          None
-        }
-      } else {
-        None
+impl From<&AuthUser> for TokenIdentity {
+  fn from(u: &AuthUser) -> Self {
+    TokenIdentity {
+      id: u.id,
+      username: u.username.clone(),
+      email: u.email.clone(),
+      scopes: u.scopes.clone(),
+      job: u.job.clone(),
+      deploy_key: u.deploy_key.as_ref().map(|k| (k.key_id, k.repo_id)),
+      robot_repo: u.robot_repo,
+      token_id: None,
+    }
+  }
+}
+
+impl From<TokenIdentity> for AuthUser {
+  fn from(t: TokenIdentity) -> Self {
+    AuthUser {
+      id: t.id,
+      username: t.username,
+      email: t.email,
+      scopes: t.scopes,
+      job: t.job,
+      deploy_key: t.deploy_key.map(|(key_id, repo_id)| crate::auth::DeployKeyBinding { key_id, repo_id }),
+      robot_repo: t.robot_repo,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegistryClaims {
+  iss: String,
+  sub: String,
+  aud: String,
+  exp: usize,
+  iat: usize,
+  access: Vec<TokenAccess>,
+  /// Absent for anonymous tokens, which only ever grant pulls of public images.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  user: Option<TokenIdentity>,
+}
+
+/// Identity (none for anonymous tokens) and grants carried by a registry token.
+pub type RegistryGrant = (Option<AuthUser>, Vec<TokenAccess>);
+
+/// Whether `token` is a registry token (it may still fail to verify).
+fn is_registry_token(token: &str) -> bool {
+  jsonwebtoken
+    ::decode_header(token)
+    .is_ok_and(|h| h.alg == jsonwebtoken::Algorithm::HS256 && h.kid.as_deref() == Some(REGISTRY_TOKEN_KID))
+}
+
+/// Decodes a registry token. `None` when the token is not one; `Some(Err)` when it is but does not verify,
+/// or when the credential it was issued for has been revoked since.
+pub async fn verify_registry_token(state: &AppState, token: &str) -> Option<Result<RegistryGrant, String>> {
+  if !is_registry_token(token) {
+    return None;
+  }
+  let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+  validation.set_audience(&[REGISTRY_SERVICE]);
+  let key = jsonwebtoken::DecodingKey::from_secret(&REGISTRY_TOKEN_SECRET);
+  let claims = match jsonwebtoken::decode::<RegistryClaims>(token, &key, &validation) {
+    Ok(data) => data.claims,
+    Err(e) => {
+      return Some(Err(format!("Invalid registry token: {}", e)));
+    }
+  };
+  if let Some(identity) = &claims.user {
+    match identity.revoked(state).await {
+      Ok(false) => {}
+      Ok(true) => {
+        return Some(Err("Registry token credentials were revoked".to_string()));
+      }
+      Err(e) => {
+        return Some(Err(e.to_string()));
       }
-    } else {
-      None
     }
-  } else {
-    None
+  }
+  Some(Ok((claims.user.map(AuthUser::from), claims.access)))
+}
+
+/// Who is calling the registry. With a registry token, `grants` limits what the call may do on top of
+/// the caller's own permissions, which are still checked on every request.
+#[derive(Clone)]
+struct DockerCaller {
+  user: Option<AuthUser>,
+  grants: Option<Vec<TokenAccess>>,
+  /// Token endpoint advertised in challenges.
+  realm: String,
+}
+
+impl DockerCaller {
+  fn is_authenticated(&self) -> bool {
+    self.user.is_some() || self.grants.is_some()
+  }
+
+  fn granted(&self, kind: &str, name: &str, action: &str) -> bool {
+    match &self.grants {
+      None => true,
+      Some(grants) => grants.iter().any(|g| g.kind == kind && g.name == name && g.actions.iter().any(|a| a == action || a == "*")),
+    }
+  }
+}
+
+/// Docker's token challenge: the client fetches a token for `scope` from the realm and retries.
+fn unauthorized(caller: &DockerCaller, scope: Option<&str>, message: &str) -> (StatusCode, HeaderMap, String) {
+  let mut challenge = format!("Bearer realm=\"{}\",service=\"{}\"", caller.realm, REGISTRY_SERVICE);
+  if let Some(scope) = scope {
+    challenge.push_str(&format!(",scope=\"{}\"", scope));
+  }
+  if caller.is_authenticated() {
+    challenge.push_str(",error=\"insufficient_scope\"");
+  }
+  let mut h = docker_headers();
+  h.insert(header::WWW_AUTHENTICATE, challenge.parse().unwrap());
+  (StatusCode::UNAUTHORIZED, h, message.to_string())
+}
+
+/// Outcome of a successful registry authorization.
+struct DockerAccess {
+  /// Canonical image name (current repository name, redirects resolved).
+  name: String,
+  repo_id: Uuid,
+  user: Option<AuthUser>,
+}
+
+/// Caller identified by the registry credentials: a registry token, or a personal token, deploy key
+/// or OIDC token sent as Docker's Basic password or as a Bearer.
+async fn docker_caller(state: &Arc<AppState>, headers: &HeaderMap) -> DockerCaller {
+  let anonymous = DockerCaller { user: None, grants: None, realm: REGISTRY_TOKEN_REALM.clone() };
+
+  let Some(token) = docker_credential(headers) else {
+    return anonymous;
   };
 
-  let user_id = user_info.as_ref().map(|u| u.0);
+  if let Some(result) = verify_registry_token(state, &token).await {
+    return match result {
+      Ok((user, grants)) => DockerCaller { user, grants: Some(grants), ..anonymous },
+      Err(e) => {
+        tracing::warn!("🐳 Docker Auth: {}", e);
+        anonymous
+      }
+    };
+  }
+
+  match crate::auth::verify_token(state, &token).await {
+    Ok(user) => DockerCaller { user: Some(user), ..anonymous },
+    Err(e) => {
+      tracing::warn!("🐳 Docker Auth: {}", e);
+      anonymous
+    }
+  }
+}
 
-  let row = sqlx
-    ::query(
-      r#"
-    SELECT r.id, r.is_public, rm.role::text 
-    FROM repositories r
-    LEFT JOIN repository_members rm ON r.id = rm.repo_id AND rm.user_id = $2
-    WHERE r.name = $1
-    "#
-    )
-    .bind(plectr_repo_name)
-    .bind(user_id)
-    .fetch_optional(&state.db).await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()))?;
+/// Token sent as Docker's Basic password or as a Bearer.
+fn docker_credential(headers: &HeaderMap) -> Option<String> {
+  let auth_str = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+  if let Some(token) = auth_str.strip_prefix("Bearer ") {
+    return Some(token.to_string());
+  }
+  let decoded = general_purpose::STANDARD.decode(auth_str.strip_prefix("Basic ")?).ok()?;
+  String::from_utf8(decoded).ok()?.split_once(':').map(|(_, password)| password.to_string())
+}
 
-  match row {
-    Some(r) => {
-      let is_public: bool = r.get("is_public");
-      let role: Option<String> = r.get("role");
+async fn check_docker_access(
+  state: &Arc<AppState>,
+  full_image_name: &str,
+  headers: &HeaderMap,
+  action: RegistryAction
+) -> Result<DockerAccess, (StatusCode, HeaderMap, String)> {
+  let caller = docker_caller(state, headers).await;
+  authorize_docker(state, full_image_name, caller, action).await
+}
 
-      if require_write {
-        if user_id.is_none() {
-          let mut h = docker_headers();
-          h.insert("Www-Authenticate", "Basic realm=\"Registry Realm\"".parse().unwrap());
-          return Err((StatusCode::UNAUTHORIZED, h, "Authentication required".to_string()));
-        }
+async fn authorize_docker(
+  state: &Arc<AppState>,
+  full_image_name: &str,
+  caller: DockerCaller,
+  action: RegistryAction
+) -> Result<DockerAccess, (StatusCode, HeaderMap, String)> {
+  let scope = format!("repository:{}:{}", full_image_name, action.as_str());
+  if !caller.granted("repository", full_image_name, action.as_str()) {
+    return Err(unauthorized(&caller, Some(&scope), "Registry token does not grant this action"));
+  }
+  let user_info = caller.user.clone();
+  let user_id = user_info.as_ref().map(|u| u.id);
 
-        if role.is_some() && role.as_deref() != Some("viewer") {
-          return Ok(());
-        }
-        return Err((StatusCode::FORBIDDEN, docker_headers(), "Write access denied".to_string()));
-      } else {
-        if is_public || role.is_some() {
-          return Ok(());
+  // `org/repo/...` images live in an organization repository, `repo/...` in a personal one. Organizations
+  // and personal repositories cannot share a name, so at most one of the two lookups matches.
+  let segments: Vec<&str> = full_image_name.splitn(3, '/').collect();
+  let mut resolved = None;
+  if segments.len() >= 2 {
+    let org_repo = format!("{}/{}", segments[0], segments[1]);
+    if
+      let Some(access) = crate::auth
+        ::repo_access(&state.db, &org_repo, user_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()))?
+    {
+      resolved = Some((access, segments.get(2).copied()));
+    }
+  }
+  if resolved.is_none() {
+    let access = crate::auth
+      ::repo_access(&state.db, segments[0], user_id).await
+      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()))?;
+    resolved = access.map(|a| (a, full_image_name.split_once('/').map(|(_, rest)| rest)));
+  }
+
+  match resolved {
+    Some((mut access, image_path)) => {
+      if let Some(user) = &user_info {
+        user.confine(&mut access);
+      }
+
+      // Old repository names resolve through redirects; handlers always work on the current one.
+      let canonical_name = match image_path {
+        Some(rest) => format!("{}/{}", access.repo_name, rest),
+        None => access.repo_name.clone(),
+      };
+
+      if action == RegistryAction::Pull {
+        let token_can_pull = user_info
+          .as_ref()
+          .map(|u| u.has_scope("repo:read") || u.has_scope("registry:push"))
+          .unwrap_or(false);
+        if access.is_public || (registry_allows(&access, action) && token_can_pull) {
+          return Ok(DockerAccess { name: canonical_name, repo_id: access.repo_id, user: user_info });
         }
 
         if user_id.is_none() {
-          let mut h = docker_headers();
-          h.insert("Www-Authenticate", "Basic realm=\"Registry Realm\"".parse().unwrap());
-          return Err((StatusCode::UNAUTHORIZED, h, "Authentication required".to_string()));
+          return Err(unauthorized(&caller, Some(&scope), "Authentication required"));
         }
         return Err((StatusCode::FORBIDDEN, docker_headers(), "Read access denied".to_string()));
       }
+
+      if user_id.is_none() {
+        return Err(unauthorized(&caller, Some(&scope), "Authentication required"));
+      }
+
+      // Personal access tokens need the `registry:push` scope on top of the registry permission.
+      if registry_allows(&access, action) && user_info.as_ref().map(|u| u.has_scope("registry:push")).unwrap_or(false) {
+        return Ok(DockerAccess { name: canonical_name, repo_id: access.repo_id, user: user_info });
+      }
+      Err((StatusCode::FORBIDDEN, docker_headers(), format!("Registry '{}' permission denied", action.as_str())))
     }
     None => {
-      if !require_write {
+      if action != RegistryAction::Push {
         return Err((StatusCode::NOT_FOUND, docker_headers(), "Repository not found".to_string()));
       }
 
-      if let Some((uid, username, email)) = user_info {
-        let _ = sqlx
-          ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO NOTHING")
-          .bind(uid)
-          .bind(&username)
-          .bind(&email)
-          .execute(&state.db).await;
-
-        let repo_uuid = Uuid::new_v4();
-        let create_res = sqlx
-          ::query("INSERT INTO repositories (id, name, description, is_public) VALUES ($1, $2, 'Auto-created via Docker Push', FALSE)")
-          .bind(repo_uuid)
-          .bind(plectr_repo_name)
-          .execute(&state.db).await;
-
-        if create_res.is_err() {
+      let Some(user) = user_info else {
+        return Err(unauthorized(&caller, Some(&scope), "Authentication required to create repository"));
+      };
+      if !user.has_scope("registry:push") {
+        return Err((StatusCode::FORBIDDEN, docker_headers(), "Token lacks the 'registry:push' scope".to_string()));
+      }
+      if user.confined_repo().is_some() {
+        return Err((StatusCode::FORBIDDEN, docker_headers(), "These credentials cannot create repositories".to_string()));
+      }
+      if *REGISTRY_AUTO_CREATE == AutoCreate::Off {
+        return Err((StatusCode::NOT_FOUND, docker_headers(), "Repository not found: create it before pushing".to_string()));
+      }
+      let uid = user.id;
+
+      let _ = sqlx
+        ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO NOTHING")
+        .bind(uid)
+        .bind(&user.username)
+        .bind(&user.email)
+        .execute(&state.db).await;
+
+      // Pushing to `org/repo` creates the repository inside the organization when the caller belongs to it.
+      let mut org_id: Option<Uuid> = None;
+      let mut plectr_repo_name = segments[0];
+      if segments.len() >= 2 {
+        if let Ok((id, Some(role))) = crate::org::membership(&state.db, segments[0], uid).await {
+          if *REGISTRY_AUTO_CREATE == AutoCreate::Namespace && role != "owner" {
+            return Err((StatusCode::FORBIDDEN, docker_headers(), "Only organization owners can create repositories by pushing".to_string()));
+          }
+          org_id = Some(id);
+          plectr_repo_name = segments[1];
+        }
+      }
+
+      // Outside organizations, the pusher's own name is the only namespace they own.
+      if org_id.is_none() && *REGISTRY_AUTO_CREATE == AutoCreate::Namespace && plectr_repo_name != user.username {
+        return Err((
+          StatusCode::FORBIDDEN,
+          docker_headers(),
+          format!("Pushing can only create repositories under your namespace ({}/...)", user.username),
+        ));
+      }
+
+      if !crate::validation::is_valid_name(plectr_repo_name) {
+        return Err((StatusCode::BAD_REQUEST, docker_headers(), "Invalid repository name".to_string()));
+      }
+
+      let repo_uuid = Uuid::new_v4();
+      let create_res = sqlx
+        ::query("INSERT INTO repositories (id, name, description, is_public, org_id) VALUES ($1, $2, 'Auto-created via Docker Push', FALSE, $3)")
+        .bind(repo_uuid)
+        .bind(plectr_repo_name)
+        .bind(org_id)
+        .execute(&state.db).await;
+
+      match create_res {
+        Ok(_) => {}
+        // Taken by a repository created concurrently, or by an organization the pusher does not belong to.
+        Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+          return Err((StatusCode::FORBIDDEN, docker_headers(), format!("Repository name '{}' is not available", plectr_repo_name)));
+        }
+        Err(_) => {
           return Err((StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), "Failed to auto-create repo".to_string()));
         }
+      }
 
-        let _ = sqlx
-          ::query("INSERT INTO repository_members (repo_id, user_id, role) VALUES ($1, $2, 'admin')")
-          .bind(repo_uuid)
-          .bind(uid)
-          .execute(&state.db).await;
+      let _ = sqlx
+        ::query("INSERT INTO repository_members (repo_id, user_id, role) VALUES ($1, $2, 'admin')")
+        .bind(repo_uuid)
+        .bind(uid)
+        .execute(&state.db).await;
+
+      tracing::info!("🐳 Auto-created Docker repository: {}", plectr_repo_name);
+      Ok(DockerAccess { name: full_image_name.to_string(), repo_id: repo_uuid, user: Some(user) })
+    }
+  }
+}
+
+/// Version check, also how clients discover authentication: anonymous calls get the token challenge.
+pub async fn v2_base_check(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+  let caller = docker_caller(&state, &headers).await;
+  if !caller.is_authenticated() {
+    return unauthorized(&caller, None, "Authentication required").into_response();
+  }
+  (StatusCode::OK, docker_headers(), Json(json!({}))).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct TokenQuery {
+  #[serde(default)]
+  scope: Vec<String>,
+}
 
-        tracing::info!("🐳 Auto-created Docker repository: {}", plectr_repo_name);
-        return Ok(());
+/// Docker token endpoint. Takes a personal token, deploy key or OIDC token (usually as the Basic
+/// password of `docker login`), or nothing for anonymous pulls, and issues a short-lived token
+/// limited to the requested scopes the caller actually holds.
+pub async fn issue_token(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  axum_extra::extract::Query(query): axum_extra::extract::Query<TokenQuery>
+) -> impl IntoResponse {
+  let caller = docker_caller(&state, &headers).await;
+  // Bad credentials must fail `docker login` rather than fall back to an anonymous token.
+  if docker_credential(&headers).is_some() && caller.user.is_none() {
+    return unauthorized(&caller, None, "Invalid credentials").into_response();
+  }
+  // A registry token cannot mint other tokens: it would renew itself past its expiry and widen its own scopes.
+  if caller.grants.is_some() {
+    return unauthorized(&caller, None, "Registry tokens cannot be exchanged for new ones").into_response();
+  }
+
+  let mut access: Vec<TokenAccess> = Vec::new();
+  for scope in query.scope.iter().flat_map(|s| s.split(' ')) {
+    let Some(requested) = TokenAccess::parse(scope) else {
+      continue;
+    };
+    let mut actions: Vec<String> = Vec::new();
+    match requested.kind.as_str() {
+      // The catalog only lists what the caller can pull anyway, but it is not offered anonymously.
+      "registry" if requested.name == "catalog" && caller.user.is_some() => actions.push("*".to_string()),
+      "repository" => {
+        let wants = |action: RegistryAction| requested.actions.iter().any(|a| a == action.as_str() || a == "*");
+        let pull = authorize_docker(&state, &requested.name, caller.clone(), RegistryAction::Pull).await;
+        if wants(RegistryAction::Pull) && pull.is_ok() {
+          actions.push("pull".to_string());
+        }
+        for action in [RegistryAction::Push, RegistryAction::Delete] {
+          if !wants(action) {
+            continue;
+          }
+          let allowed = match &pull {
+            // Unknown image: pushing may create it, decided when the manifest arrives.
+            Err((StatusCode::NOT_FOUND, _, _)) =>
+              action == RegistryAction::Push &&
+                caller.user.as_ref().is_some_and(|u| u.has_scope("registry:push") && u.confined_repo().is_none()) &&
+                *REGISTRY_AUTO_CREATE != AutoCreate::Off,
+            _ => authorize_docker(&state, &requested.name, caller.clone(), action).await.is_ok(),
+          };
+          if allowed {
+            actions.push(action.as_str().to_string());
+          }
+        }
       }
+      _ => {}
+    }
+    if !actions.is_empty() {
+      access.push(TokenAccess { actions, ..requested });
+    }
+  }
 
-      let mut h = docker_headers();
-      h.insert("Www-Authenticate", "Basic realm=\"Registry Realm\"".parse().unwrap());
-      return Err((StatusCode::UNAUTHORIZED, h, "Authentication required to create repository".to_string()));
+  // Personal tokens are referenced so that revoking them also revokes this token.
+  let mut identity = caller.user.as_ref().map(TokenIdentity::from);
+  if let (Some(identity), Some(secret)) = (identity.as_mut(), docker_credential(&headers)) {
+    if secret.starts_with(crate::token::TOKEN_PREFIX) {
+      identity.token_id = match
+        sqlx::query("SELECT id FROM personal_access_tokens WHERE token_hash = $1").bind(crate::token::hash_token(&secret)).fetch_optional(&state.db).await
+      {
+        Ok(row) => row.map(|r| r.get("id")),
+        Err(e) => {
+          return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+        }
+      };
     }
   }
+
+  let now = chrono::Utc::now().timestamp();
+  let claims = RegistryClaims {
+    iss: REGISTRY_SERVICE.to_string(),
+    sub: caller.user.as_ref().map(|u| u.username.clone()).unwrap_or_default(),
+    aud: REGISTRY_SERVICE.to_string(),
+    exp: (now + REGISTRY_TOKEN_TTL_SECS) as usize,
+    iat: now as usize,
+    access,
+    user: identity,
+  };
+  let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+  header.kid = Some(REGISTRY_TOKEN_KID.to_string());
+  let token = match jsonwebtoken::encode(&header, &claims, &jsonwebtoken::EncodingKey::from_secret(&REGISTRY_TOKEN_SECRET)) {
+    Ok(t) => t,
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
+
+  Json(
+    json!({
+    "token": token,
+    "access_token": token,
+    "expires_in": REGISTRY_TOKEN_TTL_SECS,
+    "issued_at": chrono::DateTime::from_timestamp(now, 0).map(|d| d.to_rfc3339())
+  })
+  ).into_response()
+}
+
+/// Largest page of the tag list and the catalog, also used when the client does not send `n`.
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+  n: Option<i64>,
+  last: Option<String>,
+}
+
+impl PageQuery {
+  fn size(&self) -> i64 {
+    self.n.unwrap_or(MAX_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+  }
+}
+
+/// `Link` header pointing at the page after `last`, as the distribution spec describes.
+fn next_page_link(path: &str, n: i64, last: &str) -> header::HeaderValue {
+  format!("<{}?n={}&last={}>; rel=\"next\"", path, n, last).parse().unwrap()
+}
+
+async fn list_tags_logic(state: Arc<AppState>, headers: HeaderMap, name: String, page: PageQuery) -> impl IntoResponse {
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Pull).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let n = page.size();
+
+  // Byte order ("C" collation): the `last` cursor must compare the same way clients sort.
+  let rows = sqlx
+    ::query(
+      r#"
+      SELECT t.tag FROM docker_tags t
+      JOIN docker_repositories r ON t.repo_id = r.id
+      WHERE r.name = $1 AND ($2::text IS NULL OR t.tag COLLATE "C" > $2)
+      ORDER BY t.tag COLLATE "C"
+      LIMIT $3
+      "#
+    )
+    .bind(&access.name)
+    .bind(&page.last)
+    .bind(n + 1)
+    .fetch_all(&state.db).await;
+
+  let mut tags: Vec<String> = match rows {
+    Ok(rows) => rows.iter().map(|r| r.get("tag")).collect(),
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
+
+  let mut h = docker_headers();
+  if tags.len() as i64 > n {
+    tags.truncate(n as usize);
+    h.insert(header::LINK, next_page_link(&format!("/v2/{}/tags/list", access.name), n, tags.last().unwrap()));
+  }
+  (StatusCode::OK, h, Json(json!({ "name": access.name, "tags": tags }))).into_response()
 }
 
-pub async fn v2_base_check() -> impl IntoResponse {
+/// Images the caller may pull, in name order; authenticated callers only. Visibility is evaluated in SQL with the same rules as
+/// `authorize_docker` for a pull, so private and trashed repositories never show up and each page is
+/// a single bounded query.
+pub async fn catalog(State(state): State<Arc<AppState>>, headers: HeaderMap, Query(page): Query<PageQuery>) -> impl IntoResponse {
+  let n = page.size();
+  let caller = docker_caller(&state, &headers).await;
+  if caller.user.is_none() || !caller.granted("registry", "catalog", "*") {
+    return unauthorized(&caller, Some("registry:catalog:*"), "Registry token does not grant the catalog").into_response();
+  }
+
+  // Past the catalog grant, every image is filtered by the caller's own permissions.
+  let user = caller.user.as_ref();
+  let can_pull_private = user.is_some_and(|u| u.has_scope("repo:read") || u.has_scope("registry:push"));
+  // Job tokens and deploy keys read their repository whatever the membership of their identity.
+  let confined_reader = user.is_some_and(|u| u.job.is_some() || (u.deploy_key.is_some() && u.perm_cap() >= RepoPerm::Read));
+
+  let rows = sqlx
+    ::query(
+      &format!(
+        r#"
+        SELECT d.name FROM docker_repositories d
+        JOIN repositories r ON r.id = d.repository_id
+        LEFT JOIN organizations o ON r.org_id = o.id
+        LEFT JOIN repository_members rm ON rm.repo_id = r.id AND rm.user_id = $2
+        LEFT JOIN organization_members om ON om.org_id = r.org_id AND om.user_id = $2
+        WHERE r.deleted_at IS NULL
+          -- Entries left under a former repository name resolve elsewhere: only list canonical names.
+          AND (d.name = {full} OR left(d.name, length({full}) + 1) = {full} || '/')
+          AND ($1::text IS NULL OR d.name COLLATE "C" > $1)
+          AND (
+            r.is_public OR (
+              $3 AND ($4::uuid IS NULL OR r.id = $4) AND
+              CASE WHEN rm.registry_perms IS NOT NULL THEN 'pull' = ANY(rm.registry_perms)
+              ELSE $5 OR rm.role IS NOT NULL OR om.role IS NOT NULL OR EXISTS (
+                SELECT 1 FROM team_repo_grants g JOIN team_members tm ON tm.team_id = g.team_id WHERE g.repo_id = r.id AND tm.user_id = $2
+              ) END
+            )
+          )
+        ORDER BY d.name COLLATE "C"
+        LIMIT $6
+        "#,
+        full = crate::auth::REPO_FULL_NAME_SQL
+      )
+    )
+    .bind(&page.last)
+    .bind(user.map(|u| u.id))
+    .bind(can_pull_private)
+    .bind(user.and_then(|u| u.confined_repo()))
+    .bind(confined_reader)
+    .bind(n + 1)
+    .fetch_all(&state.db).await;
+  let mut names: Vec<String> = match rows {
+    Ok(rows) => rows.iter().map(|r| r.get("name")).collect(),
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
+
   let mut h = docker_headers();
-  h.insert("Www-Authenticate", "Basic realm=\"Registry Realm\"".parse().unwrap());
-  (StatusCode::OK, h, Json(json!({})))
+  if names.len() as i64 > n {
+    names.truncate(n as usize);
+    h.insert(header::LINK, next_page_link("/v2/_catalog", n, names.last().unwrap()));
+  }
+  (StatusCode::OK, h, Json(json!({ "repositories": names }))).into_response()
 }
 
 async fn head_blob_logic(state: Arc<AppState>, headers: HeaderMap, name: String, digest: String) -> impl IntoResponse {
-  if let Err(e) = check_docker_access(&state, &name, &headers, false).await {
-    return e.into_response();
-  }
-  let hash = digest.strip_prefix("sha256:").unwrap_or(&digest);
-  let exists = sqlx::query("SELECT size FROM blobs WHERE sha256 = $1").bind(hash).fetch_optional(&state.db).await.unwrap_or(None);
-  match exists {
-    Some(row) => {
-      let size: i64 = row.get("size");
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Pull).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  match image_blob(&state, &access.name, &digest).await {
+    Some((_, size)) => {
       let mut h = docker_headers();
       h.insert(header::CONTENT_LENGTH, size.to_string().parse().unwrap());
       h.insert("Docker-Content-Digest", digest.parse().unwrap());
       (StatusCode::OK, h, "").into_response()
     }
-    None => (StatusCode::NOT_FOUND, docker_headers(), "").into_response(),
+    None => (StatusCode::NOT_FOUND, docker_headers(), "").into_response(),
+  }
+}
+
+/// Parses a single `bytes=` range against a blob of `size` bytes into an inclusive `(start, end)`.
+/// `Err(())` means the range cannot be satisfied; an unparsable header is ignored like a missing one.
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+  let Some(spec) = value.trim().strip_prefix("bytes=") else {
+    return Ok(None);
+  };
+  // Multipart ranges are not supported: serve the whole blob instead.
+  if spec.contains(',') {
+    return Ok(None);
+  }
+  let Some((first, last)) = spec.split_once('-') else {
+    return Ok(None);
+  };
+  let (start, end) = match (first.trim(), last.trim()) {
+    ("", suffix) => {
+      let Ok(n) = suffix.parse::<u64>() else {
+        return Ok(None);
+      };
+      if n == 0 {
+        return Err(());
+      }
+      (size.saturating_sub(n), size.saturating_sub(1))
+    }
+    (first, last) => {
+      let Ok(start) = first.parse::<u64>() else {
+        return Ok(None);
+      };
+      let end = if last.is_empty() {
+        size.saturating_sub(1)
+      } else {
+        match last.parse::<u64>() {
+          Ok(end) => end.min(size.saturating_sub(1)),
+          Err(_) => {
+            return Ok(None);
+          }
+        }
+      };
+      if end < start {
+        return Ok(None);
+      }
+      (start, end)
+    }
+  };
+  if start >= size {
+    return Err(());
+  }
+  Ok(Some((start, end)))
+}
+
+/// Streams `len` bytes of a stored object from `start` through a pipe, so layers are never held in memory.
+fn stream_object(state: &AppState, key: String, start: u64, len: u64) -> Body {
+  let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+  let bucket = state.bucket.clone();
+  tokio::spawn(async move {
+    // S3 ranges are inclusive and must span more than one byte; `take` below trims the one-byte case.
+    let end = if len > 1 { Some(start + len - 1) } else { None };
+    if let Err(e) = bucket.get_object_range_to_writer(&key, start, end, &mut writer).await {
+      tracing::warn!("🐳 Blob stream {} interrupted: {}", key, e);
+    }
+  });
+  Body::from_stream(ReaderStream::new(reader.take(len)))
+}
+
+/// Storage key and size of a blob, provided it was pushed or mounted into `image`. Blobs are shared
+/// across images in the CAS: knowing a digest must not be enough to read one.
+async fn image_blob(state: &AppState, image: &str, digest: &str) -> Option<(String, u64)> {
+  let hash = digest.strip_prefix("sha256:").unwrap_or(digest);
+  let row = sqlx
+    ::query(
+      r#"
+      SELECT b.hash, b.size FROM blobs b
+      JOIN docker_repository_blobs l ON l.sha256 = b.sha256
+      JOIN docker_repositories r ON r.id = l.repo_id
+      WHERE b.sha256 = $1 AND r.name = $2
+      LIMIT 1
+      "#
+    )
+    .bind(hash)
+    .bind(image)
+    .fetch_optional(&state.db).await
+    .unwrap_or(None)?;
+  Some((row.get("hash"), row.get::<i64, _>("size").max(0) as u64))
+}
+
+/// Row of the image in `docker_repositories`, created on first push. The image follows the repository
+/// that owns its name: a name left behind by a purged repository is taken over by the new owner.
+async fn image_id(state: &AppState, access: &DockerAccess) -> Result<Uuid, sqlx::Error> {
+  let row = sqlx
+    ::query(
+      "INSERT INTO docker_repositories (name, repository_id) VALUES ($1, $2) ON CONFLICT (name) DO UPDATE SET repository_id = EXCLUDED.repository_id RETURNING id"
+    )
+    .bind(&access.name)
+    .bind(access.repo_id)
+    .fetch_one(&state.db).await?;
+  Ok(row.get("id"))
+}
+
+/// Makes a blob readable from the image and referenceable by its manifests.
+async fn link_blob(state: &AppState, access: &DockerAccess, digest: &str) -> Result<(), sqlx::Error> {
+  let image = image_id(state, access).await?;
+  sqlx
+    ::query("INSERT INTO docker_repository_blobs (repo_id, sha256) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+    .bind(image)
+    .bind(digest.strip_prefix("sha256:").unwrap_or(digest))
+    .execute(&state.db).await?;
+  Ok(())
+}
+
+async fn get_blob_logic(state: Arc<AppState>, headers: HeaderMap, name: String, digest: String) -> impl IntoResponse {
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Pull).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let Some((key, size)) = image_blob(&state, &access.name, &digest).await else {
+    return (StatusCode::NOT_FOUND, docker_headers(), "Blob unknown").into_response();
+  };
+
+  let range = headers
+    .get(header::RANGE)
+    .and_then(|v| v.to_str().ok())
+    .map(|v| parse_range(v, size))
+    .unwrap_or(Ok(None));
+
+  let mut h = docker_headers();
+  h.insert("Docker-Content-Digest", digest.parse().unwrap());
+  h.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+  h.insert(header::CONTENT_TYPE, "application/octet-stream".parse().unwrap());
+
+  match range {
+    Err(()) => {
+      h.insert(header::CONTENT_RANGE, format!("bytes */{}", size).parse().unwrap());
+      (StatusCode::RANGE_NOT_SATISFIABLE, h, "").into_response()
+    }
+    Ok(Some((start, end))) => {
+      let len = end - start + 1;
+      h.insert(header::CONTENT_LENGTH, len.to_string().parse().unwrap());
+      h.insert(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size).parse().unwrap());
+      (StatusCode::PARTIAL_CONTENT, h, stream_object(&state, key, start, len)).into_response()
+    }
+    Ok(None) => {
+      h.insert(header::CONTENT_LENGTH, size.to_string().parse().unwrap());
+      (StatusCode::OK, h, stream_object(&state, key, 0, size)).into_response()
+    }
+  }
+}
+
+/// Counts and hashes what flows through it: SHA-256 for the registry digest, BLAKE3 for the storage key.
+struct HashingReader<R> {
+  inner: R,
+  sha256: Sha256,
+  blake3: blake3::Hasher,
+  len: u64,
+}
+
+impl<R> HashingReader<R> {
+  fn new(inner: R) -> Self {
+    Self { inner, sha256: Sha256::new(), blake3: blake3::Hasher::new(), len: 0 }
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+    let this = self.get_mut();
+    let before = buf.filled().len();
+    let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+    if let Poll::Ready(Ok(())) = &res {
+      let read = &buf.filled()[before..];
+      this.sha256.update(read);
+      this.blake3.update(read);
+      this.len += read.len() as u64;
+    }
+    res
+  }
+}
+
+/// Fresh storage key for the `index`-th chunk of an upload session. Unique per request: a PATCH that
+/// loses the race for the slot never overwrites the chunk of the one that won it.
+fn chunk_key(uuid: Uuid, index: usize) -> String {
+  format!("uploads/{}/{}-{}", uuid, index, Uuid::new_v4().simple())
+}
+
+struct UploadSession {
+  uuid: Uuid,
+  size: i64,
+  /// Storage keys of the chunks received so far, in order.
+  chunk_keys: Vec<String>,
+}
+
+/// Headers describing an upload session, returned after every step so clients can resume.
+fn upload_headers(name: &str, session: &UploadSession) -> HeaderMap {
+  let mut h = docker_headers();
+  h.insert(header::LOCATION, format!("/v2/{}/blobs/uploads/{}", name, session.uuid).parse().unwrap());
+  h.insert(header::RANGE, format!("0-{}", (session.size - 1).max(0)).parse().unwrap());
+  h.insert("Docker-Upload-UUID", session.uuid.to_string().parse().unwrap());
+  h
+}
+
+/// Finds an upload session opened on this image. Sessions of other images are reported unknown.
+async fn find_upload(state: &AppState, name: &str, uuid: &str) -> Result<UploadSession, (StatusCode, HeaderMap, String)> {
+  let unknown = || (StatusCode::NOT_FOUND, docker_headers(), "Upload unknown".to_string());
+  let uuid = Uuid::parse_str(uuid).map_err(|_| unknown())?;
+  let row = sqlx
+    ::query("SELECT size, chunk_keys FROM docker_uploads WHERE uuid = $1 AND repo_name = $2")
+    .bind(uuid)
+    .bind(name)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()))?
+    .ok_or_else(unknown)?;
+  Ok(UploadSession { uuid, size: row.get("size"), chunk_keys: row.get("chunk_keys") })
+}
+
+/// Drops an upload session and its stored chunks.
+pub async fn discard_upload(state: &AppState, uuid: Uuid, chunk_keys: &[String]) {
+  for key in chunk_keys {
+    let _ = state.bucket.delete_object(key).await;
+  }
+  let _ = state.bucket.delete_object(format!("uploads/{}/blob", uuid)).await;
+  let _ = sqlx::query("DELETE FROM docker_uploads WHERE uuid = $1").bind(uuid).execute(&state.db).await;
+}
+
+/// Stores a request body as the session's next chunk. A `Content-Range` must start where the upload stands.
+async fn append_chunk(state: &AppState, headers: &HeaderMap, session: &mut UploadSession, body: Body) -> Result<(), (StatusCode, HeaderMap, String)> {
+  let range_start = headers
+    .get(header::CONTENT_RANGE)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.trim_start_matches("bytes ").split('-').next()?.trim().parse::<i64>().ok());
+  if range_start.is_some_and(|start| start != session.size) {
+    return Err((StatusCode::RANGE_NOT_SATISFIABLE, docker_headers(), format!("Upload is at offset {}", session.size)));
+  }
+
+  let index = session.chunk_keys.len();
+  let key = chunk_key(session.uuid, index);
+  let stream = body.into_data_stream().map(|chunk| chunk.map_err(std::io::Error::other));
+  let mut reader = HashingReader::new(StreamReader::new(stream));
+  state.bucket
+    .put_object_stream(&mut reader, &key).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()))?;
+
+  // Guarded on the chunk count: two concurrent PATCHes cannot both claim the same slot, and the loser's
+  // chunk, stored under its own key, is dropped.
+  let updated = sqlx
+    ::query(
+      "UPDATE docker_uploads SET chunks = chunks + 1, chunk_keys = array_append(chunk_keys, $4), size = size + $2, updated_at = NOW() WHERE uuid = $1 AND chunks = $3"
+    )
+    .bind(session.uuid)
+    .bind(reader.len as i64)
+    .bind(index as i32)
+    .bind(&key)
+    .execute(&state.db).await;
+  let claimed = matches!(&updated, Ok(done) if done.rows_affected() == 1);
+  if !claimed {
+    let _ = state.bucket.delete_object(&key).await;
+  }
+  if let Err(e) = updated {
+    return Err((StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()));
+  }
+  if !claimed {
+    return Err((StatusCode::RANGE_NOT_SATISFIABLE, docker_headers(), "Concurrent upload to the same session".to_string()));
+  }
+
+  session.chunk_keys.push(key);
+  session.size += reader.len as i64;
+  Ok(())
+}
+
+/// Concatenates the chunks into one object while hashing it, checks the digest and stores the blob
+/// under its BLAKE3 key. Returns the SHA-256 digest.
+async fn finalize_upload(state: &AppState, session: &UploadSession, expected_digest: &str) -> Result<String, (StatusCode, HeaderMap, String)> {
+  let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e);
+
+  let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+  let bucket = state.bucket.clone();
+  let keys = session.chunk_keys.clone();
+  let feeder = tokio::spawn(async move {
+    for key in keys {
+      bucket.get_object_to_writer(&key, &mut writer).await?;
+    }
+    Ok::<(), s3::error::S3Error>(())
+  });
+
+  let assembled = format!("uploads/{}/blob", session.uuid);
+  let mut hashing = HashingReader::new(reader);
+  let stored = state.bucket.put_object_stream(&mut hashing, &assembled).await;
+  feeder
+    .await
+    .map_err(|e| internal(e.to_string()))?
+    .map_err(|e| internal(e.to_string()))?;
+  stored.map_err(|e| internal(e.to_string()))?;
+
+  let digest = format!("sha256:{:x}", hashing.sha256.finalize());
+  if digest != expected_digest {
+    return Err((StatusCode::BAD_REQUEST, docker_headers(), "Digest mismatch".to_string()));
+  }
+  let blake3 = hashing.blake3.finalize().to_hex().to_string();
+
+  let exists = sqlx
+    ::query("SELECT 1 FROM blobs WHERE hash = $1")
+    .bind(&blake3)
+    .fetch_optional(&state.db).await
+    .map_err(|e| internal(e.to_string()))?
+    .is_some();
+  if !exists {
+    state.bucket.copy_object_internal(&assembled, &blake3).await.map_err(|e| internal(e.to_string()))?;
+  }
+
+  sqlx
+    ::query(
+      "INSERT INTO blobs (hash, sha256, size, mime_type, storage_path) VALUES ($1, $2, $3, 'application/vnd.docker.image.rootfs.diff.tar.gzip', $1) ON CONFLICT (hash) DO UPDATE SET sha256 = $2"
+    )
+    .bind(&blake3)
+    .bind(digest.strip_prefix("sha256:").unwrap())
+    .bind(hashing.len as i64)
+    .execute(&state.db).await
+    .map_err(|e| internal(e.to_string()))?;
+
+  Ok(digest)
+}
+
+#[derive(Deserialize)]
+pub struct MountQuery {
+  mount: Option<String>,
+  from: Option<String>,
+}
+
+/// Opens an upload session, unless `mount`/`from` name a blob the caller can already read in
+/// another image: the CAS holds it, so it is linked without any transfer. Per the spec, a mount
+/// that cannot be honored falls back to a regular session.
+async fn start_upload_logic(state: Arc<AppState>, headers: HeaderMap, name: String, mount: MountQuery) -> impl IntoResponse {
+  let caller = docker_caller(&state, &headers).await;
+  let access = match authorize_docker(&state, &name, caller.clone(), RegistryAction::Push).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let name = access.name.clone();
+
+  if let (Some(digest), Some(from)) = (&mount.mount, &mount.from) {
+    let well_formed = digest.strip_prefix("sha256:").is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if !well_formed {
+      return registry_error(StatusCode::BAD_REQUEST, "DIGEST_INVALID", "Invalid mount digest");
+    }
+    if let Ok(source) = authorize_docker(&state, from, caller, RegistryAction::Pull).await {
+      if image_blob(&state, &source.name, digest).await.is_some() {
+        // Readable from the target right away, as the spec requires after a 201.
+        if let Err(e) = link_blob(&state, &access, digest).await {
+          return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+        }
+        tracing::info!("🐳 Mounted {} from {} into {}", digest, source.name, name);
+        let mut h = docker_headers();
+        h.insert("Docker-Content-Digest", digest.parse().unwrap());
+        h.insert(header::LOCATION, format!("/v2/{}/blobs/{}", name, digest).parse().unwrap());
+        return (StatusCode::CREATED, h, "").into_response();
+      }
+    }
+  }
+
+  let session = UploadSession { uuid: Uuid::new_v4(), size: 0, chunk_keys: Vec::new() };
+  if let Err(e) = sqlx::query("INSERT INTO docker_uploads (uuid, repo_name) VALUES ($1, $2)").bind(session.uuid).bind(&name).execute(&state.db).await {
+    return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+  }
+  (StatusCode::ACCEPTED, upload_headers(&name, &session), "").into_response()
+}
+
+async fn patch_upload_logic(state: Arc<AppState>, headers: HeaderMap, name: String, uuid: String, body: Body) -> impl IntoResponse {
+  let name = match check_docker_access(&state, &name, &headers, RegistryAction::Push).await {
+    Ok(access) => access.name,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let mut session = match find_upload(&state, &name, &uuid).await {
+    Ok(s) => s,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  if let Err(e) = append_chunk(&state, &headers, &mut session, body).await {
+    return e.into_response();
+  }
+  (StatusCode::ACCEPTED, upload_headers(&name, &session), "").into_response()
+}
+
+async fn upload_status_logic(state: Arc<AppState>, headers: HeaderMap, name: String, uuid: String) -> impl IntoResponse {
+  let name = match check_docker_access(&state, &name, &headers, RegistryAction::Push).await {
+    Ok(access) => access.name,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  match find_upload(&state, &name, &uuid).await {
+    Ok(session) => (StatusCode::NO_CONTENT, upload_headers(&name, &session), "").into_response(),
+    Err(e) => e.into_response(),
   }
 }
 
-async fn start_upload_logic(state: Arc<AppState>, headers: HeaderMap, name: String) -> impl IntoResponse {
-  if let Err(e) = check_docker_access(&state, &name, &headers, true).await {
-    return e.into_response();
+async fn cancel_upload_logic(state: Arc<AppState>, headers: HeaderMap, name: String, uuid: String) -> impl IntoResponse {
+  let name = match check_docker_access(&state, &name, &headers, RegistryAction::Push).await {
+    Ok(access) => access.name,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  match find_upload(&state, &name, &uuid).await {
+    Ok(session) => {
+      discard_upload(&state, session.uuid, &session.chunk_keys).await;
+      (StatusCode::NO_CONTENT, docker_headers(), "").into_response()
+    }
+    Err(e) => e.into_response(),
   }
-  let uuid = Uuid::new_v4();
-  let _ = sqlx::query("INSERT INTO docker_uploads (uuid, repo_name) VALUES ($1, $2)").bind(uuid).bind(&name).execute(&state.db).await;
-  let mut h = docker_headers();
-  let location = format!("/v2/{}/blobs/uploads/{}", name, uuid);
-  h.insert(header::LOCATION, location.parse().unwrap());
-  h.insert("Range", "0-0".parse().unwrap());
-  (StatusCode::ACCEPTED, h, "").into_response()
 }
 
+/// Final `PUT`: an optional last chunk, then digest verification. The session is gone afterwards.
 async fn complete_upload_logic(
   state: Arc<AppState>,
   headers: HeaderMap,
   name: String,
   uuid: String,
   digest_opt: Option<String>,
-  body: axum::body::Body
+  body: Body
 ) -> impl IntoResponse {
-  if let Err(e) = check_docker_access(&state, &name, &headers, true).await {
-    return e.into_response();
-  }
-  let expected_digest = match digest_opt {
-    Some(d) => d,
-    None => {
-      return (StatusCode::BAD_REQUEST, docker_headers(), "Missing digest").into_response();
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Push).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let name = access.name.clone();
+  let Some(expected_digest) = digest_opt else {
+    return (StatusCode::BAD_REQUEST, docker_headers(), "Missing digest").into_response();
+  };
+  let mut session = match find_upload(&state, &name, &uuid).await {
+    Ok(s) => s,
+    Err(e) => {
+      return e.into_response();
     }
   };
 
-  let mut stream = body.into_data_stream();
-  let mut data = Vec::new();
-  let mut hasher_sha256 = Sha256::new();
-  let mut hasher_blake3 = blake3::Hasher::new();
+  let empty_body = headers
+    .get(header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| v.trim() == "0");
+  if !empty_body {
+    if let Err(e) = append_chunk(&state, &headers, &mut session, body).await {
+      return e.into_response();
+    }
+  }
+
+  let result = finalize_upload(&state, &session, &expected_digest).await;
+  discard_upload(&state, session.uuid, &session.chunk_keys).await;
 
-  while let Some(chunk) = stream.next().await {
-    match chunk {
-      Ok(bytes) => {
-        data.extend_from_slice(&bytes);
-        hasher_sha256.update(&bytes);
-        hasher_blake3.update(&bytes);
-      }
-      Err(_) => {
-        return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), "Stream error").into_response();
+  match result {
+    Ok(digest) => {
+      if let Err(e) = link_blob(&state, &access, &digest).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
       }
+      let mut h = docker_headers();
+      h.insert("Docker-Content-Digest", digest.parse().unwrap());
+      h.insert(header::LOCATION, format!("/v2/{}/blobs/{}", name, digest).parse().unwrap());
+      (StatusCode::CREATED, h, "").into_response()
     }
+    Err(e) => e.into_response(),
   }
+}
 
-  let calculated_sha256 = format!("sha256:{:x}", hasher_sha256.finalize());
-  let calculated_blake3 = hasher_blake3.finalize().to_hex().to_string();
+const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
 
-  if calculated_sha256 != expected_digest {
-    return (StatusCode::BAD_REQUEST, docker_headers(), "Digest mismatch").into_response();
-  }
+/// Manifest lists and OCI indexes point to one manifest per platform instead of layers.
+fn is_index(media_type: &str) -> bool {
+  media_type == DOCKER_MANIFEST_LIST || media_type == OCI_INDEX
+}
 
-  if let Err(e) = state.bucket.put_object(&calculated_blake3, &data).await {
-    return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
-  }
+fn registry_error(status: StatusCode, code: &str, message: &str) -> axum::response::Response {
+  (status, docker_headers(), Json(json!({"errors": [{"code": code, "message": message}]}))).into_response()
+}
+
+/// Platform entries of an index, without the attestation manifests BuildKit adds as `unknown/unknown`.
+fn index_platforms(content: &Value) -> impl Iterator<Item = &Value> {
+  content["manifests"]
+    .as_array()
+    .into_iter()
+    .flatten()
+    .filter(|m| m["platform"]["os"].as_str() != Some("unknown"))
+}
+
+/// Media types from the `Accept` headers, parameters dropped. Empty means the client accepts anything.
+fn accepted_types(headers: &HeaderMap) -> Vec<String> {
+  headers
+    .get_all(header::ACCEPT)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(','))
+    .map(|t| t.split(';').next().unwrap_or("").trim().to_string())
+    .filter(|t| !t.is_empty())
+    .collect()
+}
+
+fn accepts(accepted: &[String], media_type: &str) -> bool {
+  accepted.is_empty() || accepted.iter().any(|a| a == media_type || a == "*/*")
+}
 
-  let sha256_clean = calculated_sha256.strip_prefix("sha256:").unwrap();
-  let size = data.len() as i64;
+struct StoredManifest {
+  digest: String,
+  media_type: String,
+  content: Value,
+  raw: Vec<u8>,
+}
 
-  let _ = sqlx
+/// Resolves a tag or a digest within one image.
+async fn find_manifest(state: &AppState, name: &str, reference: &str) -> Result<Option<StoredManifest>, sqlx::Error> {
+  let row = sqlx
     ::query(
-      "INSERT INTO blobs (hash, sha256, size, mime_type, storage_path) VALUES ($1, $2, $3, 'application/vnd.docker.image.rootfs.diff.tar.gzip', $1) ON CONFLICT (hash) DO UPDATE SET sha256 = $2"
+      r#"
+      SELECT m.digest, m.media_type, m.content, m.raw
+      FROM docker_manifests m
+      JOIN docker_repositories r ON m.repo_id = r.id
+      WHERE r.name = $1
+        AND m.digest = COALESCE((SELECT t.manifest_digest FROM docker_tags t WHERE t.repo_id = r.id AND t.tag = $2), $2)
+      "#
     )
-    .bind(&calculated_blake3)
-    .bind(sha256_clean)
-    .bind(size)
-    .execute(&state.db).await;
+    .bind(name)
+    .bind(reference)
+    .fetch_optional(&state.db).await?;
 
-  let _ = sqlx::query("DELETE FROM docker_uploads WHERE uuid = $1").bind(Uuid::parse_str(&uuid).unwrap_or_default()).execute(&state.db).await;
-
-  let mut h = docker_headers();
-  h.insert("Docker-Content-Digest", calculated_sha256.parse().unwrap());
-  h.insert(header::LOCATION, format!("/v2/{}/blobs/{}", name, calculated_sha256).parse().unwrap());
-  (StatusCode::CREATED, h, "").into_response()
+  Ok(
+    row.map(|r| {
+      let content: Value = r.get("content");
+      // Manifests pushed before the exact bytes were kept only have their JSON.
+      let raw = r.get::<Option<Vec<u8>>, _>("raw").unwrap_or_else(|| serde_json::to_vec(&content).unwrap_or_default());
+      StoredManifest { digest: r.get("digest"), media_type: r.get("media_type"), content, raw }
+    })
+  )
 }
 
-async fn put_manifest_logic(state: Arc<AppState>, headers: HeaderMap, name: String, tag: String, body: Bytes) -> impl IntoResponse {
-  if let Err(e) = check_docker_access(&state, &name, &headers, true).await {
-    return e.into_response();
-  }
+/// The reference is a tag, or the digest of the body when a client pushes by digest
+/// (as for the platform manifests of a multi-arch image).
+async fn put_manifest_logic(state: Arc<AppState>, headers: HeaderMap, ip: ClientIp, name: String, reference: String, body: Bytes) -> impl IntoResponse {
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Push).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  let name = access.name.clone();
   let mut hasher = Sha256::new();
   hasher.update(&body);
   let digest = format!("sha256:{:x}", hasher.finalize());
   let manifest: Value = match serde_json::from_slice(&body) {
     Ok(v) => v,
     Err(_) => {
-      return (StatusCode::BAD_REQUEST, docker_headers(), "Invalid JSON").into_response();
+      return registry_error(StatusCode::BAD_REQUEST, "MANIFEST_INVALID", "Invalid JSON");
     }
   };
 
-  let parts: Vec<&str> = name.splitn(2, '/').collect();
-  let plectr_repo_name = parts[0];
+  let tag = if reference.contains(':') {
+    if reference != digest {
+      return registry_error(StatusCode::BAD_REQUEST, "DIGEST_INVALID", "Manifest digest does not match the reference");
+    }
+    None
+  } else {
+    Some(reference)
+  };
 
-  let repo_row = sqlx
-    ::query("SELECT id FROM repositories WHERE name = $1")
-    .bind(plectr_repo_name)
-    .fetch_optional(&state.db).await
-    .unwrap_or(None);
+  // The body's `mediaType` wins; OCI manifests may omit it and rely on Content-Type alone.
+  let content_type = headers
+    .get(header::CONTENT_TYPE)
+    .and_then(|v| v.to_str().ok())
+    .map(|v| v.split(';').next().unwrap_or("").trim());
+  let media_type = match (manifest["mediaType"].as_str(), content_type) {
+    (Some(declared), Some(sent)) if declared != sent && sent != "application/json" => {
+      return registry_error(StatusCode::BAD_REQUEST, "MANIFEST_INVALID", "Content-Type does not match the manifest mediaType");
+    }
+    (Some(declared), _) => declared.to_string(),
+    (None, Some(sent)) => sent.to_string(),
+    (None, None) => if manifest["manifests"].is_array() { OCI_INDEX } else { OCI_MANIFEST }.to_string(),
+  };
+  if ![DOCKER_MANIFEST, DOCKER_MANIFEST_LIST, OCI_MANIFEST, OCI_INDEX].contains(&media_type.as_str()) {
+    return registry_error(StatusCode::BAD_REQUEST, "MANIFEST_INVALID", &format!("Unsupported manifest media type '{}'", media_type));
+  }
 
-  let repo_id = match repo_row {
-    Some(r) => r.get::<Uuid, _>("id"),
-    None => {
-      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), "Repo missing after check").into_response();
+  let docker_repo_id = match image_id(&state, &access).await {
+    Ok(id) => id,
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
     }
   };
 
-  let _ = sqlx
-    ::query("INSERT INTO docker_repositories (name) VALUES ($1) ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name")
-    .bind(&name)
-    .execute(&state.db).await;
+  // Image manifests may only reference blobs pushed or mounted into this image: otherwise pushing a
+  // manifest would be a way to read any layer whose digest is known. Foreign layers are never stored.
+  if !is_index(&media_type) {
+    let mut blobs: Vec<String> = std::iter
+      ::once(&manifest["config"])
+      .chain(manifest["layers"].as_array().into_iter().flatten().filter(|l| l["urls"].is_null()))
+      .filter_map(|d| d["digest"].as_str())
+      .map(|d| d.strip_prefix("sha256:").unwrap_or(d).to_string())
+      .collect();
+    blobs.sort();
+    blobs.dedup();
+    let linked: i64 = sqlx
+      ::query("SELECT COUNT(*) AS n FROM docker_repository_blobs WHERE repo_id = $1 AND sha256 = ANY($2)")
+      .bind(docker_repo_id)
+      .bind(&blobs)
+      .fetch_one(&state.db).await
+      .map(|r| r.get("n"))
+      .unwrap_or(0);
+    if linked != (blobs.len() as i64) {
+      return registry_error(StatusCode::BAD_REQUEST, "MANIFEST_BLOB_UNKNOWN", "Manifest references a blob that was not pushed to this image");
+    }
+  }
 
-  let docker_repo_id_row = sqlx::query("SELECT id FROM docker_repositories WHERE name = $1").bind(&name).fetch_one(&state.db).await;
-  let docker_repo_id: Uuid = docker_repo_id_row.unwrap().get("id");
+  // Each platform of an index must already be pushed to this image, or pulls would dangle.
+  if is_index(&media_type) {
+    let children: Vec<String> = manifest["manifests"]
+      .as_array()
+      .into_iter()
+      .flatten()
+      .filter_map(|m| m["digest"].as_str().map(String::from))
+      .collect();
+    let known: i64 = sqlx
+      ::query("SELECT COUNT(DISTINCT digest) AS n FROM docker_manifests WHERE repo_id = $1 AND digest = ANY($2)")
+      .bind(docker_repo_id)
+      .bind(&children)
+      .fetch_one(&state.db).await
+      .map(|r| r.get("n"))
+      .unwrap_or(0);
+    let mut unique = children.clone();
+    unique.sort();
+    unique.dedup();
+    if known != (unique.len() as i64) {
+      return registry_error(StatusCode::BAD_REQUEST, "MANIFEST_BLOB_UNKNOWN", "Index references a manifest that was not pushed to this image");
+    }
+  }
 
-  let _ = sqlx
-    ::query(
-      "INSERT INTO docker_manifests (digest, repo_id, content) VALUES ($1, $2, $3) ON CONFLICT (digest) DO UPDATE SET content = EXCLUDED.content"
-    )
-    .bind(&digest)
-    .bind(docker_repo_id)
-    .bind(&manifest)
-    .execute(&state.db).await;
+  // Signatures, SBOMs and attestations point to the image they describe through `subject`.
+  let subject = manifest["subject"]["digest"].as_str().map(String::from);
+  let artifact_type = manifest["artifactType"].as_str().or(manifest["config"]["mediaType"].as_str()).map(String::from);
 
-  let _ = sqlx
-    ::query(
-      "INSERT INTO docker_tags (repo_id, tag, manifest_digest) VALUES ($1, $2, $3) ON CONFLICT (repo_id, tag) DO UPDATE SET manifest_digest = $3, updated_at = NOW()"
-    )
-    .bind(docker_repo_id)
-    .bind(&tag)
-    .bind(&digest)
-    .execute(&state.db).await;
+  // The manifest and its tag land together: a client told `201 Created` can pull what it pushed.
+  let result: Result<Option<String>, sqlx::Error> = async {
+    let mut tx = state.db.begin().await?;
+
+    sqlx
+      ::query(
+        r#"
+        INSERT INTO docker_manifests (digest, repo_id, content, media_type, raw, subject_digest, artifact_type) VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (repo_id, digest) DO UPDATE
+        SET content = EXCLUDED.content, media_type = EXCLUDED.media_type, raw = EXCLUDED.raw,
+            subject_digest = EXCLUDED.subject_digest, artifact_type = EXCLUDED.artifact_type
+        "#
+      )
+      .bind(&digest)
+      .bind(docker_repo_id)
+      .bind(&manifest)
+      .bind(&media_type)
+      .bind(body.as_ref())
+      .bind(&subject)
+      .bind(&artifact_type)
+      .execute(&mut *tx).await?;
+
+    let mut previous_digest = None;
+    if let Some(tag) = &tag {
+      previous_digest = sqlx
+        ::query("SELECT manifest_digest FROM docker_tags WHERE repo_id = $1 AND tag = $2 FOR UPDATE")
+        .bind(docker_repo_id)
+        .bind(tag)
+        .fetch_optional(&mut *tx).await?
+        .map(|r| r.get("manifest_digest"));
+
+      sqlx
+        ::query(
+          "INSERT INTO docker_tags (repo_id, tag, manifest_digest) VALUES ($1, $2, $3) ON CONFLICT (repo_id, tag) DO UPDATE SET manifest_digest = $3, updated_at = NOW()"
+        )
+        .bind(docker_repo_id)
+        .bind(tag)
+        .bind(&digest)
+        .execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(previous_digest)
+  }.await;
+
+  let previous_digest = match result {
+    Ok(previous_digest) => previous_digest,
+    Err(e) => {
+      tracing::error!("❌ Manifest push to {} failed: {}", name, e);
+      return registry_error(StatusCode::INTERNAL_SERVER_ERROR, "UNKNOWN", "Could not store the manifest");
+    }
+  };
+
+  if let Some(tag) = &tag {
+    audit::record(&state.db, access.user.as_ref(), &ip, audit::Entry {
+      action: "registry.push",
+      repo_id: Some(access.repo_id),
+      target: &format!("{}:{}", name, tag),
+      before: previous_digest.map(|d| json!({ "digest": d })),
+      after: Some(json!({ "digest": digest, "media_type": media_type })),
+    }).await;
+  }
 
   let mut h = docker_headers();
   h.insert("Docker-Content-Digest", digest.parse().unwrap());
   h.insert(header::LOCATION, format!("/v2/{}/manifests/{}", name, digest).parse().unwrap());
+  // Tells clients the referrers API indexes this subject, so no fallback tag is needed.
+  if let Some(subject) = subject.and_then(|d| d.parse().ok()) {
+    h.insert("OCI-Subject", subject);
+  }
   (StatusCode::CREATED, h, "").into_response()
 }
 
-async fn get_manifest_logic(state: Arc<AppState>, headers: HeaderMap, name: String, reference: String, is_head: bool) -> impl IntoResponse {
-  if let Err(e) = check_docker_access(&state, &name, &headers, false).await {
-    return e.into_response();
+#[derive(Deserialize)]
+pub struct ReferrersQuery {
+  #[serde(rename = "artifactType")]
+  artifact_type: Option<String>,
+}
+
+/// Manifests whose `subject` is `digest`, as an OCI image index. The subject itself need not exist.
+async fn referrers_logic(state: Arc<AppState>, headers: HeaderMap, name: String, digest: String, query: ReferrersQuery) -> impl IntoResponse {
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Pull).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+  if !digest.contains(':') {
+    return registry_error(StatusCode::BAD_REQUEST, "DIGEST_INVALID", "Invalid digest");
   }
 
-  let row = sqlx
+  let rows = sqlx
     ::query(
       r#"
-    SELECT m.digest, m.content 
-    FROM docker_manifests m
-    JOIN docker_repositories r ON m.repo_id = r.id
-    LEFT JOIN docker_tags t ON t.repo_id = r.id AND t.manifest_digest = m.digest
-    WHERE r.name = $1 AND (t.tag = $2 OR m.digest = $2)
-    LIMIT 1
-    "#
+      SELECT m.digest, m.media_type, m.artifact_type, m.content->'annotations' AS annotations,
+             COALESCE(octet_length(m.raw), octet_length(m.content::text)) AS size
+      FROM docker_manifests m
+      JOIN docker_repositories r ON m.repo_id = r.id
+      WHERE r.name = $1 AND m.subject_digest = $2 AND ($3::text IS NULL OR m.artifact_type = $3)
+      ORDER BY m.created_at
+      "#
     )
-    .bind(&name)
-    .bind(&reference)
-    .fetch_optional(&state.db).await
-    .unwrap_or(None);
+    .bind(&access.name)
+    .bind(&digest)
+    .bind(&query.artifact_type)
+    .fetch_all(&state.db).await;
 
-  match row {
-    Some(r) => {
-      let digest: String = r.get("digest");
-      let content: Value = r.get("content");
-      let mut h = docker_headers();
-      h.insert("Docker-Content-Digest", digest.parse().unwrap());
-      h.insert(header::CONTENT_TYPE, "application/vnd.docker.distribution.manifest.v2+json".parse().unwrap());
+  let manifests: Vec<Value> = match rows {
+    Ok(rows) =>
+      rows
+        .iter()
+        .map(|r| {
+          let mut entry =
+            json!({
+            "mediaType": r.get::<String, _>("media_type"),
+            "digest": r.get::<String, _>("digest"),
+            "size": r.get::<i32, _>("size"),
+          });
+          if let Some(t) = r.get::<Option<String>, _>("artifact_type") {
+            entry["artifactType"] = json!(t);
+          }
+          if let Some(a) = r.get::<Option<Value>, _>("annotations") {
+            entry["annotations"] = a;
+          }
+          entry
+        })
+        .collect(),
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
 
-      if is_head {
-        let size = serde_json::to_vec(&content).unwrap().len();
-        h.insert(header::CONTENT_LENGTH, size.to_string().parse().unwrap());
-        (StatusCode::OK, h, "").into_response()
-      } else {
-        (StatusCode::OK, h, Json(content)).into_response()
+  let mut h = docker_headers();
+  h.insert(header::CONTENT_TYPE, OCI_INDEX.parse().unwrap());
+  if query.artifact_type.is_some() {
+    h.insert("OCI-Filters-Applied", "artifactType".parse().unwrap());
+  }
+  (StatusCode::OK, h, Json(json!({ "schemaVersion": 2, "mediaType": OCI_INDEX, "manifests": manifests }))).into_response()
+}
+
+async fn get_manifest_logic(state: Arc<AppState>, headers: HeaderMap, name: String, reference: String, is_head: bool) -> impl IntoResponse {
+  let name = match check_docker_access(&state, &name, &headers, RegistryAction::Pull).await {
+    Ok(access) => access.name,
+    Err(e) => {
+      return e.into_response();
+    }
+  };
+
+  let mut manifest = match find_manifest(&state, &name, &reference).await {
+    Ok(Some(m)) => m,
+    Ok(None) => {
+      return registry_error(StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN", "Manifest unknown");
+    }
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
+
+  // A client that cannot read indexes gets the linux/amd64 platform, as Docker Hub does.
+  // Single manifests are served as pushed: there is no conversion between formats.
+  let accepted = accepted_types(&headers);
+  if is_index(&manifest.media_type) && !accepts(&accepted, &manifest.media_type) {
+    let fallback = index_platforms(&manifest.content)
+      .find(|m| {
+        m["platform"]["os"] == "linux" && m["platform"]["architecture"] == "amd64" && accepts(&accepted, m["mediaType"].as_str().unwrap_or(""))
+      })
+      .and_then(|m| m["digest"].as_str().map(String::from));
+    let child = match fallback {
+      Some(digest) => find_manifest(&state, &name, &digest).await.unwrap_or(None),
+      None => None,
+    };
+    manifest = match child {
+      Some(child) => child,
+      None => {
+        return registry_error(StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN", "No manifest matches the Accept header");
       }
+    };
+  }
+
+  let mut h = docker_headers();
+  h.insert("Docker-Content-Digest", manifest.digest.parse().unwrap());
+  h.insert(header::CONTENT_TYPE, manifest.media_type.parse().unwrap());
+  h.insert(header::CONTENT_LENGTH, manifest.raw.len().to_string().parse().unwrap());
+
+  if is_head {
+    (StatusCode::OK, h, "").into_response()
+  } else {
+    (StatusCode::OK, h, manifest.raw).into_response()
+  }
+}
+
+/// Deleting a tag only unlinks it; deleting a digest removes the manifest and every tag pointing at
+/// it. Layers then lose their last reference and are left to `storage::collect_garbage`.
+async fn delete_manifest_logic(state: Arc<AppState>, headers: HeaderMap, ip: ClientIp, name: String, reference: String) -> impl IntoResponse {
+  let access = match check_docker_access(&state, &name, &headers, RegistryAction::Delete).await {
+    Ok(access) => access,
+    Err(e) => {
+      return e.into_response();
     }
-    None => (StatusCode::NOT_FOUND, docker_headers(), Json(json!({"errors": [{"code": "MANIFEST_UNKNOWN"}]}))).into_response(),
+  };
+  let manifest_unknown = || registry_error(StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN", "Manifest unknown");
+
+  // Tags cannot contain ':', digests always do.
+  if !reference.contains(':') {
+    let deleted = sqlx
+      ::query(
+        "DELETE FROM docker_tags t USING docker_repositories r WHERE t.repo_id = r.id AND r.name = $1 AND t.tag = $2 RETURNING t.manifest_digest"
+      )
+      .bind(&access.name)
+      .bind(&reference)
+      .fetch_optional(&state.db).await;
+
+    let digest: String = match deleted {
+      Ok(Some(row)) => row.get("manifest_digest"),
+      Ok(None) => {
+        return manifest_unknown();
+      }
+      Err(e) => {
+        return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+      }
+    };
+
+    audit::record(&state.db, access.user.as_ref(), &ip, audit::Entry {
+      action: "registry.tag.delete",
+      repo_id: Some(access.repo_id),
+      target: &format!("{}:{}", access.name, reference),
+      before: Some(json!({ "digest": digest })),
+      after: None,
+    }).await;
+
+    return (StatusCode::ACCEPTED, docker_headers(), "").into_response();
   }
+
+  let result: Result<Option<Vec<String>>, sqlx::Error> = async {
+    let mut tx = state.db.begin().await?;
+
+    let Some(row) = sqlx
+      ::query(
+        "SELECT r.id, m.media_type, m.content FROM docker_manifests m JOIN docker_repositories r ON m.repo_id = r.id WHERE r.name = $1 AND m.digest = $2 FOR UPDATE OF m"
+      )
+      .bind(&access.name)
+      .bind(&reference)
+      .fetch_optional(&mut *tx).await? else {
+      return Ok(None);
+    };
+    let docker_repo_id: Uuid = row.get("id");
+    let media_type: String = row.get("media_type");
+    let content: Value = row.get("content");
+
+    let tags: Vec<String> = sqlx
+      ::query("DELETE FROM docker_tags WHERE repo_id = $1 AND manifest_digest = $2 RETURNING tag")
+      .bind(docker_repo_id)
+      .bind(&reference)
+      .fetch_all(&mut *tx).await?
+      .iter()
+      .map(|r| r.get("tag"))
+      .collect();
+
+    sqlx::query("DELETE FROM docker_manifests WHERE repo_id = $1 AND digest = $2").bind(docker_repo_id).bind(&reference).execute(&mut *tx).await?;
+
+    // Platforms of a deleted index go with it, unless tagged or listed by another index.
+    if is_index(&media_type) {
+      let children: Vec<String> = content["manifests"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["digest"].as_str().map(String::from))
+        .collect();
+      sqlx
+        ::query(
+          r#"
+          DELETE FROM docker_manifests m
+          WHERE m.repo_id = $1 AND m.digest = ANY($2)
+            AND NOT EXISTS (SELECT 1 FROM docker_tags t WHERE t.repo_id = m.repo_id AND t.manifest_digest = m.digest)
+            AND NOT EXISTS (
+              SELECT 1 FROM docker_manifests i
+              WHERE i.repo_id = m.repo_id AND i.content->'manifests' @> jsonb_build_array(jsonb_build_object('digest', m.digest))
+            )
+          "#
+        )
+        .bind(docker_repo_id)
+        .bind(&children)
+        .execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(tags))
+  }.await;
+
+  let tags = match result {
+    Ok(Some(tags)) => tags,
+    Ok(None) => {
+      return manifest_unknown();
+    }
+    Err(e) => {
+      return (StatusCode::INTERNAL_SERVER_ERROR, docker_headers(), e.to_string()).into_response();
+    }
+  };
+
+  audit::record(&state.db, access.user.as_ref(), &ip, audit::Entry {
+    action: "registry.manifest.delete",
+    repo_id: Some(access.repo_id),
+    target: &format!("{}@{}", access.name, reference),
+    before: Some(json!({ "digest": reference, "tags": tags })),
+    after: None,
+  }).await;
+
+  tracing::info!("🗑️ Manifest deleted: {}@{}", access.name, reference);
+  (StatusCode::ACCEPTED, docker_headers(), "").into_response()
 }
 
 pub async fn head_blob(
@@ -376,25 +1718,55 @@ pub async fn head_blob(
 ) -> impl IntoResponse {
   head_blob_logic(state, headers, name, digest).await
 }
-pub async fn start_upload(State(state): State<Arc<AppState>>, headers: HeaderMap, Path(name): Path<String>) -> impl IntoResponse {
-  start_upload_logic(state, headers, name).await
+pub async fn get_blob(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((name, digest)): Path<(String, String)>
+) -> impl IntoResponse {
+  get_blob_logic(state, headers, name, digest).await
+}
+pub async fn list_tags(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path(name): Path<String>,
+  Query(page): Query<PageQuery>
+) -> impl IntoResponse {
+  list_tags_logic(state, headers, name, page).await
+}
+pub async fn start_upload(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path(name): Path<String>,
+  Query(mount): Query<MountQuery>
+) -> impl IntoResponse {
+  start_upload_logic(state, headers, name, mount).await
 }
 pub async fn complete_upload(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
   Path((name, uuid)): Path<(String, String)>,
   Query(query): Query<DigestQuery>,
-  body: axum::body::Body
+  body: Body
 ) -> impl IntoResponse {
   complete_upload_logic(state, headers, name, uuid, query.digest, body).await
 }
+pub async fn patch_upload(State(state): State<Arc<AppState>>, headers: HeaderMap, Path((name, uuid)): Path<(String, String)>, body: Body) -> impl IntoResponse {
+  patch_upload_logic(state, headers, name, uuid, body).await
+}
+pub async fn upload_status(State(state): State<Arc<AppState>>, headers: HeaderMap, Path((name, uuid)): Path<(String, String)>) -> impl IntoResponse {
+  upload_status_logic(state, headers, name, uuid).await
+}
+pub async fn cancel_upload(State(state): State<Arc<AppState>>, headers: HeaderMap, Path((name, uuid)): Path<(String, String)>) -> impl IntoResponse {
+  cancel_upload_logic(state, headers, name, uuid).await
+}
 pub async fn put_manifest(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
+  ip: ClientIp,
   Path((name, tag)): Path<(String, String)>,
   body: Bytes
 ) -> impl IntoResponse {
-  put_manifest_logic(state, headers, name, tag, body).await
+  put_manifest_logic(state, headers, ip, name, tag, body).await
 }
 pub async fn get_manifest(
   State(state): State<Arc<AppState>>,
@@ -403,6 +1775,22 @@ pub async fn get_manifest(
 ) -> impl IntoResponse {
   get_manifest_logic(state, headers, name, reference, false).await
 }
+pub async fn delete_manifest(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  ip: ClientIp,
+  Path((name, reference)): Path<(String, String)>
+) -> impl IntoResponse {
+  delete_manifest_logic(state, headers, ip, name, reference).await
+}
+pub async fn referrers(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((name, digest)): Path<(String, String)>,
+  Query(query): Query<ReferrersQuery>
+) -> impl IntoResponse {
+  referrers_logic(state, headers, name, digest, query).await
+}
 pub async fn head_manifest(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
@@ -418,29 +1806,68 @@ pub async fn head_blob_ns(
 ) -> impl IntoResponse {
   head_blob_logic(state, headers, format!("{}/{}", ns, img), digest).await
 }
+pub async fn get_blob_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img, digest)): Path<(String, String, String)>
+) -> impl IntoResponse {
+  get_blob_logic(state, headers, format!("{}/{}", ns, img), digest).await
+}
+pub async fn list_tags_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img)): Path<(String, String)>,
+  Query(page): Query<PageQuery>
+) -> impl IntoResponse {
+  list_tags_logic(state, headers, format!("{}/{}", ns, img), page).await
+}
 pub async fn start_upload_ns(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
-  Path((ns, img)): Path<(String, String)>
+  Path((ns, img)): Path<(String, String)>,
+  Query(mount): Query<MountQuery>
 ) -> impl IntoResponse {
-  start_upload_logic(state, headers, format!("{}/{}", ns, img)).await
+  start_upload_logic(state, headers, format!("{}/{}", ns, img), mount).await
 }
 pub async fn complete_upload_ns(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
   Path((ns, img, uuid)): Path<(String, String, String)>,
   Query(query): Query<DigestQuery>,
-  body: axum::body::Body
+  body: Body
 ) -> impl IntoResponse {
   complete_upload_logic(state, headers, format!("{}/{}", ns, img), uuid, query.digest, body).await
 }
+pub async fn patch_upload_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img, uuid)): Path<(String, String, String)>,
+  body: Body
+) -> impl IntoResponse {
+  patch_upload_logic(state, headers, format!("{}/{}", ns, img), uuid, body).await
+}
+pub async fn upload_status_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img, uuid)): Path<(String, String, String)>
+) -> impl IntoResponse {
+  upload_status_logic(state, headers, format!("{}/{}", ns, img), uuid).await
+}
+pub async fn cancel_upload_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img, uuid)): Path<(String, String, String)>
+) -> impl IntoResponse {
+  cancel_upload_logic(state, headers, format!("{}/{}", ns, img), uuid).await
+}
 pub async fn put_manifest_ns(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
+  ip: ClientIp,
   Path((ns, img, tag)): Path<(String, String, String)>,
   body: Bytes
 ) -> impl IntoResponse {
-  put_manifest_logic(state, headers, format!("{}/{}", ns, img), tag, body).await
+  put_manifest_logic(state, headers, ip, format!("{}/{}", ns, img), tag, body).await
 }
 pub async fn get_manifest_ns(
   State(state): State<Arc<AppState>>,
@@ -449,6 +1876,22 @@ pub async fn get_manifest_ns(
 ) -> impl IntoResponse {
   get_manifest_logic(state, headers, format!("{}/{}", ns, img), reference, false).await
 }
+pub async fn delete_manifest_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  ip: ClientIp,
+  Path((ns, img, reference)): Path<(String, String, String)>
+) -> impl IntoResponse {
+  delete_manifest_logic(state, headers, ip, format!("{}/{}", ns, img), reference).await
+}
+pub async fn referrers_ns(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Path((ns, img, digest)): Path<(String, String, String)>,
+  Query(query): Query<ReferrersQuery>
+) -> impl IntoResponse {
+  referrers_logic(state, headers, format!("{}/{}", ns, img), digest, query).await
+}
 pub async fn head_manifest_ns(
   State(state): State<Arc<AppState>>,
   headers: HeaderMap,
@@ -457,64 +1900,234 @@ pub async fn head_manifest_ns(
   get_manifest_logic(state, headers, format!("{}/{}", ns, img), reference, true).await
 }
 
-pub async fn list_repo_images(State(state): State<Arc<AppState>>, Path(repo_name): Path<String>) -> impl IntoResponse {
+/// Layer count and compressed size of an image manifest.
+fn layer_stats(content: &Value) -> (usize, i64) {
+  let layers = content["layers"].as_array().map(|l| l.as_slice()).unwrap_or_default();
+  (layers.len(), layers.iter().map(|l| l["size"].as_i64().unwrap_or(0)).sum())
+}
+
+/// Coarse kind of an attached artifact, from its artifact type.
+fn referrer_kind(artifact_type: &str) -> &'static str {
+  let t = artifact_type.to_ascii_lowercase();
+  if t.contains("spdx") || t.contains("cyclonedx") || t.contains("syft") || t.contains("sbom") {
+    "sbom"
+  } else if t.contains("in-toto") || t.contains("provenance") || t.contains("attestation") {
+    "attestation"
+  } else if t.contains("signature") || t.contains("sig") {
+    "signature"
+  } else {
+    "other"
+  }
+}
+
+/// Artifacts attached to a manifest: referrers through `subject`, plus the `sha256-<hex>.sig|.att|.sbom`
+/// tags cosign uses with registries that predate the referrers API. `rows` are the repository's
+/// manifests joined with their tags, as loaded by `list_repo_images`.
+fn attached_artifacts(rows: &[sqlx::postgres::PgRow], docker_repo_id: Uuid, digest: &str) -> Vec<Value> {
+  let in_image = |r: &&sqlx::postgres::PgRow| r.get::<Uuid, _>("docker_repo_id") == docker_repo_id;
+
+  let mut artifacts: Vec<Value> = Vec::new();
+  let mut seen = std::collections::HashSet::new();
+  for r in rows.iter().filter(in_image) {
+    if r.get::<Option<String>, _>("subject_digest").as_deref() != Some(digest) {
+      continue;
+    }
+    let referrer: String = r.get("digest");
+    if seen.insert(referrer.clone()) {
+      let artifact_type: Option<String> = r.get("artifact_type");
+      let kind = referrer_kind(artifact_type.as_deref().unwrap_or(""));
+      artifacts.push(json!({ "digest": referrer, "artifact_type": artifact_type, "kind": kind }));
+    }
+  }
+
+  let prefix = format!("{}.", digest.replacen(':', "-", 1));
+  for r in rows.iter().filter(in_image) {
+    let Some(tag) = r.get::<Option<String>, _>("tag").filter(|t| t.starts_with(&prefix)) else {
+      continue;
+    };
+    let kind = match &tag[prefix.len()..] {
+      "sig" => "signature",
+      "att" => "attestation",
+      "sbom" => "sbom",
+      _ => continue,
+    };
+    artifacts.push(json!({ "digest": r.get::<String, _>("digest"), "artifact_type": null, "kind": kind, "tag": tag }));
+  }
+  artifacts
+}
+
+pub async fn list_repo_images(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> impl IntoResponse {
+  // Every manifest of the repository's images, once per tag: untagged ones are the platforms of
+  // multi-arch images and the artifacts attached to them.
   let rows = sqlx
     ::query(
       r#"
-    SELECT t.tag, m.digest, t.updated_at, m.content, r.name as image_name
-    FROM docker_tags t
-    JOIN docker_repositories r ON t.repo_id = r.id
-    JOIN docker_manifests m ON t.manifest_digest = m.digest
-    WHERE r.name = $1 OR r.name LIKE $1 || '/%'
-    ORDER BY t.updated_at DESC
+    SELECT r.id as docker_repo_id, r.name as image_name, m.digest, m.media_type, m.content, m.subject_digest, m.artifact_type,
+           t.tag, t.updated_at
+    FROM docker_repositories r
+    JOIN docker_manifests m ON m.repo_id = r.id
+    LEFT JOIN docker_tags t ON t.repo_id = m.repo_id AND t.manifest_digest = m.digest
+    WHERE r.repository_id = $1
+    ORDER BY t.updated_at DESC NULLS LAST, m.created_at
     "#
     )
-    .bind(&repo_name)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .unwrap_or_default();
 
-  let images: Vec<Value> = rows
+  let manifests: std::collections::HashMap<(Uuid, String), Value> = rows
     .iter()
-    .map(|r| {
-      let content: Value = r.get("content");
-      let empty_vec = vec![];
-      let layers = content["layers"].as_array().unwrap_or(&empty_vec);
-      let size: i64 = layers
-        .iter()
-        .map(|l| l["size"].as_i64().unwrap_or(0))
-        .sum();
+    .map(|r| ((r.get("docker_repo_id"), r.get("digest")), r.get("content")))
+    .collect();
+
+  let mut images: Vec<Value> = Vec::new();
+  for r in rows.iter().filter(|r| r.get::<Option<String>, _>("tag").is_some()) {
+    let content: Value = r.get("content");
+    let media_type: String = r.get("media_type");
+    let docker_repo_id: Uuid = r.get("docker_repo_id");
 
+    // Multi-arch images: one entry per platform, the image size being their sum.
+    let mut platforms: Vec<Value> = Vec::new();
+    if is_index(&media_type) {
+      for entry in index_platforms(&content) {
+        let Some(digest) = entry["digest"].as_str() else {
+          continue;
+        };
+        let (layers_count, size) = manifests.get(&(docker_repo_id, digest.to_string())).map(layer_stats).unwrap_or((0, 0));
+        platforms.push(
+          json!({
+          "digest": digest,
+          "os": entry["platform"]["os"],
+          "architecture": entry["platform"]["architecture"],
+          "variant": entry["platform"]["variant"],
+          "size": size,
+          "layers_count": layers_count
+        })
+        );
+      }
+    }
+
+    let digest: String = r.get("digest");
+    let artifacts = attached_artifacts(&rows, docker_repo_id, &digest);
+    let has = |kind: &str| artifacts.iter().any(|a| a["kind"] == kind);
+    let (signed, has_sbom) = (has("signature"), has("sbom"));
+
+    let (layers_count, size) = if is_index(&media_type) {
+      (0, platforms.iter().map(|p| p["size"].as_i64().unwrap_or(0)).sum())
+    } else {
+      layer_stats(&content)
+    };
+
+    images.push(
       json!({
       "image_name": r.get::<String, _>("image_name"),
-      "tag": r.get::<String, _>("tag"),
-      "digest": r.get::<String, _>("digest"),
-      "updated_at": r.get::<chrono::DateTime<chrono::Utc>, _>("updated_at").to_rfc3339(),
+      "tag": r.get::<Option<String>, _>("tag"),
+      "digest": digest,
+      "media_type": media_type,
+      "updated_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("updated_at").map(|t| t.to_rfc3339()),
       "size": size,
-      "layers_count": layers.len(),
+      "layers_count": layers_count,
+      "platforms": platforms,
+      "signed": signed,
+      "has_sbom": has_sbom,
+      "referrers": artifacts,
       "content": content
     })
-    })
-    .collect();
+    );
+  }
 
   Json(images)
 }
 
+#[derive(Deserialize)]
+pub struct TagQuery {
+  /// Full image name, e.g. `org/repo/api`.
+  image: String,
+  tag: String,
+}
+
+/// Removes a tag from one of the repository's images. Requires the registry `delete` permission.
+pub async fn delete_image_tag(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  ip: ClientIp,
+  guard: RepoReadGuard,
+  Path(_repo_name): Path<String>,
+  Query(q): Query<TagQuery>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  if q.image != guard.repo_name && !q.image.starts_with(&format!("{}/", guard.repo_name)) {
+    return Err((StatusCode::NOT_FOUND, "Image not found".to_string()));
+  }
+  let access = check_docker_access(&state, &q.image, &headers, RegistryAction::Delete).await.map_err(|(status, _, msg)| (status, msg))?;
+
+  let digest: String = sqlx
+    ::query(
+      "DELETE FROM docker_tags t USING docker_repositories r WHERE t.repo_id = r.id AND r.name = $1 AND t.tag = $2 RETURNING t.manifest_digest"
+    )
+    .bind(&access.name)
+    .bind(&q.tag)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Tag not found".to_string()))?
+    .get("manifest_digest");
+
+  audit::record(&state.db, access.user.as_ref(), &ip, audit::Entry {
+    action: "registry.tag.delete",
+    repo_id: Some(access.repo_id),
+    target: &format!("{}:{}", access.name, q.tag),
+    before: Some(json!({ "digest": digest })),
+    after: None,
+  }).await;
+
+  Ok(Json(json!({ "status": "deleted", "image": access.name, "tag": q.tag })))
+}
+
 pub async fn inspect_image_config(
   State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
   Path((_repo_name, digest)): Path<(String, String)>
 ) -> impl IntoResponse {
+  // Only inspect a manifest stored under one of this repository's images.
   let row = sqlx
-    ::query("SELECT content FROM docker_manifests WHERE digest = $1")
+    ::query(
+      r#"
+    SELECT m.repo_id, m.media_type, m.content FROM docker_manifests m
+    JOIN docker_repositories r ON m.repo_id = r.id
+    WHERE m.digest = $1 AND r.repository_id = $2
+    LIMIT 1
+    "#
+    )
     .bind(&digest)
+    .bind(guard.repo_id)
     .fetch_optional(&state.db).await
     .unwrap_or(None);
 
   if let Some(r) = row {
-    let manifest: Value = r.get("content");
+    let docker_repo_id: Uuid = r.get("repo_id");
+    let mut manifest: Value = r.get("content");
+    // A multi-arch image is inspected through its first platform.
+    if is_index(r.get::<String, _>("media_type").as_str()) {
+      let first = index_platforms(&manifest).find_map(|m| m["digest"].as_str().map(String::from));
+      if let Some(child) = first {
+        manifest = sqlx
+          ::query("SELECT content FROM docker_manifests WHERE repo_id = $1 AND digest = $2")
+          .bind(docker_repo_id)
+          .bind(&child)
+          .fetch_optional(&state.db).await
+          .unwrap_or(None)
+          .map(|c| c.get("content"))
+          .unwrap_or(Value::Null);
+      }
+    }
     if let Some(config_digest) = manifest["config"]["digest"].as_str() {
       let clean_sha = config_digest.strip_prefix("sha256:").unwrap_or(config_digest);
 
-      let blob_row = sqlx::query("SELECT hash FROM blobs WHERE sha256 = $1").bind(clean_sha).fetch_optional(&state.db).await.unwrap_or(None);
+      let blob_row = sqlx
+        ::query("SELECT b.hash FROM blobs b JOIN docker_repository_blobs l ON l.sha256 = b.sha256 WHERE l.repo_id = $1 AND b.sha256 = $2 LIMIT 1")
+        .bind(docker_repo_id)
+        .bind(clean_sha)
+        .fetch_optional(&state.db).await
+        .unwrap_or(None);
 
       if let Some(br) = blob_row {
         let hash: String = br.get("hash");
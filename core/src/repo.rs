@@ -1,12 +1,12 @@
-use axum::{ body::Body, http::{ header, StatusCode, HeaderMap }, response::IntoResponse, extract::{ Path, State }, Json };
+use axum::{ body::Body, http::{ header, StatusCode }, response::IntoResponse, extract::{ Path, State }, Json };
 use serde::Deserialize;
 use serde_json::{ json, Value };
 use std::sync::Arc;
 use std::collections::HashMap;
 use sqlx::Row;
+use once_cell::sync::Lazy;
 use uuid::Uuid;
-use crate::{ diff, state::AppState, auth::{ AuthUser, RepoReadGuard, RepoWriteGuard, RepoAdminGuard, RepoPerm, Claims } };
-use base64::{ Engine as _, engine::general_purpose };
+use crate::{ audit::{ self, ClientIp }, diff, org, registry, validation, state::AppState, auth::{ AuthUser, RepoReadGuard, RepoWriteGuard, RepoAdminGuard, RepoPerm, REPO_FULL_NAME_SQL } };
 use crate::mirror;
 use crate::pipeline; 
 
@@ -15,6 +15,7 @@ pub struct CreateRepoRequest {
   pub name: String,
   pub description: Option<String>,
   pub is_public: bool,
+  pub org: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -39,41 +40,27 @@ pub struct MergeRequest {
   pub decisions: HashMap<String, String>,
 }
 
+#[derive(Deserialize)]
+pub struct ForkRepoRequest {
+  pub name: String,
+  pub description: Option<String>,
+  pub is_public: Option<bool>,
+  pub org: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct CompareRequest {
   pub local_hash: String,
   pub remote_hash: String,
 }
 
-pub async fn list_repos(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Json<Value> {
-  let user_id = if let Some(auth_header) = headers.get("Authorization") {
-    if let Ok(str_val) = auth_header.to_str() {
-      if str_val.starts_with("Bearer ") {
-        let token = &str_val[7..];
-        let parts: Vec<&str> = token.split('.').collect();
-        if parts.len() == 3 {
-          if let Ok(decoded) = general_purpose::URL_SAFE_NO_PAD.decode(parts[1]) {
-            if let Ok(claims) = serde_json::from_slice::<Claims>(&decoded) { Uuid::parse_str(&claims.sub).ok() } else { None }
-          } else {
-            None
-          }
-        } else {
-          None
-        }
-      } else {
-        None
-      }
-    } else {
-      None
-    }
-  } else {
-    None
-  };
+pub async fn list_repos(State(state): State<Arc<AppState>>, auth: Option<AuthUser>) -> Json<Value> {
+  let user_id = auth.map(|a| a.id);
 
-  let query =
+  let query = format!(
     r#"
         SELECT 
-            r.id, r.name, r.description, r.is_public,
+            r.id, {full} as full_name, o.name as org_name, r.description, r.is_public,
             to_char(COALESCE(MAX(c.created_at), r.created_at), 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as last_updated,
             (
                 SELECT split_part(cf.file_path, '.', 2) as ext
@@ -81,20 +68,32 @@ pub async fn list_repos(State(state): State<Arc<AppState>>, headers: HeaderMap)
                 JOIN commits c2 ON cf.commit_id = c2.id
                 WHERE c2.repo_id = r.id
                 GROUP BY ext ORDER BY COUNT(*) DESC LIMIT 1
-            ) as primary_extension
+            ) as primary_extension,
+            COALESCE(uo.name || '/', '') || u.name as forked_from
         FROM repositories r
+        LEFT JOIN organizations o ON r.org_id = o.id
+        LEFT JOIN repositories u ON r.forked_from = u.id
+        LEFT JOIN organizations uo ON u.org_id = uo.id
         LEFT JOIN commits c ON r.id = c.repo_id
         -- Jointure pour vérifier les permissions
         LEFT JOIN repository_members rm ON r.id = rm.repo_id AND rm.user_id = $1
+        LEFT JOIN organization_members om ON r.org_id = om.org_id AND om.user_id = $1
         WHERE 
-            r.is_public = TRUE 
-            OR 
-            rm.user_id IS NOT NULL -- L'utilisateur est membre (viewer/editor/admin)
-        GROUP BY r.id, r.name, r.description, r.is_public, r.created_at
+            r.deleted_at IS NULL
+            AND (
+                r.is_public = TRUE 
+                OR 
+                rm.user_id IS NOT NULL -- L'utilisateur est membre (viewer/editor/admin)
+                OR
+                om.user_id IS NOT NULL -- Rôle hérité de l'organisation
+            )
+        GROUP BY r.id, r.name, o.name, r.description, r.is_public, r.created_at, u.name, uo.name
         ORDER BY last_updated DESC
-    "#;
+    "#,
+    full = REPO_FULL_NAME_SQL
+  );
 
-  let rows = sqlx::query(query).bind(user_id).fetch_all(&state.db).await.unwrap_or_default();
+  let rows = sqlx::query(&query).bind(user_id).fetch_all(&state.db).await.unwrap_or_default();
 
   let json_repos: Vec<Value> = rows
     .iter()
@@ -112,11 +111,13 @@ pub async fn list_repos(State(state): State<Arc<AppState>>, headers: HeaderMap)
 
       json!({
             "id": r.get::<Uuid, _>("id"),
-            "name": r.get::<String, _>("name"),
+            "name": r.get::<String, _>("full_name"),
+            "org": r.get::<Option<String>, _>("org_name"),
             "description": r.get::<Option<String>, _>("description"),
             "is_public": r.get::<bool, _>("is_public"),
             "last_updated": r.get::<String, _>("last_updated"),
-            "language": lang
+            "language": lang,
+            "forked_from": r.get::<Option<String>, _>("forked_from")
         })
     })
     .collect();
@@ -125,6 +126,13 @@ pub async fn list_repos(State(state): State<Arc<AppState>>, headers: HeaderMap)
 }
 
 pub async fn create_repo(State(state): State<Arc<AppState>>, auth: AuthUser, Json(payload): Json<CreateRepoRequest>) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("repo:write")?;
+  auth.require_unconfined()?;
+
+  if !validation::is_valid_name(&payload.name) {
+    return Err((StatusCode::BAD_REQUEST, "Invalid repository name".to_string()));
+  }
+
   sqlx
     ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
     .bind(auth.id)
@@ -133,17 +141,20 @@ pub async fn create_repo(State(state): State<Arc<AppState>>, auth: AuthUser, Jso
     .execute(&state.db).await
     .ok();
 
+  let (org_id, full_name) = target_namespace(&state, payload.org.as_deref(), &payload.name, auth.id).await?;
+
   let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
   let row_res = sqlx
-    ::query("INSERT INTO repositories (name, description, is_public) VALUES ($1, $2, $3) RETURNING id")
+    ::query("INSERT INTO repositories (name, description, is_public, org_id) VALUES ($1, $2, $3, $4) RETURNING id")
     .bind(&payload.name)
     .bind(&payload.description)
     .bind(payload.is_public)
+    .bind(org_id)
     .fetch_one(&mut *tx).await;
   let row = match row_res {
     Ok(r) => r,
     Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
-      return Err((StatusCode::CONFLICT, format!("Repository '{}' already exists.", payload.name)));
+      return Err((StatusCode::CONFLICT, format!("Repository '{}' already exists.", full_name)));
     }
     Err(e) => {
       return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
@@ -159,12 +170,159 @@ pub async fn create_repo(State(state): State<Arc<AppState>>, auth: AuthUser, Jso
     .execute(&mut *tx).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+  // A real repository always wins over a redirect left behind by a rename.
+  sqlx
+    ::query("DELETE FROM repo_redirects WHERE old_name = $1")
+    .bind(&full_name)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
   tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  Ok(Json(json!({ "status": "created", "repo_id": repo_id })))
+  Ok(Json(json!({ "status": "created", "repo_id": repo_id, "name": full_name })))
 }
 
-pub async fn get_head_commit(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+/// Resolves where a new repository lives: the personal namespace, or an organization the caller
+/// belongs to. Returns the organization id and the resulting full name (`org/repo`).
+async fn target_namespace(state: &Arc<AppState>, org: Option<&str>, name: &str, user_id: Uuid) -> Result<(Option<Uuid>, String), (StatusCode, String)> {
+  match org {
+    None => {
+      let org_exists = sqlx
+        ::query("SELECT 1 FROM organizations WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&state.db).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .is_some();
+      if org_exists {
+        return Err((StatusCode::CONFLICT, format!("'{}' is the name of an organization.", name)));
+      }
+      Ok((None, name.to_string()))
+    }
+    Some(org_name) => {
+      match org::membership(&state.db, org_name, user_id).await? {
+        (org_id, Some(_)) => Ok((Some(org_id), format!("{}/{}", org_name, name))),
+        (_, None) => Err((StatusCode::FORBIDDEN, format!("You are not a member of '{}'", org_name))),
+      }
+    }
+  }
+}
+
+pub async fn fork_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  guard: RepoReadGuard,
+  Path(_repo_name): Path<String>,
+  Json(payload): Json<ForkRepoRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  auth.require_scope("repo:write")?;
+  auth.require_unconfined()?;
+
+  if !validation::is_valid_name(&payload.name) {
+    return Err((StatusCode::BAD_REQUEST, "Invalid repository name".to_string()));
+  }
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
+    .bind(auth.id)
+    .bind(&auth.username)
+    .bind(&auth.email)
+    .execute(&state.db).await
+    .ok();
+
+  let (org_id, full_name) = target_namespace(&state, payload.org.as_deref(), &payload.name, auth.id).await?;
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let source = sqlx
+    ::query("SELECT description, is_public FROM repositories WHERE id = $1")
+    .bind(guard.repo_id)
+    .fetch_one(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let description = payload.description.or_else(|| source.get::<Option<String>, _>("description"));
+  let source_public = source.get::<Option<bool>, _>("is_public").unwrap_or(false);
+  // Reading a private repository does not entitle anyone to publish it: only its admins may fork it as public.
+  if payload.is_public == Some(true) && !source_public && guard.perm < RepoPerm::Admin {
+    return Err((StatusCode::FORBIDDEN, "Forks of a private repository stay private unless you administer it".to_string()));
+  }
+  let is_public = payload.is_public.unwrap_or(source_public);
+
+  let row_res = sqlx
+    ::query("INSERT INTO repositories (name, description, is_public, forked_from, org_id) VALUES ($1, $2, $3, $4, $5) RETURNING id")
+    .bind(&payload.name)
+    .bind(&description)
+    .bind(is_public)
+    .bind(guard.repo_id)
+    .bind(org_id)
+    .fetch_one(&mut *tx).await;
+  let fork_id: Uuid = match row_res {
+    Ok(r) => r.get("id"),
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, format!("Repository '{}' already exists.", full_name)));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  };
+
+  sqlx
+    ::query("INSERT INTO repository_members (repo_id, user_id, role) VALUES ($1, $2, 'admin')")
+    .bind(fork_id)
+    .bind(auth.id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  sqlx
+    ::query("DELETE FROM repo_redirects WHERE old_name = $1")
+    .bind(&full_name)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  // Commits are duplicated so the fork owns its history, but commit_files keep pointing at the same
+  // blob hashes: no object is copied in storage. Each copy records its `upstream_id`, which is what
+  // merge requests use to find the merge base with the source.
+  let copied = sqlx
+    ::query(
+      r#"
+      INSERT INTO commits (id, repo_id, message, author_name, author_email, tree_hash, is_divergent, created_at, upstream_id)
+      SELECT gen_random_uuid(), $2, message, author_name, author_email, tree_hash, is_divergent, created_at, id
+      FROM commits WHERE repo_id = $1
+      "#
+    )
+    .bind(guard.repo_id)
+    .bind(fork_id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .rows_affected();
+
+  sqlx
+    ::query(
+      "INSERT INTO commit_files (commit_id, file_path, blob_hash) SELECT f.id, cf.file_path, cf.blob_hash FROM commits f JOIN commit_files cf ON cf.commit_id = f.upstream_id WHERE f.repo_id = $1"
+    )
+    .bind(fork_id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  sqlx
+    ::query(
+      r#"
+      UPDATE commits f SET parent_id = p.id
+      FROM commits s, commits p
+      WHERE f.repo_id = $1 AND s.id = f.upstream_id AND p.repo_id = $1 AND p.upstream_id = s.parent_id
+      "#
+    )
+    .bind(fork_id)
+    .execute(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  tracing::info!("🍴 Forked {} into {} ({} commits)", guard.repo_name, full_name, copied);
+
+  Ok(Json(json!({ "status": "forked", "repo_id": fork_id, "name": full_name, "forked_from": guard.repo_name })))
+}
+
+pub async fn get_head_commit(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
   let row = sqlx
     ::query(
       r#"
@@ -183,6 +341,15 @@ pub async fn get_head_commit(State(state): State<Arc<AppState>>, guard: RepoRead
     .fetch_optional(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+  let forked_from: Option<String> = sqlx
+    ::query(
+      "SELECT COALESCE(uo.name || '/', '') || u.name AS name FROM repositories r JOIN repositories u ON r.forked_from = u.id LEFT JOIN organizations uo ON u.org_id = uo.id WHERE r.id = $1"
+    )
+    .bind(guard.repo_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("name"));
+
   let access = match guard.perm {
     RepoPerm::Admin => "admin",
     RepoPerm::Write => "editor",
@@ -197,30 +364,39 @@ pub async fn get_head_commit(State(state): State<Arc<AppState>>, guard: RepoRead
           json!({
         "status": "active",
         "repo_id": guard.repo_id,
+        "name": guard.repo_name,
         "commit_id": r.get::<Uuid, _>("id"),
         "message": r.get::<String, _>("message"),
         "date": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at"),
-        "access_level": access
+        "access_level": access,
+        "forked_from": forked_from
       })
         )
       ),
     None => Ok(Json(json!({
         "status": "empty",
         "repo_id": guard.repo_id,
+        "name": guard.repo_name,
         "commit_id": null, 
         "message": "Repository is void",
-        "access_level": access
+        "access_level": access,
+        "forked_from": forked_from
       }))),
   }
 }
 
-pub async fn list_commit_files(State(state): State<Arc<AppState>>, Path((_repo_name, commit_id_str)): Path<(String, String)>) -> Result<Json<Value>, String> {
+pub async fn list_commit_files(
+  State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
+  Path((_repo_name, commit_id_str)): Path<(String, String)>
+) -> Result<Json<Value>, String> {
   let commit_uuid = Uuid::parse_str(&commit_id_str).map_err(|_| "Invalid UUID")?;
   let rows = sqlx
     ::query(r#"SELECT cf.file_path, b.hash, b.size, b.mime_type
-           FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash
-           WHERE cf.commit_id = $1 ORDER BY cf.file_path ASC"#)
+           FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash JOIN commits c ON cf.commit_id = c.id
+           WHERE cf.commit_id = $1 AND c.repo_id = $2 ORDER BY cf.file_path ASC"#)
     .bind(commit_uuid)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .map_err(|e| e.to_string())?;
 
@@ -237,20 +413,19 @@ pub async fn list_commit_files(State(state): State<Arc<AppState>>, Path((_repo_n
 
 pub async fn create_commit(
   State(state): State<Arc<AppState>>,
-  _guard: RepoWriteGuard,
-  Path(repo_name): Path<String>,
+  guard: RepoWriteGuard,
+  Path(_repo_name): Path<String>,
   Json(payload): Json<CreateCommitRequest>
 ) -> Result<Json<Value>, (StatusCode, String)> {
   let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  let repo_row = sqlx
-    ::query("SELECT id FROM repositories WHERE name = $1")
-    .bind(&repo_name)
-    .fetch_optional(&mut *tx).await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-    .ok_or((StatusCode::NOT_FOUND, "Repo not found".to_string()))?;
+  let repo_id = guard.0.repo_id;
 
-  let repo_id: Uuid = repo_row.get("id");
+  let hashes: Vec<String> = payload.files
+    .iter()
+    .map(|f| f.hash.clone())
+    .collect();
+  check_repo_blobs(&mut tx, repo_id, &hashes).await?;
 
   let head_row = sqlx
     ::query("SELECT id FROM commits WHERE repo_id = $1 ORDER BY created_at DESC LIMIT 1")
@@ -303,6 +478,8 @@ pub async fn create_commit(
       .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
   }
 
+  clear_pending_uploads(&mut tx, repo_id, &hashes).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
   tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
   mirror::trigger_sync_background(state.clone(), repo_id).await;
@@ -317,7 +494,40 @@ pub async fn create_commit(
   Ok(Json(json!({ "status": "success", "commit_id": commit_id, "is_divergent": is_divergent })))
 }
 
-pub async fn get_file_content(State(state): State<Arc<AppState>>, Path((_repo_name, commit_id_str, file_path)): Path<(String, String, String)>) -> impl IntoResponse {
+/// Blobs are shared across repositories, so a commit may only reference content uploaded to this
+/// repository or already in its history; knowing a hash must not give access to another repository's file.
+async fn check_repo_blobs(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, repo_id: Uuid, hashes: &[String]) -> Result<(), (StatusCode, String)> {
+  let unknown: Option<String> = sqlx
+    ::query(
+      r#"
+      SELECT h FROM unnest($2::text[]) AS h
+      WHERE NOT EXISTS (SELECT 1 FROM pending_uploads p WHERE p.repo_id = $1 AND p.blob_hash = h)
+        AND NOT EXISTS (SELECT 1 FROM commit_files cf JOIN commits c ON cf.commit_id = c.id WHERE c.repo_id = $1 AND cf.blob_hash = h)
+      LIMIT 1
+      "#
+    )
+    .bind(repo_id)
+    .bind(hashes)
+    .fetch_optional(&mut **tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("h"));
+
+  match unknown {
+    Some(hash) => Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Blob {} was not uploaded to this repository", hash))),
+    None => Ok(()),
+  }
+}
+
+async fn clear_pending_uploads(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, repo_id: Uuid, hashes: &[String]) -> Result<(), sqlx::Error> {
+  sqlx::query("DELETE FROM pending_uploads WHERE repo_id = $1 AND blob_hash = ANY($2)").bind(repo_id).bind(hashes).execute(&mut **tx).await?;
+  Ok(())
+}
+
+pub async fn get_file_content(
+  State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
+  Path((_repo_name, commit_id_str, file_path)): Path<(String, String, String)>
+) -> impl IntoResponse {
   let commit_uuid = match Uuid::parse_str(&commit_id_str) {
     Ok(u) => u,
     Err(_) => {
@@ -326,9 +536,12 @@ pub async fn get_file_content(State(state): State<Arc<AppState>>, Path((_repo_na
   };
 
   let row_opt = sqlx
-    ::query("SELECT b.hash, b.mime_type FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash WHERE cf.commit_id = $1 AND cf.file_path = $2")
+    ::query(
+      "SELECT b.hash, b.mime_type FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash JOIN commits c ON cf.commit_id = c.id WHERE cf.commit_id = $1 AND cf.file_path = $2 AND c.repo_id = $3"
+    )
     .bind(commit_uuid)
     .bind(file_path)
+    .bind(guard.repo_id)
     .fetch_optional(&state.db).await
     .unwrap_or(None);
 
@@ -354,11 +567,19 @@ pub async fn get_file_content(State(state): State<Arc<AppState>>, Path((_repo_na
   (h, Body::from(content)).into_response()
 }
 
-pub async fn get_file_metadata(State(state): State<Arc<AppState>>, Path((_repo_name, commit_id_str, file_path)): Path<(String, String, String)>) -> Result<Json<Value>, String> {
+pub async fn get_file_metadata(
+  State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
+  Path((_repo_name, commit_id_str, file_path)): Path<(String, String, String)>
+) -> Result<Json<Value>, String> {
+  let commit_uuid = Uuid::parse_str(&commit_id_str).map_err(|_| "Invalid UUID")?;
   let row = sqlx
-    ::query("SELECT b.metadata, b.size, b.mime_type FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash WHERE cf.commit_id = $1 AND cf.file_path = $2")
-    .bind(Uuid::parse_str(&commit_id_str).unwrap())
+    ::query(
+      "SELECT b.metadata, b.size, b.mime_type FROM commit_files cf JOIN blobs b ON cf.blob_hash = b.hash JOIN commits c ON cf.commit_id = c.id WHERE cf.commit_id = $1 AND cf.file_path = $2 AND c.repo_id = $3"
+    )
+    .bind(commit_uuid)
     .bind(file_path)
+    .bind(guard.repo_id)
     .fetch_optional(&state.db).await
     .map_err(|e| e.to_string())?
     .ok_or("Not found")?;
@@ -370,7 +591,7 @@ pub async fn get_file_metadata(State(state): State<Arc<AppState>>, Path((_repo_n
     })))
 }
 
-pub async fn list_repo_commits(State(state): State<Arc<AppState>>, Path(repo_name): Path<String>) -> Result<Json<Value>, String> {
+pub async fn list_repo_commits(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, String> {
   let rows = sqlx
     ::query(
       r#"
@@ -380,13 +601,12 @@ pub async fn list_repo_commits(State(state): State<Arc<AppState>>, Path(repo_nam
         (SELECT COUNT(*) FROM commit_files cf WHERE cf.commit_id = c.id) as file_count,
         u.avatar_url
       FROM commits c 
-      JOIN repositories r ON c.repo_id = r.id 
       LEFT JOIN users u ON c.author_name = u.username -- Tentative de lier à un avatar réel
-      WHERE r.name = $1 
+      WHERE c.repo_id = $1
       ORDER BY c.created_at DESC
       "#
     )
-    .bind(&repo_name)
+    .bind(guard.repo_id)
     .fetch_all(&state.db).await
     .map_err(|e| e.to_string())?;
 
@@ -416,49 +636,84 @@ pub async fn list_repo_commits(State(state): State<Arc<AppState>>, Path(repo_nam
 pub struct AddMemberRequest {
   pub email: String,
   pub role: String,
+  /// Explicit registry permissions (`pull`, `push`, `delete`); omitted to follow the role.
+  pub registry: Option<Vec<String>>,
 }
 
 pub async fn add_repo_member(
   State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
   guard: RepoAdminGuard,
   Path(_repo_name): Path<String>,
   Json(payload): Json<AddMemberRequest>
 ) -> Result<Json<Value>, (StatusCode, String)> {
   let user_row = sqlx
-    ::query("SELECT id FROM users WHERE email = $1")
+    ::query("SELECT id, robot_repo_id FROM users WHERE email = $1")
     .bind(&payload.email)
     .fetch_optional(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  let user_id = match user_row {
-    Some(row) => row.get::<Uuid, _>("id"),
+  let (user_id, robot_repo) = match user_row {
+    Some(row) => (row.get::<Uuid, _>("id"), row.get::<Option<Uuid>, _>("robot_repo_id")),
     None => {
       return Err((StatusCode::NOT_FOUND, "User not found (must login once)".to_string()));
     }
   };
 
+  if let Some(unknown) = payload.registry.iter().flatten().find(|p| !registry::REGISTRY_PERMS.contains(&p.as_str())) {
+    return Err((StatusCode::BAD_REQUEST, format!("Unknown registry permission '{}'", unknown)));
+  }
+
+  // Robots stay confined to the repository they were created in, and never administer it.
+  if let Some(robot_repo) = robot_repo {
+    if robot_repo != guard.0.repo_id {
+      return Err((StatusCode::BAD_REQUEST, "Robot accounts belong to another repository".to_string()));
+    }
+    if payload.role == "admin" {
+      return Err((StatusCode::BAD_REQUEST, "Robot accounts cannot be admins".to_string()));
+    }
+  }
+
+  let previous = sqlx
+    ::query("SELECT role::text, registry_perms FROM repository_members WHERE repo_id = $1 AND user_id = $2")
+    .bind(guard.0.repo_id)
+    .bind(user_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| json!({ "user": payload.email, "role": r.get::<String, _>("role"), "registry": r.get::<Option<Vec<String>>, _>("registry_perms") }));
+
   sqlx
     ::query(
       r#"
-        INSERT INTO repository_members (repo_id, user_id, role) 
-        VALUES ($1, $2, $3::repo_role_enum)
-        ON CONFLICT (repo_id, user_id) DO UPDATE SET role = EXCLUDED.role
+        INSERT INTO repository_members (repo_id, user_id, role, registry_perms) 
+        VALUES ($1, $2, $3::repo_role_enum, $4)
+        ON CONFLICT (repo_id, user_id) DO UPDATE SET role = EXCLUDED.role, registry_perms = EXCLUDED.registry_perms
         "#
     )
     .bind(guard.0.repo_id)
     .bind(user_id)
     .bind(&payload.role)
+    .bind(&payload.registry)
     .execute(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  Ok(Json(json!({ "status": "member_added", "user": payload.email, "role": payload.role })))
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.member.add",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: previous,
+    after: Some(json!({ "user": payload.email, "role": payload.role, "registry": payload.registry })),
+  }).await;
+
+  Ok(Json(json!({ "status": "member_added", "user": payload.email, "role": payload.role, "registry": payload.registry })))
 }
 
 pub async fn list_repo_members(State(state): State<Arc<AppState>>, guard: RepoReadGuard, Path(_repo_name): Path<String>) -> Result<Json<Value>, String> {
   let rows = sqlx
     ::query(
       r#"
-        SELECT u.username, u.email, rm.role::text, u.avatar_url
+        SELECT u.username, u.email, rm.role::text, rm.registry_perms, u.avatar_url
         FROM repository_members rm
         JOIN users u ON rm.user_id = u.id
         WHERE rm.repo_id = $1
@@ -477,6 +732,7 @@ pub async fn list_repo_members(State(state): State<Arc<AppState>>, guard: RepoRe
         "username": r.get::<String, _>("username"),
         "email": r.get::<String, _>("email"),
         "role": r.get::<String, _>("role"), 
+        "registry": r.get::<Option<Vec<String>>, _>("registry_perms"),
         "avatar": r.get::<Option<String>, _>("avatar_url")
     })
     )
@@ -485,25 +741,26 @@ pub async fn list_repo_members(State(state): State<Arc<AppState>>, guard: RepoRe
   Ok(Json(json!(members)))
 }
 
-pub async fn merge_commits(State(state): State<Arc<AppState>>, _guard: RepoWriteGuard, Path(repo_name): Path<String>, Json(payload): Json<MergeRequest>) -> Result<Json<Value>, (StatusCode, String)> {
+pub async fn merge_commits(State(state): State<Arc<AppState>>, guard: RepoWriteGuard, Path(_repo_name): Path<String>, Json(payload): Json<MergeRequest>) -> Result<Json<Value>, (StatusCode, String)> {
   let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  let repo_row = sqlx
-    ::query("SELECT id FROM repositories WHERE name = $1")
-    .bind(&repo_name)
-    .fetch_optional(&mut *tx).await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-  let repo_id: Uuid = match repo_row {
-    Some(r) => r.get("id"),
-    None => {
-      return Err((StatusCode::NOT_FOUND, "Repo not found".to_string()));
-    }
-  };
+  let repo_id = guard.0.repo_id;
 
   let remote_uuid = Uuid::parse_str(&payload.remote_commit_id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Remote ID".to_string()))?;
   let local_uuid = Uuid::parse_str(&payload.divergent_commit_id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Local ID".to_string()))?;
 
+  let owned: i64 = sqlx
+    ::query("SELECT COUNT(*) as n FROM commits WHERE repo_id = $1 AND id IN ($2, $3)")
+    .bind(repo_id)
+    .bind(remote_uuid)
+    .bind(local_uuid)
+    .fetch_one(&mut *tx).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("n");
+  if owned < (if remote_uuid == local_uuid { 1 } else { 2 }) {
+    return Err((StatusCode::NOT_FOUND, "Commit not found in this repository".to_string()));
+  }
+
   let remote_files_rows = sqlx
     ::query("SELECT file_path, blob_hash FROM commit_files WHERE commit_id = $1")
     .bind(remote_uuid)
@@ -533,6 +790,9 @@ pub async fn merge_commits(State(state): State<Arc<AppState>>, _guard: RepoWrite
     final_tree.insert(path, resolved_hash);
   }
 
+  let hashes: Vec<String> = final_tree.values().cloned().collect();
+  check_repo_blobs(&mut tx, repo_id, &hashes).await?;
+
   let message = format!("Merge resonance from local divergence ({})", &payload.divergent_commit_id[..8]);
 
   let commit_row = sqlx
@@ -561,12 +821,33 @@ pub async fn merge_commits(State(state): State<Arc<AppState>>, _guard: RepoWrite
     .bind(local_uuid)
     .execute(&mut *tx).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  clear_pending_uploads(&mut tx, repo_id, &hashes).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
   tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
   Ok(Json(json!({ "status": "merged", "commit_id": new_commit_id })))
 }
 
-pub async fn compare_blobs(State(state): State<Arc<AppState>>, Json(payload): Json<CompareRequest>) -> Result<Json<Value>, (StatusCode, String)> {
+pub async fn compare_blobs(
+  State(state): State<Arc<AppState>>,
+  guard: RepoReadGuard,
+  Json(payload): Json<CompareRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  // Blobs are content-addressed and shared across repositories: only diff what this repository's history references.
+  let reachable: i64 = sqlx
+    ::query(
+      "SELECT COUNT(DISTINCT cf.blob_hash) as n FROM commit_files cf JOIN commits c ON cf.commit_id = c.id WHERE c.repo_id = $1 AND cf.blob_hash IN ($2, $3)"
+    )
+    .bind(guard.repo_id)
+    .bind(&payload.local_hash)
+    .bind(&payload.remote_hash)
+    .fetch_one(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("n");
+  let expected = if payload.local_hash == payload.remote_hash { 1 } else { 2 };
+  if reachable < expected {
+    return Err((StatusCode::NOT_FOUND, "Blob not found in this repository".to_string()));
+  }
+
   let get_content = |hash: &str| {
     let hash = hash.to_string();
     let state = state.clone();
@@ -585,44 +866,354 @@ pub async fn compare_blobs(State(state): State<Arc<AppState>>, Json(payload): Js
 
 #[derive(Deserialize)]
 pub struct UpdateRepoRequest {
+  pub name: Option<String>,
   pub is_public: Option<bool>,
   pub description: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct TransferRepoRequest {
+  pub org: String,
+}
+
 pub async fn update_repo(
   State(state): State<Arc<AppState>>,
-  _guard: RepoAdminGuard,
-  Path(repo_name): Path<String>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>,
   Json(payload): Json<UpdateRepoRequest>
 ) -> Result<Json<Value>, (StatusCode, String)> {
-  let row = sqlx
+  if let Some(name) = &payload.name {
+    if !validation::is_valid_name(name) {
+      return Err((StatusCode::BAD_REQUEST, "Invalid repository name".to_string()));
+    }
+  }
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let before = sqlx
+    ::query("SELECT is_public, description FROM repositories WHERE id = $1 FOR UPDATE")
+    .bind(guard.0.repo_id)
+    .fetch_one(&mut *tx).await
+    .map(|r| json!({ "name": guard.0.repo_name, "is_public": r.get::<bool, _>("is_public"), "description": r.get::<Option<String>, _>("description") }))
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let update_res = sqlx
     ::query("UPDATE repositories 
        SET is_public = COALESCE($1, is_public), 
-         description = COALESCE($2, description) 
-       WHERE name = $3 
-       RETURNING id")
+         description = COALESCE($2, description),
+         name = COALESCE($3, name)
+       WHERE id = $4 
+       RETURNING is_public, description")
     .bind(payload.is_public)
     .bind(payload.description)
-    .bind(&repo_name)
+    .bind(&payload.name)
+    .bind(guard.0.repo_id)
+    .fetch_optional(&mut *tx).await;
+
+  let updated = match update_res {
+    Ok(Some(r)) => r,
+    Ok(None) => {
+      return Err((StatusCode::NOT_FOUND, "Repository not found".to_string()));
+    }
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, format!("Repository '{}' already exists.", payload.name.unwrap_or_default())));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  };
+
+  let new_name = repo_full_name(&mut tx, guard.0.repo_id).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if new_name != guard.0.repo_name {
+    rename_repo_namespace(&mut tx, guard.0.repo_id, &guard.0.repo_name, &new_name).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  }
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.update",
+    repo_id: Some(guard.0.repo_id),
+    target: &new_name,
+    before: Some(before),
+    after: Some(json!({ "name": new_name, "is_public": updated.get::<bool, _>("is_public"), "description": updated.get::<Option<String>, _>("description") })),
+  }).await;
+
+  Ok(Json(json!({ "status": "updated", "name": new_name })))
+}
+
+async fn repo_full_name(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, repo_id: Uuid) -> Result<String, sqlx::Error> {
+  let query = format!("SELECT {} AS full_name FROM repositories r LEFT JOIN organizations o ON r.org_id = o.id WHERE r.id = $1", REPO_FULL_NAME_SQL);
+  Ok(sqlx::query(&query).bind(repo_id).fetch_one(&mut **tx).await?.get("full_name"))
+}
+
+/// Keeps the old name resolvable after a rename or a transfer: a redirect is recorded for the API and
+/// the CLI, and the Docker image namespace (`old/...`) is moved so `docker pull` keeps working.
+async fn rename_repo_namespace(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, repo_id: Uuid, old_name: &str, new_name: &str) -> Result<(), sqlx::Error> {
+  sqlx
+    ::query(
+      "INSERT INTO repo_redirects (old_name, repo_id) VALUES ($1, $2) ON CONFLICT (old_name) DO UPDATE SET repo_id = EXCLUDED.repo_id, created_at = NOW()"
+    )
+    .bind(old_name)
+    .bind(repo_id)
+    .execute(&mut **tx).await?;
+
+  sqlx::query("DELETE FROM repo_redirects WHERE old_name = $1").bind(new_name).execute(&mut **tx).await?;
+
+  // Plain prefix comparison: `_` and `%` are ordinary characters in names.
+  sqlx
+    ::query(
+      "UPDATE docker_repositories SET name = $2 || substr(name, length($1) + 1) WHERE repository_id = $3 AND (name = $1 OR left(name, length($1) + 1) = $1 || '/')"
+    )
+    .bind(old_name)
+    .bind(new_name)
+    .bind(repo_id)
+    .execute(&mut **tx).await?;
+
+  // Uploads in progress finish under the new name.
+  sqlx
+    ::query("UPDATE docker_uploads SET repo_name = $2 || substr(repo_name, length($1) + 1) WHERE repo_name = $1 OR left(repo_name, length($1) + 1) = $1 || '/'")
+    .bind(old_name)
+    .bind(new_name)
+    .execute(&mut **tx).await?;
+
+  tracing::info!("🔀 Repository renamed: {} -> {}", old_name, new_name);
+  Ok(())
+}
+
+pub async fn transfer_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>,
+  Json(payload): Json<TransferRepoRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = match org::membership(&state.db, &payload.org, auth.id).await? {
+    (id, Some(role)) if role == "owner" => id,
+    _ => {
+      return Err((StatusCode::FORBIDDEN, "Only organization owners can receive repositories".to_string()));
+    }
+  };
+
+  let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let update_res = sqlx::query("UPDATE repositories SET org_id = $1 WHERE id = $2").bind(org_id).bind(guard.0.repo_id).execute(&mut *tx).await;
+
+  match update_res {
+    Ok(_) => {}
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, format!("'{}' already has a repository with this name.", payload.org)));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  }
+
+  let new_name = repo_full_name(&mut tx, guard.0.repo_id).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if new_name != guard.0.repo_name {
+    rename_repo_namespace(&mut tx, guard.0.repo_id, &guard.0.repo_name, &new_name).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  }
+
+  tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.transfer",
+    repo_id: Some(guard.0.repo_id),
+    target: &new_name,
+    before: Some(json!({ "name": guard.0.repo_name })),
+    after: Some(json!({ "name": new_name, "org": payload.org })),
+  }).await;
+
+  Ok(Json(json!({ "status": "transferred", "name": new_name, "previous_name": guard.0.repo_name, "org": payload.org })))
+}
+
+/// How long a deleted repository stays in the trash before the purge job removes it for good.
+static REPO_RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+  std::env
+    ::var("REPO_RETENTION_DAYS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(30)
+});
+
+pub async fn delete_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  guard: RepoAdminGuard,
+  Path(_repo_name): Path<String>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let row = sqlx
+    ::query("UPDATE repositories SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1 AND deleted_at IS NULL RETURNING deleted_at")
+    .bind(guard.0.repo_id)
+    .bind(auth.id)
     .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Repository not found".to_string()))?;
+
+  let deleted_at: chrono::DateTime<chrono::Utc> = row.get("deleted_at");
+  let purge_at = deleted_at + chrono::Duration::days(*REPO_RETENTION_DAYS);
+
+  tracing::info!("🗑️ Repository moved to trash: {} (purge after {})", guard.0.repo_name, purge_at.to_rfc3339());
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.delete",
+    repo_id: Some(guard.0.repo_id),
+    target: &guard.0.repo_name,
+    before: None,
+    after: Some(json!({ "deleted_at": deleted_at.to_rfc3339(), "purge_at": purge_at.to_rfc3339() })),
+  }).await;
+
+  Ok(Json(json!({ "status": "trashed", "repo": guard.0.repo_name, "repo_id": guard.0.repo_id, "purge_at": purge_at.to_rfc3339() })))
+}
+
+pub async fn list_trash(State(state): State<Arc<AppState>>, auth: AuthUser) -> Result<Json<Value>, (StatusCode, String)> {
+  let query = format!(
+    r#"
+      SELECT r.id, {full} as full_name, r.deleted_at, u.username as deleted_by
+      FROM repositories r
+      LEFT JOIN organizations o ON r.org_id = o.id
+      LEFT JOIN users u ON r.deleted_by = u.id
+      WHERE r.deleted_at IS NOT NULL AND (
+          EXISTS (SELECT 1 FROM repository_members rm WHERE rm.repo_id = r.id AND rm.user_id = $1 AND rm.role = 'admin')
+          OR EXISTS (SELECT 1 FROM organization_members om WHERE om.org_id = r.org_id AND om.user_id = $1 AND om.role = 'owner')
+          OR EXISTS (
+              SELECT 1 FROM team_repo_grants g JOIN team_members tm ON tm.team_id = g.team_id
+              WHERE g.repo_id = r.id AND tm.user_id = $1 AND g.role = 'admin'
+          )
+      )
+      ORDER BY r.deleted_at DESC
+      "#,
+    full = REPO_FULL_NAME_SQL
+  );
+
+  let rows = sqlx
+    ::query(&query)
+    .bind(auth.id)
+    .fetch_all(&state.db).await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-  match row {
-    Some(_) => Ok(Json(json!({ "status": "updated" }))),
-    None => Err((StatusCode::NOT_FOUND, "Repository not found".to_string())),
+  let repos: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      let deleted_at: chrono::DateTime<chrono::Utc> = r.get("deleted_at");
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "name": r.get::<String, _>("full_name"),
+        "deleted_by": r.get::<Option<String>, _>("deleted_by"),
+        "deleted_at": deleted_at.to_rfc3339(),
+        "purge_at": (deleted_at + chrono::Duration::days(*REPO_RETENTION_DAYS)).to_rfc3339(),
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(repos)))
+}
+
+async fn require_trashed_admin(state: &Arc<AppState>, repo_id: Uuid, auth: &AuthUser) -> Result<String, (StatusCode, String)> {
+  let access = crate::auth
+    ::trashed_repo_access(&state.db, repo_id, auth.id).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Repository not found in trash".to_string()))?;
+
+  if std::cmp::min(access.perm, auth.perm_cap()) < RepoPerm::Admin {
+    return Err((StatusCode::FORBIDDEN, "Admin access required".to_string()));
   }
+  Ok(access.repo_name)
 }
 
-pub async fn delete_repo(State(state): State<Arc<AppState>>, _guard: RepoAdminGuard, Path(repo_name): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
-  let result = sqlx
-    ::query("DELETE FROM repositories WHERE name = $1")
-    .bind(&repo_name)
-    .execute(&state.db).await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+pub async fn restore_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  Path(repo_id): Path<Uuid>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let name = require_trashed_admin(&state, repo_id, &auth).await?;
+
+  let res = sqlx::query("UPDATE repositories SET deleted_at = NULL, deleted_by = NULL WHERE id = $1").bind(repo_id).execute(&state.db).await;
+
+  match res {
+    Ok(_) => {}
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, format!("The name '{}' has been taken by another repository since the deletion.", name)));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  }
+
+  tracing::info!("♻️ Repository restored from trash: {}", name);
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "repo.restore",
+    repo_id: Some(repo_id),
+    target: &name,
+    before: None,
+    after: None,
+  }).await;
+
+  Ok(Json(json!({ "status": "restored", "repo": name, "repo_id": repo_id })))
+}
+
+pub async fn purge_repo_now(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  Path(repo_id): Path<Uuid>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let name = require_trashed_admin(&state, repo_id, &auth).await?;
+
+  purge_repo(&state, repo_id, &name, Some(&auth), &ip).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "purged", "repo": name })))
+}
+
+/// Hard-deletes a repository: the cascade removes commits, pipelines and mirrors. Blobs are shared
+/// in the CAS and are left to `storage::collect_garbage`. `actor` is `None` for the retention purge.
+async fn purge_repo(state: &Arc<AppState>, repo_id: Uuid, full_name: &str, actor: Option<&AuthUser>, ip: &ClientIp) -> Result<(), sqlx::Error> {
+  let mut tx = state.db.begin().await?;
+
+  // Its images go too. An image whose name was taken over by a newer repository belongs to that one.
+  let images = "SELECT id FROM docker_repositories WHERE repository_id = $1";
+  sqlx::query(&format!("DELETE FROM docker_tags WHERE repo_id IN ({})", images)).bind(repo_id).execute(&mut *tx).await?;
+  sqlx::query(&format!("DELETE FROM docker_manifests WHERE repo_id IN ({})", images)).bind(repo_id).execute(&mut *tx).await?;
+  sqlx::query("DELETE FROM docker_repositories WHERE repository_id = $1").bind(repo_id).execute(&mut *tx).await?;
+
+  sqlx::query("DELETE FROM repositories WHERE id = $1").bind(repo_id).execute(&mut *tx).await?;
+
+  tx.commit().await?;
+
+  tracing::info!("🔥 Repository purged: {}", full_name);
+
+  audit::record(&state.db, actor, ip, audit::Entry {
+    action: "repo.purge",
+    repo_id: Some(repo_id),
+    target: full_name,
+    before: None,
+    after: None,
+  }).await;
+  Ok(())
+}
+
+/// Purges the repositories whose retention period has expired. Returns how many were purged.
+pub async fn purge_expired_repos(state: &Arc<AppState>) -> Result<usize, sqlx::Error> {
+  let rows = sqlx
+    ::query(
+      &format!(
+        "SELECT r.id, {} as full_name FROM repositories r LEFT JOIN organizations o ON r.org_id = o.id WHERE r.deleted_at < NOW() - make_interval(days => $1)",
+        REPO_FULL_NAME_SQL
+      )
+    )
+    .bind(*REPO_RETENTION_DAYS as i32)
+    .fetch_all(&state.db).await?;
 
-  if result.rows_affected() == 0 {
-    return Err((StatusCode::NOT_FOUND, "Repository not found".to_string()));
+  for row in &rows {
+    purge_repo(state, row.get("id"), &row.get::<String, _>("full_name"), None, &ClientIp(None)).await?;
   }
 
-  Ok(Json(json!({ "status": "deleted", "repo": repo_name })))
+  Ok(rows.len())
 }
@@ -0,0 +1,446 @@
+//! Walks every route registered in `router()` and checks that callers without the required
+//! permission are turned away. The coverage test runs everywhere; the guard tests need a migrated
+//! database at `TEST_DATABASE_URL` (same Postgres image as compose) and are ignored by default:
+//! run them with `cargo test -- --include-ignored`.
+
+use axum::{ body::Body, http::{ Method, Request, StatusCode }, Router };
+use base64::{ Engine as _, engine::general_purpose };
+use dashmap::DashMap;
+use s3::{ bucket::Bucket, creds::Credentials, region::Region };
+use sqlx::{ postgres::PgPoolOptions, PgPool };
+use std::sync::Arc;
+use tower::ServiceExt;
+use uuid::Uuid;
+use crate::{ oidc::OidcVerifier, ratelimit::RateLimiter, router, state::AppState };
+
+/// Who may call a route.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Guard {
+  /// Anyone, including anonymous callers.
+  Public,
+  /// Any authenticated caller; the handler scopes the data to them.
+  User,
+  /// Anyone who can read the repository (public repositories are readable anonymously).
+  RepoRead,
+  RepoWrite,
+  RepoAdmin,
+  /// Owners of the organization.
+  OrgOwner,
+  /// Members of the organization.
+  OrgMember,
+  SystemAdmin,
+  /// CI job or runner credentials.
+  Runner,
+  /// Runner WebSocket, authenticated during the handshake; an in-process request cannot upgrade.
+  RunnerSocket,
+  RegistryPull,
+  RegistryPush,
+}
+
+use Guard::*;
+
+const ROUTES: &[(&str, &str, Guard)] = &[
+  ("GET", "/", Public),
+  ("GET", "/api/me", User),
+  ("PATCH", "/api/me", User),
+  ("GET", "/api/auth/config", Public),
+  ("GET", "/api/tokens", User),
+  ("POST", "/api/tokens", User),
+  ("DELETE", "/api/tokens/:id", User),
+  ("GET", "/api/me/ssh-keys", User),
+  ("POST", "/api/me/ssh-keys", User),
+  ("DELETE", "/api/me/ssh-keys/:id", User),
+  ("GET", "/api/check/repo/:name", Public),
+  ("GET", "/api/check/user/:name", Public),
+  ("POST", "/repos", User),
+  ("GET", "/repos", Public),
+  ("POST", "/orgs", User),
+  ("GET", "/orgs", User),
+  ("GET", "/orgs/:org", OrgMember),
+  ("DELETE", "/orgs/:org", OrgOwner),
+  ("GET", "/orgs/:org/members", OrgMember),
+  ("POST", "/orgs/:org/members", OrgOwner),
+  ("DELETE", "/orgs/:org/members/:username", OrgOwner),
+  ("GET", "/orgs/:org/repos", Public),
+  ("GET", "/orgs/:org/teams", OrgMember),
+  ("POST", "/orgs/:org/teams", OrgOwner),
+  ("GET", "/orgs/:org/teams/:team", OrgMember),
+  ("DELETE", "/orgs/:org/teams/:team", OrgOwner),
+  ("POST", "/orgs/:org/teams/:team/members", OrgOwner),
+  ("DELETE", "/orgs/:org/teams/:team/members/:username", OrgOwner),
+  ("POST", "/orgs/:org/teams/:team/repos", OrgOwner),
+  ("DELETE", "/orgs/:org/teams/:team/repos/:repo", OrgOwner),
+  ("GET", "/api/trash", User),
+  ("DELETE", "/api/trash/:id", User),
+  ("POST", "/api/trash/:id/restore", User),
+  ("PATCH", "/repos/:name", RepoAdmin),
+  ("DELETE", "/repos/:name", RepoAdmin),
+  ("GET", "/repos/:name/head", RepoRead),
+  ("GET", "/repos/:name/commits", RepoRead),
+  ("POST", "/repos/:name/commits", RepoWrite),
+  ("POST", "/repos/:name/merge", RepoWrite),
+  ("GET", "/repos/:name/merge-requests", RepoRead),
+  ("POST", "/repos/:name/merge-requests", RepoWrite),
+  ("POST", "/repos/:name/merge-requests/:id/merge", RepoWrite),
+  // Also open to the request's author; the fixture's request is the owner's.
+  ("POST", "/repos/:name/merge-requests/:id/close", RepoWrite),
+  ("POST", "/repos/:name/upload", RepoWrite),
+  ("POST", "/repos/:name/fork", RepoRead),
+  ("POST", "/repos/:name/transfer", RepoAdmin),
+  ("GET", "/repos/:name/mirror", RepoAdmin),
+  ("POST", "/repos/:name/mirror", RepoAdmin),
+  ("GET", "/repos/:name/commits/:commit_id/tree", RepoRead),
+  ("GET", "/repos/:name/commits/:commit_id/files/*path", RepoRead),
+  ("GET", "/repos/:name/commits/:commit_id/metadata/*path", RepoRead),
+  ("POST", "/analytics/repos/:name/commits/:commit_id/files/*path", RepoRead),
+  ("POST", "/repos/:name/compare", RepoRead),
+  ("GET", "/repos/:name/images", RepoRead),
+  ("DELETE", "/repos/:name/images/tags", RepoWrite),
+  ("GET", "/repos/:name/images/:digest/config", RepoRead),
+  ("GET", "/repos/:name/members", RepoRead),
+  ("POST", "/repos/:name/members", RepoAdmin),
+  ("GET", "/repos/:name/audit", RepoAdmin),
+  ("GET", "/repos/:name/deploy-keys", RepoAdmin),
+  ("POST", "/repos/:name/deploy-keys", RepoAdmin),
+  ("DELETE", "/repos/:name/deploy-keys/:id", RepoAdmin),
+  ("GET", "/repos/:name/robots", RepoAdmin),
+  ("POST", "/repos/:name/robots", RepoAdmin),
+  ("DELETE", "/repos/:name/robots/:id", RepoAdmin),
+  ("POST", "/repos/:name/robots/:id/token", RepoAdmin),
+  ("GET", "/v2/", Public),
+  ("HEAD", "/v2/", Public),
+  ("GET", "/v2/token", Public),
+  ("GET", "/v2/_catalog", User),
+  ("GET", "/v2/:name/tags/list", RegistryPull),
+  ("GET", "/v2/:ns/:img/tags/list", RegistryPull),
+  ("GET", "/v2/:name/referrers/:digest", RegistryPull),
+  ("GET", "/v2/:ns/:img/referrers/:digest", RegistryPull),
+  ("GET", "/v2/:name/blobs/:digest", RegistryPull),
+  ("HEAD", "/v2/:name/blobs/:digest", RegistryPull),
+  ("POST", "/v2/:name/blobs/uploads/", RegistryPush),
+  ("PUT", "/v2/:name/blobs/uploads/:uuid", RegistryPush),
+  ("PATCH", "/v2/:name/blobs/uploads/:uuid", RegistryPush),
+  ("GET", "/v2/:name/blobs/uploads/:uuid", RegistryPush),
+  ("DELETE", "/v2/:name/blobs/uploads/:uuid", RegistryPush),
+  ("PUT", "/v2/:name/manifests/:reference", RegistryPush),
+  ("GET", "/v2/:name/manifests/:reference", RegistryPull),
+  ("HEAD", "/v2/:name/manifests/:reference", RegistryPull),
+  ("DELETE", "/v2/:name/manifests/:reference", RegistryPush),
+  ("GET", "/v2/:ns/:img/blobs/:digest", RegistryPull),
+  ("HEAD", "/v2/:ns/:img/blobs/:digest", RegistryPull),
+  ("POST", "/v2/:ns/:img/blobs/uploads/", RegistryPush),
+  ("PUT", "/v2/:ns/:img/blobs/uploads/:uuid", RegistryPush),
+  ("PATCH", "/v2/:ns/:img/blobs/uploads/:uuid", RegistryPush),
+  ("GET", "/v2/:ns/:img/blobs/uploads/:uuid", RegistryPush),
+  ("DELETE", "/v2/:ns/:img/blobs/uploads/:uuid", RegistryPush),
+  ("PUT", "/v2/:ns/:img/manifests/:reference", RegistryPush),
+  ("GET", "/v2/:ns/:img/manifests/:reference", RegistryPull),
+  ("HEAD", "/v2/:ns/:img/manifests/:reference", RegistryPull),
+  ("DELETE", "/v2/:ns/:img/manifests/:reference", RegistryPush),
+  ("GET", "/api/runner/ws", RunnerSocket),
+  ("GET", "/repos/:name/pipelines", RepoRead),
+  ("GET", "/repos/:name/pipelines/:id", RepoRead),
+  ("POST", "/api/runner/jobs/:id/artifacts", Runner),
+  ("GET", "/repos/:name/releases", RepoRead),
+  ("GET", "/repos/:name/releases/:id/download", RepoRead),
+  ("GET", "/api/admin/runners", SystemAdmin),
+  ("POST", "/api/admin/runners", SystemAdmin),
+  ("DELETE", "/api/admin/runners/:id", SystemAdmin),
+  ("POST", "/api/admin/runners/:id/rotate", SystemAdmin),
+  ("GET", "/api/admin/audit", SystemAdmin),
+];
+
+/// `(METHOD, path)` of every `.route(...)` in main.rs, read from the source so that a new route
+/// cannot be added without being classified above.
+fn registered_routes() -> Vec<(String, String)> {
+  let source = include_str!("main.rs");
+  let mut routes = Vec::new();
+  let mut rest = source;
+
+  while let Some(start) = rest.find(".route(") {
+    let body = &rest[start + ".route(".len()..];
+    let mut depth = 1;
+    let end = body
+      .char_indices()
+      .find(|(_, c)| {
+        match c {
+          '(' => depth += 1,
+          ')' => depth -= 1,
+          _ => {}
+        }
+        depth == 0
+      })
+      .map(|(i, _)| i)
+      .expect("unbalanced .route(");
+    let call = &body[..end];
+    rest = &body[end..];
+
+    let path = call.split('"').nth(1).expect("route path literal");
+    let methods = &call[call.find(',').expect("route handlers")..];
+    for method in ["get", "post", "put", "patch", "delete", "head"] {
+      let needle = format!("{}(", method);
+      let found = methods.match_indices(&needle).any(|(i, _)| {
+        !methods[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_')
+      });
+      if found {
+        routes.push((method.to_uppercase(), path.to_string()));
+      }
+    }
+  }
+  routes
+}
+
+#[test]
+fn every_route_is_classified() {
+  let registered = registered_routes();
+  assert!(registered.len() > 100, "route parser found only {} routes", registered.len());
+
+  for (method, path) in &registered {
+    assert!(
+      ROUTES.iter().any(|(m, p, _)| m == method && p == path),
+      "{} {} has no guard classification in router_tests::ROUTES",
+      method,
+      path
+    );
+  }
+  for (method, path, _) in ROUTES {
+    assert!(
+      registered.iter().any(|(m, p)| m == method && p == path),
+      "{} {} is classified but no longer routed",
+      method,
+      path
+    );
+  }
+}
+
+struct Fixture {
+  app: Router,
+  repo: String,
+  org: String,
+  team: String,
+  owner: String,
+  reader_token: String,
+  outsider_token: String,
+  merge_request: Uuid,
+}
+
+/// A private personal repository the reader can only view and the outsider cannot see, and an
+/// organization the reader belongs to without owning it.
+async fn fixture() -> Fixture {
+  let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must point at a Postgres database");
+  let db = PgPoolOptions::new().max_connections(5).connect(&url).await.expect("test database");
+  sqlx::migrate::Migrator::new(std::path::Path::new("./migrations")).await.expect("migrations").run(&db).await.expect("migrate");
+
+  let suffix = &Uuid::new_v4().simple().to_string()[..8];
+  let repo = format!("guard-repo-{}", suffix);
+  let org = format!("guard-org-{}", suffix);
+  let team = "core".to_string();
+  let owner = format!("guard-owner-{}", suffix);
+  let reader = format!("guard-reader-{}", suffix);
+  let outsider = format!("guard-outsider-{}", suffix);
+  let reader_token = format!("{}{}", crate::token::TOKEN_PREFIX, Uuid::new_v4().simple());
+  let outsider_token = format!("{}{}", crate::token::TOKEN_PREFIX, Uuid::new_v4().simple());
+
+  let merge_request = seed(&db, &repo, &org, &team, &owner, (&reader, &reader_token), (&outsider, &outsider_token)).await.expect("seed fixtures");
+
+  // Every case must reach its guard, not the limiter.
+  std::env::set_var("RATE_LIMIT_ENABLED", "false");
+  std::env::set_var("PUBLIC_URL", "http://plectr.test");
+  let region = Region::Custom { region: "us-east-1".to_owned(), endpoint: "http://127.0.0.1:9".to_owned() };
+  let credentials = Credentials::new(Some("any"), Some("any"), None, None, None).unwrap();
+  let state = Arc::new(AppState {
+    db,
+    bucket: Bucket::new("plectr-test", region, credentials).unwrap().with_path_style(),
+    active_runners: DashMap::new(),
+    oidc: Arc::new(OidcVerifier::new("http://127.0.0.1:9/realms/test".to_string(), None, "http://127.0.0.1:9/certs".to_string())),
+    rate_limiter: Arc::new(RateLimiter::from_env()),
+  });
+
+  Fixture { app: router(state), repo, org, team, owner, reader_token, outsider_token, merge_request }
+}
+
+/// Returns the id of an open merge request the owner filed against the repository.
+async fn seed(
+  db: &PgPool,
+  repo: &str,
+  org: &str,
+  team: &str,
+  owner: &str,
+  (reader, reader_token): (&str, &str),
+  (outsider, outsider_token): (&str, &str)
+) -> Result<Uuid, sqlx::Error> {
+  let owner_id = Uuid::new_v4();
+  let reader_id = Uuid::new_v4();
+  let outsider_id = Uuid::new_v4();
+  for (id, name) in [(owner_id, owner), (reader_id, reader), (outsider_id, outsider)] {
+    sqlx::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3)").bind(id).bind(name).bind(format!("{}@example.test", name)).execute(db).await?;
+  }
+
+  let repo_id: Uuid = sqlx::query_scalar("INSERT INTO repositories (name, is_public) VALUES ($1, FALSE) RETURNING id").bind(repo).fetch_one(db).await?;
+  sqlx::query("INSERT INTO repository_members (repo_id, user_id, role) VALUES ($1, $2, 'admin'), ($1, $3, 'viewer')")
+    .bind(repo_id)
+    .bind(owner_id)
+    .bind(reader_id)
+    .execute(db).await?;
+
+  let org_id: Uuid = sqlx::query_scalar("INSERT INTO organizations (name) VALUES ($1) RETURNING id").bind(org).fetch_one(db).await?;
+  sqlx::query("INSERT INTO organization_members (org_id, user_id, role) VALUES ($1, $2, 'owner'), ($1, $3, 'member')")
+    .bind(org_id)
+    .bind(owner_id)
+    .bind(reader_id)
+    .execute(db).await?;
+  sqlx::query("INSERT INTO teams (org_id, name) VALUES ($1, $2)").bind(org_id).bind(team).execute(db).await?;
+
+  let scopes: Vec<String> = crate::token::SCOPES.iter().map(|s| s.to_string()).collect();
+  for (user_id, token) in [(reader_id, reader_token), (outsider_id, outsider_token)] {
+    sqlx::query("INSERT INTO personal_access_tokens (user_id, name, token_hash, token_prefix, scopes) VALUES ($1, 'guard tests', $2, $3, $4)")
+      .bind(user_id)
+      .bind(crate::token::hash_token(token))
+      .bind(&token[..crate::token::TOKEN_PREFIX.len() + 4])
+      .bind(&scopes)
+      .execute(db).await?;
+  }
+
+  let commit_id: Uuid = sqlx
+    ::query_scalar("INSERT INTO commits (repo_id, message, author_name, author_email, tree_hash) VALUES ($1, 'guard', $2, 'owner@example.test', '') RETURNING id")
+    .bind(repo_id)
+    .bind(owner)
+    .fetch_one(db).await?;
+  sqlx
+    ::query_scalar("INSERT INTO merge_requests (source_repo_id, source_commit_id, target_repo_id, title, author_id) VALUES ($1, $2, $1, 'guard', $3) RETURNING id")
+    .bind(repo_id)
+    .bind(commit_id)
+    .bind(owner_id)
+    .fetch_one(db).await
+}
+
+impl Fixture {
+  fn uri(&self, path: &str) -> String {
+    let id = Uuid::new_v4().to_string();
+    let digest = format!("sha256:{}", "0".repeat(64));
+    path
+      .split('/')
+      .map(|segment| {
+        match segment {
+          ":name" | ":repo" => self.repo.clone(),
+          ":ns" => self.repo.clone(),
+          ":img" => "app".to_string(),
+          ":org" => self.org.clone(),
+          ":team" => self.team.clone(),
+          ":username" => self.owner.clone(),
+          ":digest" => digest.clone(),
+          ":reference" => "latest".to_string(),
+          ":id" if path.contains("/merge-requests/") => self.merge_request.to_string(),
+          "*path" => "README.md".to_string(),
+          s if s.starts_with(':') => id.clone(),
+          s => s.to_string(),
+        }
+      })
+      .collect::<Vec<_>>()
+      .join("/")
+  }
+
+  /// A well-formed request body, so that the call is refused by its guard rather than by payload validation.
+  fn body(&self, path: &str) -> (&'static str, String) {
+    let json = |value: serde_json::Value| ("application/json", value.to_string());
+    match path {
+      "/orgs/:org/members" => json(serde_json::json!({ "email": "intruder@example.test", "role": "owner" })),
+      "/orgs/:org/teams" => json(serde_json::json!({ "name": "intruders" })),
+      "/orgs/:org/teams/:team/members" => json(serde_json::json!({ "email": "intruder@example.test" })),
+      "/orgs/:org/teams/:team/repos" => json(serde_json::json!({ "repo": "app", "role": "admin" })),
+      p if p.ends_with("/artifacts") => ("multipart/form-data; boundary=guard", "--guard--\r\n".to_string()),
+      _ => json(serde_json::json!({})),
+    }
+  }
+
+  async fn call(&self, method: &str, path: &str, token: Option<&str>) -> StatusCode {
+    let mut uri = self.uri(path);
+    if path == "/repos/:name/images/tags" {
+      uri.push_str(&format!("?image={}/app&tag=latest", self.repo));
+    }
+    let (content_type, body) = self.body(path);
+    let mut request = Request::builder().method(Method::from_bytes(method.as_bytes()).unwrap()).uri(uri).header("content-type", content_type);
+    if let Some(token) = token {
+      request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let response = self.app.clone().oneshot(request.body(Body::from(body)).unwrap()).await.unwrap();
+    response.status()
+  }
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres at TEST_DATABASE_URL"]
+async fn anonymous_callers_are_rejected() {
+  let fx = fixture().await;
+
+  let mut failures = Vec::new();
+  for (method, path, guard) in ROUTES {
+    if matches!(guard, Public | RunnerSocket) {
+      continue;
+    }
+    let status = fx.call(method, path, None).await;
+    if status != StatusCode::UNAUTHORIZED {
+      failures.push(format!("{} {} answered {}", method, path, status));
+    }
+  }
+  assert!(failures.is_empty(), "anonymous callers got through:\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres at TEST_DATABASE_URL"]
+async fn read_only_callers_cannot_write() {
+  let fx = fixture().await;
+
+  let mut failures = Vec::new();
+  for (method, path, guard) in ROUTES {
+    if !matches!(guard, RepoWrite | RepoAdmin | OrgOwner | SystemAdmin | Runner | RegistryPush) {
+      continue;
+    }
+    let status = fx.call(method, path, Some(&fx.reader_token)).await;
+    if status != StatusCode::FORBIDDEN {
+      failures.push(format!("{} {} answered {}", method, path, status));
+    }
+  }
+  assert!(failures.is_empty(), "read-only callers got through:\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres at TEST_DATABASE_URL"]
+async fn non_members_cannot_read_private_repositories() {
+  let fx = fixture().await;
+
+  let mut failures = Vec::new();
+  for (method, path, guard) in ROUTES {
+    if !matches!(guard, RepoRead | RepoWrite | RepoAdmin | RegistryPull | RegistryPush) {
+      continue;
+    }
+    let status = fx.call(method, path, Some(&fx.outsider_token)).await;
+    if status != StatusCode::FORBIDDEN {
+      failures.push(format!("{} {} answered {}", method, path, status));
+    }
+  }
+  assert!(failures.is_empty(), "non-members got into a private repository:\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres at TEST_DATABASE_URL"]
+async fn registry_tokens_cannot_mint_tokens() {
+  let fx = fixture().await;
+
+  let uri = format!("/v2/token?service=plectr-registry&scope=repository:{}/app:pull", fx.repo);
+  let token_request = |authorization: String| {
+    Request::builder().uri(uri.as_str()).header("authorization", authorization).body(Body::empty()).unwrap()
+  };
+
+  let login = general_purpose::STANDARD.encode(format!("reader:{}", fx.reader_token));
+  let response = fx.app.clone().oneshot(token_request(format!("Basic {}", login))).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+  let registry_token = body["token"].as_str().expect("registry token");
+
+  let refresh = general_purpose::STANDARD.encode(format!("reader:{}", registry_token));
+  for authorization in [format!("Bearer {}", registry_token), format!("Basic {}", refresh)] {
+    let response = fx.app.clone().oneshot(token_request(authorization)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  }
+}
@@ -0,0 +1,279 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Extension, Json, Router };
+use hyper_util::{ rt::TokioIo, service::TowerToHyperService };
+use russh::{
+  keys::{ Algorithm, HashAlg, PrivateKey, PublicKey, ssh_key::LineEnding },
+  server::{ Auth, Config, Handler, Msg, Server as _, Session },
+  Channel,
+  MethodKind,
+};
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use sqlx::Row;
+use std::{ net::SocketAddr, sync::Arc, time::Duration };
+use uuid::Uuid;
+use crate::{ state::AppState, auth::AuthUser, audit::{ self, ClientIp } };
+
+/// What an SSH session may do: the agent pushes and fetches, nothing more.
+const SSH_SCOPES: [&str; 2] = ["repo:read", "repo:write"];
+
+/// Set on requests arriving through an SSH tunnel: the caller was authenticated by its SSH key.
+#[derive(Clone)]
+pub struct SshSession(pub AuthUser);
+
+#[derive(Deserialize)]
+pub struct AddKeyRequest {
+  pub title: String,
+  pub key: String,
+}
+
+pub async fn list_ssh_keys(State(state): State<Arc<AppState>>, auth: AuthUser) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query("SELECT id, title, fingerprint, public_key, last_used_at, created_at FROM ssh_keys WHERE user_id = $1 ORDER BY created_at DESC")
+    .bind(auth.id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let keys: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "title": r.get::<String, _>("title"),
+        "fingerprint": r.get::<String, _>("fingerprint"),
+        "key": r.get::<String, _>("public_key"),
+        "last_used_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_used_at").map(|d| d.to_rfc3339()),
+        "created_at": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at").to_rfc3339()
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(keys)))
+}
+
+pub async fn add_ssh_key(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  Json(payload): Json<AddKeyRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  // SSH keys are login credentials: a leaked token must not be able to add one.
+  if auth.scopes.is_some() {
+    return Err((StatusCode::FORBIDDEN, "SSH keys can only be added from an interactive session".to_string()));
+  }
+  if payload.title.trim().is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "Key title is required".to_string()));
+  }
+
+  let mut key = PublicKey::from_openssh(payload.key.trim()).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid OpenSSH public key".to_string()))?;
+  if key.algorithm() == Algorithm::Dsa {
+    return Err((StatusCode::BAD_REQUEST, "DSA keys are not supported".to_string()));
+  }
+  key.set_comment("");
+  let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+  let public_key = key.to_openssh().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
+    .bind(auth.id)
+    .bind(&auth.username)
+    .bind(&auth.email)
+    .execute(&state.db).await
+    .ok();
+
+  let res = sqlx
+    ::query("INSERT INTO ssh_keys (user_id, title, public_key, fingerprint) VALUES ($1, $2, $3, $4) RETURNING id")
+    .bind(auth.id)
+    .bind(payload.title.trim())
+    .bind(&public_key)
+    .bind(&fingerprint)
+    .fetch_one(&state.db).await;
+
+  let id: Uuid = match res {
+    Ok(row) => row.get("id"),
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      return Err((StatusCode::CONFLICT, "This key is already registered".to_string()));
+    }
+    Err(e) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+  };
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "ssh.key.add",
+    repo_id: None,
+    target: &auth.username,
+    before: None,
+    after: Some(json!({ "id": id, "title": payload.title.trim(), "fingerprint": fingerprint })),
+  }).await;
+
+  Ok(Json(json!({ "id": id, "title": payload.title.trim(), "fingerprint": fingerprint })))
+}
+
+pub async fn delete_ssh_key(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  ip: ClientIp,
+  Path(id): Path<Uuid>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let fingerprint: String = sqlx
+    ::query("DELETE FROM ssh_keys WHERE id = $1 AND user_id = $2 RETURNING fingerprint")
+    .bind(id)
+    .bind(auth.id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Key not found".to_string()))?
+    .get("fingerprint");
+
+  audit::record(&state.db, Some(&auth), &ip, audit::Entry {
+    action: "ssh.key.delete",
+    repo_id: None,
+    target: &auth.username,
+    before: Some(json!({ "id": id, "fingerprint": fingerprint })),
+    after: None,
+  }).await;
+
+  Ok(Json(json!({ "status": "deleted", "id": id })))
+}
+
+/// Loads the host key, generating it on first start so the server identity survives restarts.
+fn load_host_key(path: &std::path::Path) -> anyhow::Result<PrivateKey> {
+  if path.exists() {
+    return Ok(PrivateKey::read_openssh_file(path)?);
+  }
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  let key = PrivateKey::random(&mut rand::rngs::OsRng, Algorithm::Ed25519)?;
+  key.write_openssh_file(path, LineEnding::LF)?;
+  tracing::info!("🔑 Generated SSH host key at {}", path.display());
+  Ok(key)
+}
+
+/// Runs the SSH transport: clients authenticate with a registered key, then forward a local port
+/// (`ssh -L`) and talk to the regular HTTP API through it, without any token.
+/// `SSH_LISTEN` (default `0.0.0.0:2222`) and `SSH_HOST_KEY_PATH` configure it; `SSH_LISTEN=off` disables it.
+pub async fn serve(state: Arc<AppState>, app: Router) {
+  let listen = std::env::var("SSH_LISTEN").unwrap_or_else(|_| "0.0.0.0:2222".to_string());
+  if listen == "off" {
+    return;
+  }
+  let key_path = std::env::var("SSH_HOST_KEY_PATH").unwrap_or_else(|_| "./ssh_host_ed25519_key".to_string());
+
+  let host_key = match load_host_key(std::path::Path::new(&key_path)) {
+    Ok(k) => k,
+    Err(e) => {
+      tracing::error!("❌ SSH transport disabled, host key unavailable: {}", e);
+      return;
+    }
+  };
+
+  let config = Arc::new(Config {
+    methods: (&[MethodKind::PublicKey][..]).into(),
+    auth_rejection_time: Duration::from_secs(1),
+    auth_rejection_time_initial: Some(Duration::from_secs(0)),
+    keys: vec![host_key],
+    inactivity_timeout: Some(Duration::from_secs(3600)),
+    keepalive_interval: Some(Duration::from_secs(30)),
+    ..Default::default()
+  });
+
+  tracing::info!("🔐 SSH transport listening on {}", listen);
+  let mut server = SshServer { state, app };
+  if let Err(e) = server.run_on_address(config, listen.as_str()).await {
+    tracing::error!("❌ SSH server stopped: {}", e);
+  }
+}
+
+struct SshServer {
+  state: Arc<AppState>,
+  app: Router,
+}
+
+impl russh::server::Server for SshServer {
+  type Handler = SshHandler;
+
+  fn new_client(&mut self, peer: Option<SocketAddr>) -> SshHandler {
+    SshHandler { state: self.state.clone(), app: self.app.clone(), peer, user: None }
+  }
+}
+
+struct SshHandler {
+  state: Arc<AppState>,
+  app: Router,
+  peer: Option<SocketAddr>,
+  user: Option<AuthUser>,
+}
+
+impl SshHandler {
+  async fn find_user(&self, key: &PublicKey) -> Option<AuthUser> {
+    let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+    let row = sqlx
+      ::query(
+        "UPDATE ssh_keys k SET last_used_at = NOW() FROM users u WHERE k.fingerprint = $1 AND u.id = k.user_id RETURNING u.id, u.username, u.email, u.robot_repo_id"
+      )
+      .bind(&fingerprint)
+      .fetch_optional(&self.state.db).await
+      .ok()??;
+
+    Some(AuthUser {
+      id: row.get("id"),
+      username: row.get("username"),
+      email: row.get("email"),
+      scopes: Some(SSH_SCOPES.iter().map(|s| s.to_string()).collect()),
+      job: None,
+      deploy_key: None,
+      robot_repo: row.get("robot_repo_id"),
+    })
+  }
+}
+
+impl Handler for SshHandler {
+  type Error = russh::Error;
+
+  async fn auth_publickey_offered(&mut self, _user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+    let known = sqlx
+      ::query("SELECT 1 FROM ssh_keys WHERE fingerprint = $1")
+      .bind(key.fingerprint(HashAlg::Sha256).to_string())
+      .fetch_optional(&self.state.db).await
+      .map(|r| r.is_some())
+      .unwrap_or(false);
+    Ok(if known { Auth::Accept } else { Auth::reject() })
+  }
+
+  /// Called once the client proved it holds the private key. The SSH user name is ignored:
+  /// the key alone identifies the account.
+  async fn auth_publickey(&mut self, _user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+    match self.find_user(key).await {
+      Some(user) => {
+        tracing::info!("🔐 SSH session for {} from {:?}", user.username, self.peer);
+        self.user = Some(user);
+        Ok(Auth::Accept)
+      }
+      None => Ok(Auth::reject()),
+    }
+  }
+
+  /// Each forwarded connection carries plain HTTP, served by the API router as the key's owner.
+  async fn channel_open_direct_tcpip(
+    &mut self,
+    channel: Channel<Msg>,
+    _host_to_connect: &str,
+    _port_to_connect: u32,
+    _originator_address: &str,
+    _originator_port: u32,
+    _session: &mut Session
+  ) -> Result<bool, Self::Error> {
+    let Some(user) = self.user.clone() else {
+      return Ok(false);
+    };
+
+    let service = TowerToHyperService::new(self.app.clone().layer(Extension(SshSession(user))));
+    tokio::spawn(async move {
+      let io = TokioIo::new(channel.into_stream());
+      if let Err(e) = hyper::server::conn::http1::Builder::new().serve_connection(io, service).await {
+        tracing::debug!("SSH tunnel connection closed: {}", e);
+      }
+    });
+    Ok(true)
+  }
+}
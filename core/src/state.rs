@@ -4,10 +4,14 @@ use dashmap::DashMap;
 use axum::extract::ws::Message;
 use tokio::sync::mpsc;
 use uuid::Uuid;
+use std::sync::Arc;
+use crate::{ oidc::OidcVerifier, ratelimit::RateLimiter };
 
 #[derive(Clone)]
 pub struct AppState {
   pub db: PgPool,
   pub bucket: Bucket,
   pub active_runners: DashMap<Uuid, mpsc::UnboundedSender<Message>>,
+  pub oidc: Arc<OidcVerifier>,
+  pub rate_limiter: Arc<RateLimiter>,
 }
@@ -3,8 +3,44 @@ use anyhow::{ Context, Result };
 use axum::extract::multipart::Field;
 use futures::StreamExt;
 use std::sync::Arc;
+use once_cell::sync::Lazy;
+use sqlx::Row;
 use crate::ai;
 
+/// Orphaned blobs younger than this are kept: their commit or manifest may not be written yet.
+static BLOB_GC_GRACE_HOURS: Lazy<i32> = Lazy::new(|| {
+  std::env
+    ::var("BLOB_GC_GRACE_HOURS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(24)
+});
+
+/// Uploads never referenced by a commit stop holding their blob after this delay, and
+/// registry upload sessions idle for that long are discarded.
+static PENDING_UPLOAD_TTL_HOURS: Lazy<i32> = Lazy::new(|| {
+  std::env
+    ::var("PENDING_UPLOAD_TTL_HOURS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(24)
+});
+
+/// A blob is alive while a commit, a pending upload, a job artifact or a Docker manifest (config or layer) points to it.
+const BLOB_UNREFERENCED_SQL: &str =
+  r#"
+    NOT EXISTS (SELECT 1 FROM commit_files cf WHERE cf.blob_hash = b.hash)
+    AND NOT EXISTS (SELECT 1 FROM pending_uploads pu WHERE pu.blob_hash = b.hash)
+    AND NOT EXISTS (SELECT 1 FROM job_artifacts ja WHERE ja.blob_hash = b.hash)
+    AND (
+        b.sha256 IS NULL OR NOT EXISTS (
+            SELECT 1 FROM docker_manifests m
+            WHERE m.content->'config'->>'digest' = 'sha256:' || b.sha256
+               OR m.content->'layers' @> jsonb_build_array(jsonb_build_object('digest', 'sha256:' || b.sha256))
+        )
+    )
+"#;
+
 pub struct BlobInfo {
   pub hash: String,
   pub size: i64,
@@ -49,3 +85,47 @@ pub async fn ingest_file(state: Arc<AppState>, mut field: Field<'_>) -> Result<B
 
   Ok(BlobInfo { hash, size, mime_type: content_type, existed: false })
 }
+
+/// Deletes unreferenced blobs from the database and the bucket. Returns how many were collected.
+pub async fn collect_garbage(state: &Arc<AppState>) -> Result<usize> {
+  sqlx
+    ::query("DELETE FROM pending_uploads WHERE created_at < NOW() - make_interval(hours => $1)")
+    .bind(*PENDING_UPLOAD_TTL_HOURS)
+    .execute(&state.db).await?;
+
+  // Registry upload sessions left behind by interrupted pushes.
+  let abandoned = sqlx
+    ::query("SELECT uuid, chunk_keys FROM docker_uploads WHERE updated_at < NOW() - make_interval(hours => $1)")
+    .bind(*PENDING_UPLOAD_TTL_HOURS)
+    .fetch_all(&state.db).await?;
+  for row in abandoned {
+    let chunk_keys: Vec<String> = row.get("chunk_keys");
+    crate::registry::discard_upload(state, row.get("uuid"), &chunk_keys).await;
+  }
+
+  let candidates = sqlx
+    ::query(&format!("SELECT b.hash FROM blobs b WHERE b.created_at < NOW() - make_interval(hours => $1) AND {} LIMIT 1000", BLOB_UNREFERENCED_SQL))
+    .bind(*BLOB_GC_GRACE_HOURS)
+    .fetch_all(&state.db).await?;
+
+  let mut collected = 0;
+  for row in candidates {
+    let hash: String = row.get("hash");
+
+    // Re-checked on delete: a commit may have picked the blob up since the scan.
+    let deleted = sqlx
+      ::query(&format!("DELETE FROM blobs b WHERE b.hash = $1 AND {} RETURNING b.storage_path", BLOB_UNREFERENCED_SQL))
+      .bind(&hash)
+      .fetch_optional(&state.db).await?;
+
+    if let Some(r) = deleted {
+      let path: String = r.get("storage_path");
+      if let Err(e) = state.bucket.delete_object(&path).await {
+        tracing::warn!("⚠️ GC: failed to delete object {}: {}", path, e);
+      }
+      collected += 1;
+    }
+  }
+
+  Ok(collected)
+}
@@ -0,0 +1,282 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Json };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use std::sync::Arc;
+use sqlx::{ PgPool, Row };
+use uuid::Uuid;
+use crate::{ state::AppState, auth::AuthUser, org, validation };
+
+#[derive(Deserialize)]
+pub struct CreateTeamRequest {
+  pub name: String,
+  pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AddTeamMemberRequest {
+  pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct GrantTeamRepoRequest {
+  /// Repository name inside the organization (without the `org/` prefix).
+  pub repo: String,
+  pub role: String,
+}
+
+async fn team_id(db: &PgPool, org_id: Uuid, team: &str) -> Result<Uuid, (StatusCode, String)> {
+  sqlx
+    ::query("SELECT id FROM teams WHERE org_id = $1 AND name = $2")
+    .bind(org_id)
+    .bind(team)
+    .fetch_optional(db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("id"))
+    .ok_or((StatusCode::NOT_FOUND, format!("Team '{}' not found", team)))
+}
+
+pub async fn create_team(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path(org): Path<String>,
+  Json(payload): Json<CreateTeamRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+
+  if !validation::is_valid_name(&payload.name) {
+    return Err((StatusCode::BAD_REQUEST, "Invalid team name".to_string()));
+  }
+
+  let row_res = sqlx
+    ::query("INSERT INTO teams (org_id, name, description) VALUES ($1, $2, $3) RETURNING id")
+    .bind(org_id)
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .fetch_one(&state.db).await;
+
+  match row_res {
+    Ok(r) => Ok(Json(json!({ "status": "created", "team_id": r.get::<Uuid, _>("id"), "name": payload.name }))),
+    Err(sqlx::Error::Database(db_err)) if db_err.code().as_deref() == Some("23505") => {
+      Err((StatusCode::CONFLICT, format!("Team '{}' already exists.", payload.name)))
+    }
+    Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+  }
+}
+
+pub async fn list_teams(State(state): State<Arc<AppState>>, auth: AuthUser, Path(org): Path<String>) -> Result<Json<Value>, (StatusCode, String)> {
+  let (org_id, _) = org::require_member(&state.db, &org, auth.id).await?;
+
+  let rows = sqlx
+    ::query(
+      r#"
+        SELECT t.id, t.name, t.description,
+               (SELECT COUNT(*) FROM team_members tm WHERE tm.team_id = t.id) as member_count,
+               (SELECT COUNT(*) FROM team_repo_grants g JOIN repositories r ON g.repo_id = r.id WHERE g.team_id = t.id AND r.deleted_at IS NULL) as repo_count
+        FROM teams t
+        WHERE t.org_id = $1
+        ORDER BY t.name ASC
+        "#
+    )
+    .bind(org_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let teams: Vec<Value> = rows
+    .iter()
+    .map(
+      |r|
+        json!({
+      "id": r.get::<Uuid, _>("id"),
+      "name": r.get::<String, _>("name"),
+      "description": r.get::<Option<String>, _>("description"),
+      "member_count": r.get::<i64, _>("member_count"),
+      "repo_count": r.get::<i64, _>("repo_count"),
+    })
+    )
+    .collect();
+
+  Ok(Json(json!(teams)))
+}
+
+pub async fn get_team(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team)): Path<(String, String)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let (org_id, _) = org::require_member(&state.db, &org, auth.id).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  let members = sqlx
+    ::query(
+      r#"
+        SELECT u.username, u.email, u.avatar_url
+        FROM team_members tm
+        JOIN users u ON tm.user_id = u.id
+        WHERE tm.team_id = $1
+        ORDER BY u.username ASC
+        "#
+    )
+    .bind(team_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let repos = sqlx
+    ::query(
+      r#"
+        SELECT r.name, g.role::text as role
+        FROM team_repo_grants g
+        JOIN repositories r ON g.repo_id = r.id
+        WHERE g.team_id = $1 AND r.deleted_at IS NULL
+        ORDER BY r.name ASC
+        "#
+    )
+    .bind(team_id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(
+    Json(
+      json!({
+    "id": team_id,
+    "name": team,
+    "members": members.iter().map(|r| json!({
+      "username": r.get::<String, _>("username"),
+      "email": r.get::<String, _>("email"),
+      "avatar": r.get::<Option<String>, _>("avatar_url"),
+    })).collect::<Vec<_>>(),
+    "repos": repos.iter().map(|r| json!({
+      "name": format!("{}/{}", org, r.get::<String, _>("name")),
+      "role": r.get::<String, _>("role"),
+    })).collect::<Vec<_>>(),
+  })
+    )
+  )
+}
+
+pub async fn delete_team(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team)): Path<(String, String)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  sqlx
+    ::query("DELETE FROM teams WHERE id = $1")
+    .bind(team_id)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "deleted", "team": team })))
+}
+
+pub async fn add_team_member(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team)): Path<(String, String)>,
+  Json(payload): Json<AddTeamMemberRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  // Teams only group existing organization members.
+  let user_id: Uuid = sqlx
+    ::query(
+      "SELECT u.id FROM users u JOIN organization_members om ON om.user_id = u.id AND om.org_id = $2 WHERE u.email = $1"
+    )
+    .bind(&payload.email)
+    .bind(org_id)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("id"))
+    .ok_or((StatusCode::NOT_FOUND, "User is not a member of this organization".to_string()))?;
+
+  sqlx
+    ::query("INSERT INTO team_members (team_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+    .bind(team_id)
+    .bind(user_id)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "member_added", "team": team, "user": payload.email })))
+}
+
+pub async fn remove_team_member(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team, username)): Path<(String, String, String)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  let result = sqlx
+    ::query("DELETE FROM team_members WHERE team_id = $1 AND user_id = (SELECT id FROM users WHERE username = $2)")
+    .bind(team_id)
+    .bind(&username)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if result.rows_affected() == 0 {
+    return Err((StatusCode::NOT_FOUND, "Member not found".to_string()));
+  }
+
+  Ok(Json(json!({ "status": "member_removed", "team": team, "user": username })))
+}
+
+pub async fn grant_team_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team)): Path<(String, String)>,
+  Json(payload): Json<GrantTeamRepoRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  let repo_id: Uuid = sqlx
+    ::query("SELECT id FROM repositories WHERE org_id = $1 AND name = $2 AND deleted_at IS NULL")
+    .bind(org_id)
+    .bind(&payload.repo)
+    .fetch_optional(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|r| r.get("id"))
+    .ok_or((StatusCode::NOT_FOUND, format!("Repository '{}/{}' not found", org, payload.repo)))?;
+
+  sqlx
+    ::query(
+      r#"
+        INSERT INTO team_repo_grants (team_id, repo_id, role)
+        VALUES ($1, $2, $3::repo_role_enum)
+        ON CONFLICT (team_id, repo_id) DO UPDATE SET role = EXCLUDED.role
+        "#
+    )
+    .bind(team_id)
+    .bind(repo_id)
+    .bind(&payload.role)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+  Ok(Json(json!({ "status": "granted", "team": team, "repo": format!("{}/{}", org, payload.repo), "role": payload.role })))
+}
+
+pub async fn revoke_team_repo(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Path((org, team, repo)): Path<(String, String, String)>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  let org_id = org::require_owner(&state.db, &org, &auth).await?;
+  let team_id = team_id(&state.db, org_id, &team).await?;
+
+  let result = sqlx
+    ::query("DELETE FROM team_repo_grants WHERE team_id = $1 AND repo_id = (SELECT id FROM repositories WHERE org_id = $2 AND name = $3 AND deleted_at IS NULL)")
+    .bind(team_id)
+    .bind(org_id)
+    .bind(&repo)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if result.rows_affected() == 0 {
+    return Err((StatusCode::NOT_FOUND, "Grant not found".to_string()));
+  }
+
+  Ok(Json(json!({ "status": "revoked", "team": team, "repo": format!("{}/{}", org, repo) })))
+}
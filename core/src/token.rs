@@ -0,0 +1,196 @@
+use axum::{ extract::{ Path, State }, http::StatusCode, Json };
+use rand::{ distributions::Alphanumeric, Rng };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use sha2::{ Digest, Sha256 };
+use sqlx::Row;
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::{ state::AppState, auth::AuthUser };
+
+pub const TOKEN_PREFIX: &str = "plectr_pat_";
+
+/// Scopes a personal access token can carry. `admin` implies all the others.
+pub const SCOPES: [&str; 4] = ["repo:read", "repo:write", "registry:push", "admin"];
+
+const DEFAULT_EXPIRY_DAYS: i64 = 90;
+const MAX_EXPIRY_DAYS: i64 = 365;
+
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+  pub name: String,
+  pub scopes: Vec<String>,
+  pub expires_in_days: Option<i64>,
+}
+
+pub fn hash_token(token: &str) -> String {
+  hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A fresh random secret behind `prefix`. Only its hash is ever stored.
+pub fn generate_token(prefix: &str) -> String {
+  let secret: String = rand::thread_rng().sample_iter(&Alphanumeric).take(40).map(char::from).collect();
+  format!("{}{}", prefix, secret)
+}
+
+/// Resolves a personal access token into its owner. Revoked and expired tokens are rejected.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, String> {
+  let row = sqlx
+    ::query(
+      r#"
+        SELECT t.id, t.scopes, u.id as user_id, u.username, u.email, u.robot_repo_id
+        FROM personal_access_tokens t
+        JOIN users u ON t.user_id = u.id
+        WHERE t.token_hash = $1
+          AND t.revoked_at IS NULL
+          AND (t.expires_at IS NULL OR t.expires_at > NOW())
+        "#
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&state.db).await
+    .map_err(|e| e.to_string())?
+    .ok_or("Unknown, revoked or expired token")?;
+
+  let token_id: Uuid = row.get("id");
+
+  // Throttled so that a busy CI job does not rewrite the row on every request.
+  let _ = sqlx
+    ::query(
+      "UPDATE personal_access_tokens SET last_used_at = NOW() WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')"
+    )
+    .bind(token_id)
+    .execute(&state.db).await;
+
+  Ok(AuthUser {
+    id: row.get("user_id"),
+    username: row.get("username"),
+    email: row.get("email"),
+    scopes: Some(row.get("scopes")),
+    job: None,
+    deploy_key: None,
+    robot_repo: row.get("robot_repo_id"),
+  })
+}
+
+pub async fn create_token(
+  State(state): State<Arc<AppState>>,
+  auth: AuthUser,
+  Json(payload): Json<CreateTokenRequest>
+) -> Result<Json<Value>, (StatusCode, String)> {
+  // A leaked token must not be able to mint new ones.
+  if auth.scopes.is_some() {
+    return Err((StatusCode::FORBIDDEN, "Tokens can only be created from an interactive session".to_string()));
+  }
+
+  if payload.name.trim().is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "Token name is required".to_string()));
+  }
+  if payload.scopes.is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "At least one scope is required".to_string()));
+  }
+  if let Some(unknown) = payload.scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+    return Err((StatusCode::BAD_REQUEST, format!("Unknown scope '{}'", unknown)));
+  }
+
+  let days = payload.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+  if days < 1 || days > MAX_EXPIRY_DAYS {
+    return Err((StatusCode::BAD_REQUEST, format!("Expiry must be between 1 and {} days", MAX_EXPIRY_DAYS)));
+  }
+  let expires_at = chrono::Utc::now() + chrono::Duration::days(days);
+
+  sqlx
+    ::query("INSERT INTO users (id, username, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()")
+    .bind(auth.id)
+    .bind(&auth.username)
+    .bind(&auth.email)
+    .execute(&state.db).await
+    .ok();
+
+  let token = generate_token(TOKEN_PREFIX);
+
+  let id: Uuid = sqlx
+    ::query(
+      "INSERT INTO personal_access_tokens (user_id, name, token_hash, token_prefix, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(auth.id)
+    .bind(payload.name.trim())
+    .bind(hash_token(&token))
+    .bind(&token[..TOKEN_PREFIX.len() + 4])
+    .bind(&payload.scopes)
+    .bind(expires_at)
+    .fetch_one(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .get("id");
+
+  tracing::info!("🔑 Personal access token created for {}: {}", auth.username, payload.name);
+
+  // The clear token is only ever returned here.
+  Ok(
+    Json(
+      json!({
+    "id": id,
+    "name": payload.name.trim(),
+    "token": token,
+    "scopes": payload.scopes,
+    "expires_at": expires_at.to_rfc3339(),
+  })
+    )
+  )
+}
+
+pub async fn list_tokens(State(state): State<Arc<AppState>>, auth: AuthUser) -> Result<Json<Value>, (StatusCode, String)> {
+  let rows = sqlx
+    ::query(
+      r#"
+        SELECT id, name, token_prefix, scopes, expires_at, last_used_at, revoked_at, created_at
+        FROM personal_access_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#
+    )
+    .bind(auth.id)
+    .fetch_all(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  let tokens: Vec<Value> = rows
+    .iter()
+    .map(|r| {
+      let expires_at = r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("expires_at");
+      let revoked_at = r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("revoked_at");
+      let status = if revoked_at.is_some() {
+        "revoked"
+      } else if expires_at.map(|e| e < chrono::Utc::now()).unwrap_or(false) {
+        "expired"
+      } else {
+        "active"
+      };
+      json!({
+        "id": r.get::<Uuid, _>("id"),
+        "name": r.get::<String, _>("name"),
+        "prefix": r.get::<String, _>("token_prefix"),
+        "scopes": r.get::<Vec<String>, _>("scopes"),
+        "status": status,
+        "expires_at": expires_at.map(|d| d.to_rfc3339()),
+        "last_used_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_used_at").map(|d| d.to_rfc3339()),
+        "created_at": r.get::<Option<chrono::DateTime<chrono::Utc>>, _>("created_at").map(|d| d.to_rfc3339()),
+      })
+    })
+    .collect();
+
+  Ok(Json(json!(tokens)))
+}
+
+pub async fn revoke_token(State(state): State<Arc<AppState>>, auth: AuthUser, Path(id): Path<Uuid>) -> Result<Json<Value>, (StatusCode, String)> {
+  let result = sqlx
+    ::query("UPDATE personal_access_tokens SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+    .bind(id)
+    .bind(auth.id)
+    .execute(&state.db).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+  if result.rows_affected() == 0 {
+    return Err((StatusCode::NOT_FOUND, "Token not found".to_string()));
+  }
+
+  Ok(Json(json!({ "status": "revoked", "id": id })))
+}
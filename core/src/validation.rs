@@ -3,8 +3,22 @@ use serde_json::json;
 use std::sync::Arc;
 use crate::state::AppState;
 
+/// Repository and organization names are URL path segments and Docker namespaces: `org/repo` is
+/// reserved for organization repositories, so a single name must not contain a slash.
+pub fn is_valid_name(name: &str) -> bool {
+  !name.is_empty() &&
+    name.len() <= 100 &&
+    !name.starts_with('.') &&
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
 pub async fn check_repo_name(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> impl IntoResponse {
-  let exists = sqlx::query("SELECT 1 FROM repositories WHERE name = $1").bind(&name).fetch_optional(&state.db).await.unwrap_or(None).is_some();
+  // A personal repository cannot take the name of an organization.
+  let query = format!(
+    "SELECT 1 FROM repositories r LEFT JOIN organizations o ON r.org_id = o.id WHERE r.deleted_at IS NULL AND {} = $1 UNION ALL SELECT 1 FROM organizations WHERE name = $1",
+    crate::auth::REPO_FULL_NAME_SQL
+  );
+  let exists = sqlx::query(&query).bind(&name).fetch_optional(&state.db).await.unwrap_or(None).is_some();
 
   if exists {
     (StatusCode::CONFLICT, Json(json!({ "available": false, "message": "Repository name already taken" })))
@@ -12,7 +12,7 @@ use bollard::exec::{ CreateExecOptions, StartExecResults };
 use futures_util::{ StreamExt, SinkExt };
 use serde::{ Deserialize, Serialize };
 use serde_json::json;
-use tokio_tungstenite::{ connect_async, tungstenite::protocol::Message };
+use tokio_tungstenite::{ connect_async, tungstenite::{ client::IntoClientRequest, protocol::Message } };
 use url::Url;
 use std::env;
 use std::path::Path;
@@ -52,12 +52,15 @@ async fn main() -> Result<()> {
 
   let docker = Docker::connect_with_local_defaults().context("Failed to connect to Docker Daemon")?;
 
-  let url_string = format!("{}?token={}&name={}", core_url, token, runner_name);
-  let url = Url::parse(&url_string)?;
+  let mut url = Url::parse(&core_url)?;
+  url.query_pairs_mut().append_pair("name", &runner_name);
 
   loop {
     tracing::info!("🔌 Connecting to Core at {}...", core_url);
-    match connect_async(url.clone()).await {
+    // The token goes in a header so it never shows up in proxy or access logs.
+    let mut request = url.as_str().into_client_request()?;
+    request.headers_mut().insert("Authorization", format!("Bearer {}", token).parse()?);
+    match connect_async(request).await {
       Ok((ws_stream, _)) => {
         tracing::info!("✅ Connected via WebSocket securely.");
         let (mut write, mut read) = ws_stream.split();
@@ -403,7 +406,9 @@ async fn fetch_source_code(ctx: &JobContext, target_dir: &str) -> Result<()> {
     .use_rustls_tls()
     .build()?;
 
-  let tree_url = format!("{}/repos/{}/commits/{}/tree", ctx.api_url, ctx.repo_name, ctx.commit_id);
+  // Organization repositories are named `org/repo`: keep the name a single path segment.
+  let repo_path = ctx.repo_name.replace('/', "%2F");
+  let tree_url = format!("{}/repos/{}/commits/{}/tree", ctx.api_url, repo_path, ctx.commit_id);
   let resp = client
     .get(&tree_url)
     .header("Authorization", format!("Bearer {}", ctx.auth_token))
@@ -424,7 +429,7 @@ async fn fetch_source_code(ctx: &JobContext, target_dir: &str) -> Result<()> {
     let file_url = format!(
       "{}/repos/{}/commits/{}/files/{}",
       ctx.api_url,
-      ctx.repo_name,
+      repo_path,
       ctx.commit_id,
       path_str
     );
@@ -3,6 +3,7 @@ import React, { useRef, useState, useEffect } from "react";
 import { DiffEditor } from "@monaco-editor/react";
 import { Loader2, Check, Monitor, Smartphone } from "lucide-react";
 import axios from "axios";
+import { useSession } from "next-auth/react";
 
 interface Props {
   file: { path: string; localHash: string; remoteHash: string };
@@ -13,6 +14,7 @@ interface Props {
 const API_URL = process.env.NEXT_PUBLIC_API_URL || 'https://plectr.com';
 
 export const ConflictEditor = ({ file, onResolve, repoName }: Props) => {
+  const { data: session } = useSession();
   const [loading, setLoading] = useState(true);
   const [saving, setSaving] = useState(false);
   const [data, setData] = useState<{ local: string; remote: string } | null>(null);
@@ -80,11 +82,18 @@ export const ConflictEditor = ({ file, onResolve, repoName }: Props) => {
       const blob = new Blob([content], { type: "text/plain" });
       formData.append("file", blob, file.path);
 
-      const res = await axios.post(`${API_URL}/upload`, formData);
-      const newHash = res.data.blobs[0].hash;
-      onResolve(file.path, newHash);
-    } catch {
-      alert("Fusion failed.");
+      const res = await axios.post(`${API_URL}/repos/${repoName}/upload`, formData, {
+        headers: { Authorization: `Bearer ${session?.accessToken}` },
+      });
+      // 207: the server reports each rejected file in `errors` next to the stored ones.
+      if (!res.data.blobs?.length) {
+        throw new Error(res.data.errors?.[0]?.error ?? "Upload failed");
+      }
+      onResolve(file.path, res.data.blobs[0].hash);
+    } catch (err: any) {
+      // 422: nothing was stored, the reason is in `errors`.
+      const reason = err.response?.data?.errors?.[0]?.error ?? err.message;
+      alert(`Fusion failed: ${reason}`);
     } finally {
       setSaving(false);
     }
@@ -13,6 +13,8 @@ import {
   Trash2,
   Check,
   ShieldAlert,
+  ShieldCheck,
+  FileText,
   Download,
   AlertTriangle,
   Cpu,
@@ -24,13 +26,26 @@ import { formatBytes } from '@/utils/format';
 import { useSession } from 'next-auth/react';
 import { useToast } from '@/context/ToastContext';
 
+interface ImagePlatform {
+  digest: string;
+  os: string;
+  architecture: string;
+  variant: string | null;
+  size: number;
+  layers_count: number;
+}
+
 interface DockerImage {
   image_name: string;
   tag: string;
   digest: string;
+  media_type: string;
   updated_at: string;
   size: number;
   layers_count: number;
+  platforms: ImagePlatform[];
+  signed: boolean;
+  has_sbom: boolean;
   content: any;
 }
 
@@ -227,6 +242,16 @@ const ImageRow = ({ img, repoName, onInspect, onDelete, onCopy, copiedId }: any)
               <span className="px-2 py-0.5 rounded text-[10px] font-bold bg-zinc-800 text-zinc-500 border border-zinc-700 uppercase tracking-wide flex items-center gap-1">
                 <ShieldAlert size={10} /> Not Scanned
               </span>
+              {img.signed && (
+                <span className="px-2 py-0.5 rounded text-[10px] font-bold bg-green-500/10 text-green-400 border border-green-500/20 uppercase tracking-wide flex items-center gap-1">
+                  <ShieldCheck size={10} /> Signed
+                </span>
+              )}
+              {img.has_sbom && (
+                <span className="px-2 py-0.5 rounded text-[10px] font-bold bg-blue-500/10 text-blue-400 border border-blue-500/20 uppercase tracking-wide flex items-center gap-1">
+                  <FileText size={10} /> SBOM
+                </span>
+              )}
             </div>
           </div>
 
@@ -242,6 +267,21 @@ const ImageRow = ({ img, repoName, onInspect, onDelete, onCopy, copiedId }: any)
               <Clock size={12} /> {new Date(img.updated_at).toLocaleDateString()}
             </span>
           </div>
+
+          {img.platforms?.length > 0 && (
+            <div className="flex flex-wrap items-center gap-1.5">
+              {img.platforms.map((p: ImagePlatform) => (
+                <span
+                  key={p.digest}
+                  className="px-2 py-0.5 rounded text-[10px] font-mono bg-zinc-900 text-zinc-400 border border-zinc-800 flex items-center gap-1"
+                  title={`${p.digest} · ${formatBytes(p.size)}`}
+                >
+                  <Cpu size={10} /> {p.os}/{p.architecture}
+                  {p.variant ? `/${p.variant}` : ''}
+                </span>
+              ))}
+            </div>
+          )}
         </div>
       </div>
 
@@ -50,7 +50,7 @@ export function useRepository(repoName: string, targetCommitId?: string | null)
         if (e.response?.status === 404) {
            setFiles([]);
            setCommits([]);
-        } else if (e.response?.status === 403) {
+        } else if (e.response?.status === 401 || e.response?.status === 403) {
            console.error("🔒 Access Denied. Private repository.");
         } else {
            console.error("Forge Sync Error:", e);